@@ -0,0 +1,910 @@
+//! Text format for writing DER by hand, in the spirit of der-ascii's ascii2der.
+//!
+//! The text is a series of tokens, each of which emits bytes:
+//!
+//! ```text
+//! SEQUENCE {
+//!   INTEGER { 5 }
+//!   OBJECT_IDENTIFIER { 1.2.840.113549 }
+//!   [0] { BOOLEAN { TRUE } }
+//!   OCTET_STRING length:3 { `0102` }   # claims 3 octets, holds 2
+//! }
+//! ```
+//!
+//! - A universal type name (`SEQUENCE`, `OBJECT_IDENTIFIER`, `UTF8String`, ...)
+//!   emits its identifier octet, constructed for SEQUENCE and SET. `[0]`,
+//!   `[APPLICATION 2]` or `[UNIVERSAL 16]` emit other tags, constructed unless
+//!   followed by `PRIMITIVE`.
+//! - `{ ... }` emits a length and then what is inside. In front of the brace,
+//!   `indefinite` uses the indefinite form (the block ends with 00 00),
+//!   `long-form:N` writes the length in N octets and `length:N` claims N octets
+//!   whatever the block holds.
+//! - `5` or `-129` emits INTEGER content of any size, `1.2.840` OBJECT IDENTIFIER
+//!   content and `TRUE`/`FALSE` a BOOLEAN octet.
+//! - `` `0102ab` `` emits hex, `"text"` UTF-8 (escapes `\"`, `\\`, `\n`, `\xNN`),
+//!   `u"text"` UTF-16 and `U"text"` UTF-32, and `` b`101` `` BIT STRING content
+//!   (the unused-bits octet, then the bits).
+//! - `#` starts a comment that runs to the end of the line.
+//!
+//! Errors give the line and column where they were found.
+//!
+//! [`render_ascii`] goes the other way, writing any DER (or BER) in this format
+//! such that compiling the text gives back exactly the same bytes.
+
+use crate::decoder::{decode_value, get_universal_tag_name, oid_value_name, to_hex};
+use crate::encoder::{encode_length, encode_value, identifier_octets};
+use crate::query::{class_for_name, universal_tag_for_name};
+use crate::tlv::{DecodeError, TagClass, Tlv};
+use crate::LengthForm;
+use std::error::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+    /// Contents of `[...]`
+    Tag(String),
+    Bytes(Vec<u8>),
+}
+
+/// A token and where it starts, 1-based
+#[derive(Debug, Clone)]
+struct Located {
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+fn error_at(line: usize, column: usize, message: String) -> Box<dyn Error> {
+    format!("Line {}, column {}: {}", line, column, message).into()
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(text: &'a str) -> Lexer<'a> {
+        Lexer {
+            chars: text.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: String) -> Box<dyn Error> {
+        error_at(self.line, self.column, message)
+    }
+
+    fn tokens(mut self) -> Result<Vec<Located>, Box<dyn Error>> {
+        let mut tokens = Vec::new();
+        while let Some(&c) = self.chars.peek() {
+            let (line, column) = (self.line, self.column);
+            let token = match c {
+                c if c.is_whitespace() => {
+                    self.advance();
+                    continue;
+                }
+                '#' => {
+                    while self.advance().is_some_and(|c| c != '\n') {}
+                    continue;
+                }
+                '{' => {
+                    self.advance();
+                    Token::Open
+                }
+                '}' => {
+                    self.advance();
+                    Token::Close
+                }
+                '[' => {
+                    self.advance();
+                    Token::Tag(self.until(']', line, column)?)
+                }
+                '`' => {
+                    self.advance();
+                    Token::Bytes(self.hex(line, column)?)
+                }
+                '"' => {
+                    self.advance();
+                    Token::Bytes(self.string(line, column)?)
+                }
+                _ => {
+                    let mut word = String::new();
+                    while let Some(&c) = self.chars.peek() {
+                        if c.is_whitespace() || matches!(c, '{' | '}' | '[' | '#' | '"' | '`') {
+                            break;
+                        }
+                        word.push(c);
+                        self.advance();
+                    }
+                    match (word.as_str(), self.chars.peek()) {
+                        ("u", Some('"')) | ("U", Some('"')) => {
+                            self.advance();
+                            let text =
+                                String::from_utf8(self.string(line, column)?).map_err(|_| {
+                                    error_at(
+                                        line,
+                                        column,
+                                        "\\x escapes must form UTF-8 here".to_string(),
+                                    )
+                                })?;
+                            Token::Bytes(if word == "u" {
+                                text.encode_utf16()
+                                    .flat_map(|unit| unit.to_be_bytes())
+                                    .collect()
+                            } else {
+                                text.chars()
+                                    .flat_map(|c| (c as u32).to_be_bytes())
+                                    .collect()
+                            })
+                        }
+                        ("b", Some('`')) => {
+                            self.advance();
+                            Token::Bytes(self.bits(line, column)?)
+                        }
+                        (_, Some('"' | '`')) => {
+                            return Err(self.error(format!("Unexpected quote after '{}'", word)));
+                        }
+                        _ => Token::Word(word),
+                    }
+                }
+            };
+            tokens.push(Located {
+                token,
+                line,
+                column,
+            });
+        }
+        Ok(tokens)
+    }
+
+    /// Everything up to `end`, which is consumed
+    fn until(&mut self, end: char, line: usize, column: usize) -> Result<String, Box<dyn Error>> {
+        let mut text = String::new();
+        loop {
+            match self.advance() {
+                Some(c) if c == end => return Ok(text),
+                Some(c) => text.push(c),
+                None => return Err(error_at(line, column, format!("Missing closing '{}'", end))),
+            }
+        }
+    }
+
+    fn hex(&mut self, line: usize, column: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let digits: String = self
+            .until('`', line, column)?
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if !digits.len().is_multiple_of(2) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(error_at(
+                line,
+                column,
+                format!("Invalid hex literal `{}`", digits),
+            ));
+        }
+        Ok((0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+            .collect())
+    }
+
+    fn bits(&mut self, line: usize, column: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let bits: String = self
+            .until('`', line, column)?
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if !bits.chars().all(|c| c == '0' || c == '1') {
+            return Err(error_at(
+                line,
+                column,
+                format!("Invalid bit string literal b`{}`", bits),
+            ));
+        }
+        let mut content = vec![((8 - bits.len() % 8) % 8) as u8];
+        for chunk in bits.as_bytes().chunks(8) {
+            let byte = chunk.iter().enumerate().fold(0u8, |byte, (i, &bit)| {
+                byte | (((bit == b'1') as u8) << (7 - i))
+            });
+            content.push(byte);
+        }
+        Ok(content)
+    }
+
+    /// UTF-8 of a quoted string, with `\xNN` escapes giving raw octets
+    fn string(&mut self, line: usize, column: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut text = Vec::new();
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.advance() {
+                    Some('n') => text.push(b'\n'),
+                    Some('"') => text.push(b'"'),
+                    Some('\\') => text.push(b'\\'),
+                    Some('x') => {
+                        let digits: String = (0..2).filter_map(|_| self.advance()).collect();
+                        let byte = u8::from_str_radix(&digits, 16)
+                            .map_err(|_| self.error(format!("Invalid escape \\x{}", digits)))?;
+                        text.push(byte);
+                    }
+                    Some(c) => return Err(self.error(format!("Unknown escape \\{}", c))),
+                    None => return Err(error_at(line, column, "Unterminated string".to_string())),
+                },
+                Some(c) => text.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                None => return Err(error_at(line, column, "Unterminated string".to_string())),
+            }
+        }
+    }
+}
+
+/// How the length of the next `{ ... }` block is written
+#[derive(Default)]
+struct LengthModifiers {
+    indefinite: bool,
+    long_form: Option<usize>,
+    length: Option<usize>,
+}
+
+impl LengthModifiers {
+    fn is_empty(&self) -> bool {
+        !self.indefinite && self.long_form.is_none() && self.length.is_none()
+    }
+
+    fn header(&self, content_length: usize) -> Result<Vec<u8>, String> {
+        if self.indefinite {
+            return Ok(vec![0x80]);
+        }
+        let length = self.length.unwrap_or(content_length);
+        let Some(octets) = self.long_form else {
+            return encode_length(length).map_err(|e| e.to_string());
+        };
+        let minimal: Vec<u8> = length
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|&b| b == 0)
+            .collect();
+        if octets < minimal.len().max(1) || octets > 127 {
+            return Err(format!(
+                "Length {} does not fit in {} long-form octets",
+                length, octets
+            ));
+        }
+        let mut header = vec![0x80 | octets as u8];
+        header.resize(1 + octets - minimal.len(), 0x00);
+        header.extend_from_slice(&minimal);
+        Ok(header)
+    }
+}
+
+/// Identifier octets for the contents of `[...]`
+fn tag_identifier(tag: &str) -> Result<Vec<u8>, String> {
+    let mut words: Vec<&str> = tag.split_whitespace().collect();
+    let constructed = match words
+        .last()
+        .map(|word| word.to_ascii_uppercase())
+        .as_deref()
+    {
+        Some("PRIMITIVE") => {
+            words.pop();
+            false
+        }
+        Some("CONSTRUCTED") => {
+            words.pop();
+            true
+        }
+        _ => true,
+    };
+    let (class_bits, number) = match words[..] {
+        [number] => (0x80, number),
+        [class, number] => {
+            let class_bits = match class_for_name(class) {
+                Some("UNIVERSAL") => 0x00,
+                Some("APPLICATION") => 0x40,
+                Some("CONTEXT") => 0x80,
+                Some("PRIVATE") => 0xC0,
+                _ => return Err(format!("Unknown tag class '{}'", class)),
+            };
+            (class_bits, number)
+        }
+        _ => return Err(format!("Invalid tag [{}]", tag)),
+    };
+    let number = number
+        .parse::<u32>()
+        .map_err(|_| format!("Invalid tag number '{}'", number))?;
+    Ok(identifier_octets(
+        class_bits | if constructed { 0x20 } else { 0x00 },
+        number,
+    ))
+}
+
+fn is_integer(word: &str) -> bool {
+    let digits = word.strip_prefix('-').unwrap_or(word);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_oid(word: &str) -> bool {
+    word.contains('.')
+        && word
+            .split('.')
+            .all(|arc| !arc.is_empty() && arc.bytes().all(|b| b.is_ascii_digit()))
+}
+
+struct Parser {
+    tokens: Vec<Located>,
+    position: usize,
+}
+
+impl Parser {
+    /// Emit tokens until the end of the text, or the `}` closing the block opened at `open`
+    fn block(&mut self, open: Option<&Located>) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut bytes = Vec::new();
+        let mut modifiers = LengthModifiers::default();
+        let mut modifier_at: Option<Located> = None;
+
+        while let Some(located) = self.tokens.get(self.position).cloned() {
+            self.position += 1;
+            let at = |message: String| error_at(located.line, located.column, message);
+            let is_modifier = matches!(located.token, Token::Word(ref word) if is_modifier(word));
+            if !modifiers.is_empty() && located.token != Token::Open && !is_modifier {
+                return Err(at("Expected '{' after a length modifier".to_string()));
+            }
+
+            match located.token {
+                Token::Close => {
+                    if open.is_none() {
+                        return Err(at("Unexpected '}'".to_string()));
+                    }
+                    return Ok(bytes);
+                }
+                Token::Open => {
+                    let content = self.block(Some(&located))?;
+                    let modifier_at = modifier_at.take().unwrap_or(located);
+                    let header = modifiers.header(content.len()).map_err(|message| {
+                        error_at(modifier_at.line, modifier_at.column, message)
+                    })?;
+                    bytes.extend_from_slice(&header);
+                    bytes.extend_from_slice(&content);
+                    if modifiers.indefinite {
+                        bytes.extend_from_slice(&[0x00, 0x00]);
+                    }
+                    modifiers = LengthModifiers::default();
+                }
+                Token::Bytes(ref literal) => bytes.extend_from_slice(literal),
+                Token::Tag(ref tag) => bytes.extend_from_slice(&tag_identifier(tag).map_err(at)?),
+                Token::Word(ref word) => {
+                    if add_modifier(word, &mut modifiers).map_err(at)? {
+                        modifier_at.get_or_insert(located.clone());
+                    } else {
+                        bytes.extend_from_slice(&word_bytes(word).map_err(at)?);
+                    }
+                }
+            }
+        }
+
+        match open {
+            Some(open) => Err(error_at(
+                open.line,
+                open.column,
+                "Missing '}' for this '{'".to_string(),
+            )),
+            None if !modifiers.is_empty() => {
+                let at = modifier_at.unwrap();
+                Err(error_at(
+                    at.line,
+                    at.column,
+                    "Expected '{' after a length modifier".to_string(),
+                ))
+            }
+            None => Ok(bytes),
+        }
+    }
+}
+
+fn is_modifier(word: &str) -> bool {
+    word == "indefinite" || word.starts_with("long-form:") || word.starts_with("length:")
+}
+
+/// Record a length modifier, returning false for other words
+fn add_modifier(word: &str, modifiers: &mut LengthModifiers) -> Result<bool, String> {
+    let number = |value: &str| {
+        value
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid number in '{}'", word))
+    };
+    if word == "indefinite" {
+        modifiers.indefinite = true;
+    } else if let Some(octets) = word.strip_prefix("long-form:") {
+        modifiers.long_form = Some(number(octets)?);
+    } else if let Some(length) = word.strip_prefix("length:") {
+        modifiers.length = Some(number(length)?);
+    } else {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Bytes for a bare word: a type name, a number, an OID or a boolean
+fn word_bytes(word: &str) -> Result<Vec<u8>, String> {
+    match word {
+        "TRUE" => return Ok(vec![0xFF]),
+        "FALSE" => return Ok(vec![0x00]),
+        _ => {}
+    }
+    if is_integer(word) {
+        return encode_value(2, word).map_err(|e| e.to_string());
+    }
+    if is_oid(word) {
+        return encode_value(6, word).map_err(|e| e.to_string());
+    }
+    match universal_tag_for_name(word) {
+        Some(tag_number) => Ok(identifier_octets(
+            if matches!(tag_number, 16 | 17) {
+                0x20
+            } else {
+                0x00
+            },
+            tag_number,
+        )),
+        None => Err(format!("Unknown word '{}'", word)),
+    }
+}
+
+/// Compile the text format to DER
+pub fn compile_ascii(text: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let tokens = Lexer::new(text).tokens()?;
+    Parser {
+        tokens,
+        position: 0,
+    }
+    .block(None)
+}
+
+/// Octets per hex literal when rendering long content
+const HEX_LINE_OCTETS: usize = 32;
+
+/// Render DER as text that compiles back to the same bytes. Lengths that are not
+/// minimal become `long-form:N`, indefinite lengths `indefinite`, identifiers
+/// that cannot be written as a tag stay hex, and anything that does not parse
+/// is kept as hex literals.
+pub fn render_ascii(der: &[u8]) -> String {
+    let mut out = String::new();
+    render_elements(der, 0, &mut out);
+    out
+}
+
+fn indent(depth: usize) -> String {
+    "  ".repeat(depth)
+}
+
+fn render_hex_lines(bytes: &[u8], depth: usize, out: &mut String) {
+    for chunk in bytes.chunks(HEX_LINE_OCTETS) {
+        out.push_str(&format!("{}`{}`\n", indent(depth), to_hex(chunk)));
+    }
+}
+
+/// Render consecutive elements, keeping whatever does not parse as hex
+fn render_elements(data: &[u8], depth: usize, out: &mut String) {
+    let mut pos = 0;
+    while pos < data.len() {
+        let tlv = match Tlv::read_nested(&data[pos..], pos, depth) {
+            Ok(tlv) => tlv,
+            Err(e) => {
+                let reason = if matches!(e, DecodeError::TooDeep(_)) {
+                    "nested too deeply"
+                } else {
+                    "not DER"
+                };
+                out.push_str(&format!("{}# {}\n", indent(depth), reason));
+                render_hex_lines(&data[pos..], depth, out);
+                return;
+            }
+        };
+        render_element(&tlv, depth, out);
+        pos += tlv.raw().len();
+    }
+}
+
+/// Every element in `data` parses, within the nesting limit, and they fill it exactly
+fn parses_completely(data: &[u8], depth: usize) -> bool {
+    let mut pos = 0;
+    while pos < data.len() {
+        match Tlv::read_nested(&data[pos..], pos, depth) {
+            Ok(tlv) => pos += tlv.raw().len(),
+            Err(_) => return false,
+        }
+    }
+    !data.is_empty()
+}
+
+/// The identifier as a type name or `[...]` tag, if that compiles to the same octets
+fn render_identifier(tlv: &Tlv) -> String {
+    let tag = tlv.tag();
+    let octets = tlv.identifier_octets();
+    if tag.class == TagClass::Universal && tag.constructed == matches!(tag.number, 16 | 17) {
+        let name = get_universal_tag_name(tag.number).replace([' ', '-'], "_");
+        if octets.len() == 1 && universal_tag_for_name(&name) == Some(tag.number) {
+            return name;
+        }
+    }
+    let class = match tag.class {
+        TagClass::Context => String::new(),
+        class => format!("{} ", class.name()),
+    };
+    let primitive = if tag.constructed { "" } else { " PRIMITIVE" };
+    let text = format!("[{}{}{}]", class, tag.number, primitive);
+    match tag_identifier(&text[1..text.len() - 1]) {
+        Ok(compiled) if compiled == octets => text,
+        _ => format!("`{}`", to_hex(octets)),
+    }
+}
+
+/// Length modifiers needed to reproduce the original length octets
+fn render_length_modifiers(tlv: &Tlv) -> String {
+    match tlv.length_form() {
+        LengthForm::Indefinite => "indefinite ".to_string(),
+        _ if encode_length(tlv.content().len()).ok().as_deref() == Some(tlv.length_octets()) => {
+            String::new()
+        }
+        _ => format!("long-form:{} ", tlv.length_octets().len() - 1),
+    }
+}
+
+/// Primitive content as a single literal, if one compiles back to the same octets
+fn render_literal(tlv: &Tlv) -> Option<String> {
+    let content = tlv.content();
+    let tag = tlv.tag();
+    let universal = tag.class == TagClass::Universal;
+    let literal = match tag.number {
+        _ if content.is_empty() => return Some(String::new()),
+        1 if universal && content == [0xFF] => "TRUE".to_string(),
+        1 if universal && content == [0x00] => "FALSE".to_string(),
+        2 | 10 if universal => {
+            let decimal = integer_to_decimal(content);
+            (encode_value(2, &decimal).ok()? == content).then_some(decimal)?
+        }
+        6 if universal => {
+            let dotted = decode_value(6, content).split(' ').next()?.to_string();
+            (encode_value(6, &dotted).ok()? == content).then_some(dotted)?
+        }
+        30 if universal => {
+            let units: Vec<u16> = content
+                .chunks(2)
+                .map(|pair| Some(u16::from_be_bytes(pair.try_into().ok()?)))
+                .collect::<Option<_>>()?;
+            let text = String::from_utf16(&units).ok()?;
+            format!("u{}", quote(text.as_bytes(), false)?)
+        }
+        28 if universal => {
+            let chars: Vec<char> = content
+                .chunks(4)
+                .map(|quad| char::from_u32(u32::from_be_bytes(quad.try_into().ok()?)))
+                .collect::<Option<_>>()?;
+            format!(
+                "U{}",
+                quote(chars.iter().collect::<String>().as_bytes(), false)?
+            )
+        }
+        7 | 12 | 18..=27 if universal => quote(content, true)?,
+        _ if content.iter().all(|&b| (0x20..0x7F).contains(&b)) => quote(content, true)?,
+        _ => return None,
+    };
+    Some(literal)
+}
+
+/// A quoted string literal; raw octets need `\xNN`, which only plain strings allow
+fn quote(bytes: &[u8], allow_escaped_octets: bool) -> Option<String> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => {
+                if !allow_escaped_octets {
+                    return None;
+                }
+                for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                    quoted.push_str(&format!("\\x{:02X}", byte));
+                }
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    Some(quoted)
+}
+
+/// Decimal value of two's complement INTEGER content of any size
+fn integer_to_decimal(content: &[u8]) -> String {
+    let negative = content.first().is_some_and(|&b| b & 0x80 != 0);
+    let mut magnitude = content.to_vec();
+    if negative {
+        let mut carry = true;
+        for byte in magnitude.iter_mut().rev() {
+            let (sum, overflow) = (!*byte).overflowing_add(carry as u8);
+            *byte = sum;
+            carry = overflow;
+        }
+    }
+
+    let mut digits = Vec::new();
+    while magnitude.iter().any(|&b| b != 0) {
+        let mut remainder = 0u32;
+        for byte in magnitude.iter_mut() {
+            let value = (remainder << 8) | *byte as u32;
+            *byte = (value / 10) as u8;
+            remainder = value % 10;
+        }
+        digits.push(b'0' + remainder as u8);
+    }
+    if digits.is_empty() {
+        digits.push(b'0');
+    }
+    if negative {
+        digits.push(b'-');
+    }
+    digits.reverse();
+    String::from_utf8(digits).unwrap()
+}
+
+fn render_element(tlv: &Tlv, depth: usize, out: &mut String) {
+    let head = format!(
+        "{}{} {}",
+        indent(depth),
+        render_identifier(tlv),
+        render_length_modifiers(tlv)
+    );
+    let content = tlv.content();
+    let tag = tlv.tag();
+
+    if tag.constructed {
+        if content.is_empty() {
+            out.push_str(&format!("{}{{}}\n", head));
+        } else {
+            out.push_str(&format!("{}{{\n", head));
+            render_elements(content, depth + 1, out);
+            out.push_str(&format!("{}}}\n", indent(depth)));
+        }
+        return;
+    }
+
+    // OCTET STRING and BIT STRING content is often DER itself
+    let universal = tag.class == TagClass::Universal;
+    let encapsulated = match tag.number {
+        4 if universal && parses_completely(content, depth + 1) => Some(0),
+        3 if universal
+            && content.len() > 1
+            && content[0] == 0
+            && parses_completely(&content[1..], depth + 1) =>
+        {
+            Some(1)
+        }
+        _ => None,
+    };
+    if let Some(prefix) = encapsulated {
+        out.push_str(&format!("{}{{\n", head));
+        render_hex_lines(&content[..prefix], depth + 1, out);
+        render_elements(&content[prefix..], depth + 1, out);
+        out.push_str(&format!("{}}}\n", indent(depth)));
+        return;
+    }
+
+    match render_literal(tlv) {
+        Some(literal) if literal.is_empty() => out.push_str(&format!("{}{{}}\n", head)),
+        Some(literal) => {
+            let comment = match tag.number {
+                6 if universal => oid_value_name(&literal)
+                    .map(|name| format!("  # {}", name))
+                    .unwrap_or_default(),
+                _ => String::new(),
+            };
+            out.push_str(&format!("{}{{ {} }}{}\n", head, literal, comment));
+        }
+        None if content.len() <= HEX_LINE_OCTETS => {
+            out.push_str(&format!("{}{{ `{}` }}\n", head, to_hex(content)))
+        }
+        None => {
+            out.push_str(&format!("{}{{\n", head));
+            render_hex_lines(content, depth + 1, out);
+            out.push_str(&format!("{}}}\n", indent(depth)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::CERT_PEM;
+
+    fn compile(text: &str) -> Vec<u8> {
+        compile_ascii(text).unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        compile_ascii(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_compile_sequence() {
+        let der = compile(
+            "SEQUENCE {\n  INTEGER { 5 }\n  OBJECT_IDENTIFIER { 1.2.840.113549 }\n  [0] { BOOLEAN { TRUE } }\n}",
+        );
+        assert_eq!(
+            der,
+            [
+                0x30, 0x10, 0x02, 0x01, 0x05, 0x06, 0x06, 0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0xA0,
+                0x03, 0x01, 0x01, 0xFF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(compile("INTEGER { -129 }"), [0x02, 0x02, 0xFF, 0x7F]);
+        assert_eq!(
+            compile("INTEGER { 18446744073709551616 }")[..3],
+            [0x02, 0x09, 0x01]
+        );
+        assert_eq!(
+            compile("OCTET_STRING { `01 02` } NULL {}"),
+            [0x04, 0x02, 0x01, 0x02, 0x05, 0x00]
+        );
+        assert_eq!(
+            compile("UTF8String { \"a\\\"\\x41\" }"),
+            [0x0C, 0x03, 0x61, 0x22, 0x41]
+        );
+        assert_eq!(
+            compile("OCTET_STRING { \"\\xFF\u{e9}\" }"),
+            [0x04, 0x03, 0xFF, 0xC3, 0xA9]
+        );
+        assert_eq!(compile("BMPString { u\"A\" }"), [0x1E, 0x02, 0x00, 0x41]);
+        assert_eq!(
+            compile("UniversalString { U\"A\" }"),
+            [0x1C, 0x04, 0x00, 0x00, 0x00, 0x41]
+        );
+        assert_eq!(compile("BIT_STRING { b`101` }"), [0x03, 0x02, 0x05, 0xA0]);
+        assert_eq!(
+            compile("OBJECT_IDENTIFIER { 2.999.1 }"),
+            [0x06, 0x03, 0x88, 0x37, 0x01]
+        );
+        assert_eq!(
+            compile("# comment\nBOOLEAN { FALSE } # trailing"),
+            [0x01, 0x01, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_tags() {
+        assert_eq!(compile("[1 PRIMITIVE] { `AA` }"), [0x81, 0x01, 0xAA]);
+        assert_eq!(compile("[APPLICATION 2] {}"), [0x62, 0x00]);
+        assert_eq!(
+            compile("[PRIVATE 200 PRIMITIVE] {}"),
+            [0xDF, 0x81, 0x48, 0x00]
+        );
+        assert_eq!(compile("[UNIVERSAL 16] {}"), [0x30, 0x00]);
+        assert_eq!(compile("SET {}"), [0x31, 0x00]);
+    }
+
+    #[test]
+    fn test_length_overrides() {
+        assert_eq!(
+            compile("OCTET_STRING length:3 { `0102` }"),
+            [0x04, 0x03, 0x01, 0x02]
+        );
+        assert_eq!(compile("NULL long-form:2 {}"), [0x05, 0x82, 0x00, 0x00]);
+        assert_eq!(
+            compile("NULL long-form:1 length:200 {}"),
+            [0x05, 0x81, 0xC8]
+        );
+        assert_eq!(
+            compile("SEQUENCE indefinite { INTEGER { 1 } }"),
+            [0x30, 0x80, 0x02, 0x01, 0x01, 0x00, 0x00]
+        );
+    }
+
+    fn assert_round_trip(der: &[u8]) {
+        let text = render_ascii(der);
+        assert_eq!(compile(&text), der, "{}", text);
+    }
+
+    #[test]
+    fn test_render_certificate() {
+        let der = pem::parse(CERT_PEM).unwrap().into_contents();
+        let text = render_ascii(&der);
+        assert!(
+            text.starts_with("SEQUENCE {\n  SEQUENCE {\n    [0] {\n      INTEGER { 2 }\n"),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains("OBJECT_IDENTIFIER { 2.5.4.3 }  # commonName\n"),
+            "{}",
+            text
+        );
+        assert_round_trip(&der);
+    }
+
+    #[test]
+    fn test_render_keeps_non_canonical_encodings() {
+        let text = "SEQUENCE indefinite {\n  INTEGER long-form:2 { 5 }\n  [PRIVATE 200 PRIMITIVE] { \"x\" }\n  INTEGER { `0005` }\n}\n";
+        assert_eq!(render_ascii(&compile(text)), text);
+
+        // Non-minimal high tag number, a BIT STRING holding DER, and trailing garbage
+        assert_round_trip(&[0x9F, 0x80, 0x01, 0x00]);
+        assert_round_trip(&[0x03, 0x04, 0x00, 0x02, 0x01, 0x01]);
+        assert_round_trip(&[0x05, 0x00, 0x30, 0x05, 0x02]);
+        assert_round_trip(&[0x1E, 0x04, 0x00, 0x41, 0xD8, 0x00]);
+        assert_round_trip(&[0x0C, 0x04, b'#', b'"', b'\\', 0x09]);
+    }
+
+    #[test]
+    fn test_render_mutated_documents() {
+        let root = crate::decoder::decode_pem_tree(CERT_PEM).unwrap();
+        let options = crate::mutate::MutationOptions {
+            count: 50,
+            nesting_depth: 20,
+            ..Default::default()
+        };
+        for mutant in crate::mutate::mutate(&root, &options).unwrap() {
+            assert_round_trip(&mutant.der);
+        }
+    }
+
+    #[test]
+    fn test_render_deep_nesting_as_hex() {
+        // 100,000 nested SEQUENCEs with four-octet lengths, then the same in OCTET STRINGs
+        for tag in [0x30, 0x04] {
+            let levels = 100_000u32;
+            let mut der = Vec::new();
+            for level in (0..levels).rev() {
+                der.extend_from_slice(&[tag, 0x84]);
+                der.extend_from_slice(&(2 + 6 * level).to_be_bytes());
+            }
+            der.extend_from_slice(&[0x05, 0x00]);
+            let text = render_ascii(&der);
+            assert_eq!(text.contains("# nested too deeply\n"), tag == 0x30);
+            assert_eq!(compile(&text), der);
+        }
+    }
+
+    #[test]
+    fn test_errors_have_line_and_column() {
+        assert_eq!(
+            error("SEQUENCE {\n  INTEGR { 5 }\n}"),
+            "Line 2, column 3: Unknown word 'INTEGR'"
+        );
+        assert_eq!(
+            error("SEQUENCE {\n  NULL {}"),
+            "Line 1, column 10: Missing '}' for this '{'"
+        );
+        assert_eq!(error("NULL {} }"), "Line 1, column 9: Unexpected '}'");
+        assert_eq!(
+            error("OCTET_STRING { `012` }"),
+            "Line 1, column 16: Invalid hex literal `012`"
+        );
+        assert_eq!(
+            error("NULL length:3 `00`"),
+            "Line 1, column 15: Expected '{' after a length modifier"
+        );
+        assert!(error("NULL long-form:1 length:300 {}").starts_with("Line 1, column 6:"));
+        assert!(error("UTF8String { \"abc }").starts_with("Line 1, column 14: Unterminated string"));
+        assert!(error("[CONTEXT] {}").contains("Invalid tag"));
+    }
+}
@@ -0,0 +1,768 @@
+//! Output in the format of `openssl asn1parse`.
+//!
+//! Follows OpenSSL's parser byte for byte, including where it gives up
+//! ("Error in encoding") and its quirks: offsets count from the start of the
+//! parsed buffer, `l=inf` marks indefinite lengths, non-universal primitives
+//! print no value and strings are written as raw octets. `indent` matches `-i`,
+//! `offset` and `length` match `-offset` and `-length`, and `strparse` matches
+//! repeated `-strparse` options, each offset counting from the start of the
+//! previous string's value (so a BIT STRING is entered after its unused-bits
+//! octet, and a SEQUENCE is parsed again from its own identifier).
+//!
+//! OBJECT IDENTIFIERs print the name OpenSSL has for them (see
+//! `openssl_objects`), and the dotted form otherwise.
+
+use crate::openssl_objects::OPENSSL_NAMES;
+use serde::Deserialize;
+use std::error::Error;
+
+/// Deepest nesting OpenSSL will parse
+const MAX_DEPTH: usize = 128;
+
+/// Nesting limit when collecting the segments of a constructed string
+const MAX_STRING_NEST: usize = 5;
+
+/// Tag names as printed by OpenSSL, indexed by universal tag number
+const TAG_NAMES: [&str; 31] = [
+    "EOC",
+    "BOOLEAN",
+    "INTEGER",
+    "BIT STRING",
+    "OCTET STRING",
+    "NULL",
+    "OBJECT",
+    "OBJECT DESCRIPTOR",
+    "EXTERNAL",
+    "REAL",
+    "ENUMERATED",
+    "<ASN1 11>",
+    "UTF8STRING",
+    "<ASN1 13>",
+    "<ASN1 14>",
+    "<ASN1 15>",
+    "SEQUENCE",
+    "SET",
+    "NUMERICSTRING",
+    "PRINTABLESTRING",
+    "T61STRING",
+    "VIDEOTEXSTRING",
+    "IA5STRING",
+    "UTCTIME",
+    "GENERALIZEDTIME",
+    "GRAPHICSTRING",
+    "VISIBLESTRING",
+    "GENERALSTRING",
+    "UNIVERSALSTRING",
+    "<ASN1 29>",
+    "BMPSTRING",
+];
+
+/// Options for `asn1parse`, named after the `openssl asn1parse` flags
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Asn1ParseOptions {
+    /// Indent each element by its depth (`-i`)
+    pub indent: bool,
+    /// Offsets of elements whose values are parsed in turn (`-strparse`)
+    pub strparse: Vec<usize>,
+    /// Where to start parsing (`-offset`)
+    pub offset: usize,
+    /// Octets to parse, 0 for everything up to the end (`-length`)
+    pub length: usize,
+}
+
+/// Identifier and length as read by OpenSSL's `ASN1_get_object`
+struct Header {
+    constructed: bool,
+    indefinite: bool,
+    class: u8,
+    tag: u32,
+    length: usize,
+    header_length: usize,
+}
+
+/// Read the header at `pos`, allowing `max` octets for the whole element.
+/// OpenSSL rejects the same headers, including a long-form length with no
+/// octet after it.
+fn read_header(data: &[u8], pos: usize, max: usize) -> Option<Header> {
+    let first = *data.get(pos)?;
+    let omax = max;
+    let mut max = max;
+    let mut p = pos + 1;
+    let tag = if first & 0x1F == 0x1F {
+        max = max.checked_sub(1).filter(|&max| max > 0)?;
+        let mut tag: u64 = 0;
+        while data[p] & 0x80 != 0 {
+            tag = (tag << 7) | (data[p] & 0x7F) as u64;
+            p += 1;
+            max = max.checked_sub(1).filter(|&max| max > 0)?;
+            if tag > (i32::MAX >> 7) as u64 {
+                return None;
+            }
+        }
+        tag = (tag << 7) | (data[p] & 0x7F) as u64;
+        p += 1;
+        max = max.checked_sub(1).filter(|&max| max > 0)?;
+        tag as u32
+    } else {
+        max = max.checked_sub(1).filter(|&max| max > 0)?;
+        (first & 0x1F) as u32
+    };
+
+    let constructed = first & 0x20 != 0;
+    let length_octet = data[p];
+    let remaining = max - 1;
+    p += 1;
+    let (indefinite, length) = if length_octet == 0x80 {
+        (true, 0)
+    } else if length_octet & 0x80 != 0 {
+        let mut count = (length_octet & 0x7F) as usize;
+        if remaining < count + 1 {
+            return None;
+        }
+        while count > 0 && data[p] == 0 {
+            p += 1;
+            count -= 1;
+        }
+        if count > 8 {
+            return None;
+        }
+        let mut length: u64 = 0;
+        for _ in 0..count {
+            length = (length << 8) | data[p] as u64;
+            p += 1;
+        }
+        if length > i64::MAX as u64 {
+            return None;
+        }
+        (false, length as usize)
+    } else {
+        (false, length_octet as usize)
+    };
+
+    if indefinite && !constructed {
+        return None;
+    }
+    let header_length = p - pos;
+    if length > omax - header_length {
+        return None;
+    }
+    Some(Header {
+        constructed,
+        indefinite,
+        class: first & 0xC0,
+        tag,
+        length,
+        header_length,
+    })
+}
+
+/// How a call to `Parser::parse` ended
+#[derive(PartialEq)]
+enum Outcome {
+    Failed,
+    Done,
+    EndOfContents,
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    indent: bool,
+    out: Vec<u8>,
+}
+
+impl Parser<'_> {
+    fn write(&mut self, text: &str) {
+        self.out.extend_from_slice(text.as_bytes());
+    }
+
+    fn print_info(&mut self, offset: usize, depth: usize, header: &Header) {
+        let kind = if header.constructed {
+            "cons: "
+        } else {
+            "prim: "
+        };
+        let prefix = if header.indefinite {
+            format!(
+                "{:5}:d={:<2} hl={} l=inf  {}",
+                offset, depth, header.header_length, kind
+            )
+        } else {
+            format!(
+                "{:5}:d={:<2} hl={} l={:4} {}",
+                offset, depth, header.header_length, header.length, kind
+            )
+        };
+        let tag = match header.class {
+            0xC0 => format!("priv [ {} ] ", header.tag),
+            0x80 => format!("cont [ {} ]", header.tag),
+            0x40 => format!("appl [ {} ]", header.tag),
+            _ if header.tag > 30 => format!("<ASN1 {}>", header.tag),
+            _ => TAG_NAMES[header.tag as usize].to_string(),
+        };
+        let indent = if self.indent { depth } else { 0 };
+        self.write(&format!("{}{}{:<18}", prefix, " ".repeat(indent), tag));
+    }
+
+    /// Print the elements in `length` octets from `pos`, like OpenSSL's `asn1_parse2`
+    fn parse(&mut self, pos: &mut usize, length: usize, depth: usize) -> Outcome {
+        if depth > MAX_DEPTH {
+            self.write("BAD RECURSION DEPTH\n");
+            return Outcome::Failed;
+        }
+        let end = *pos + length;
+        let mut length = length;
+        while length > 0 {
+            let start = *pos;
+            let Some(header) = read_header(self.data, start, length) else {
+                self.write("Error in encoding\n");
+                return Outcome::Failed;
+            };
+            *pos += header.header_length;
+            length -= header.header_length;
+            self.print_info(start, depth, &header);
+
+            let mut content_length = header.length;
+            if header.constructed {
+                self.write("\n");
+                if header.indefinite {
+                    let content_start = *pos;
+                    loop {
+                        match self.parse(pos, end - *pos, depth + 1) {
+                            Outcome::Failed => return Outcome::Failed,
+                            Outcome::EndOfContents => break,
+                            Outcome::Done if *pos >= end => break,
+                            Outcome::Done => {}
+                        }
+                    }
+                    content_length = *pos - content_start;
+                } else {
+                    let content_end = *pos + header.length;
+                    let mut remaining = header.length;
+                    while *pos < content_end {
+                        let child_start = *pos;
+                        if self.parse(pos, remaining, depth + 1) == Outcome::Failed {
+                            return Outcome::Failed;
+                        }
+                        remaining -= *pos - child_start;
+                    }
+                }
+            } else if header.class != 0 {
+                *pos += header.length;
+                self.write("\n");
+            } else {
+                let element = &self.data[start..*pos + header.length];
+                self.print_value(&header, element);
+                self.write("\n");
+                *pos += header.length;
+                if header.tag == 0 {
+                    return Outcome::EndOfContents;
+                }
+            }
+            length -= content_length;
+        }
+        Outcome::Done
+    }
+
+    /// The value printed after a universal primitive's tag name
+    fn print_value(&mut self, header: &Header, element: &[u8]) {
+        let content = &element[header.header_length..];
+        let mut dump_content = false;
+        match header.tag {
+            12 | 18 | 19 | 20 | 22 | 23 | 24 | 26 => {
+                self.write(":");
+                self.out.extend_from_slice(content);
+            }
+            6 => match object_text(content) {
+                Some(text) => self.write(&format!(":{}", text)),
+                None => {
+                    self.write(":BAD OBJECT");
+                    dump_content = true;
+                }
+            },
+            1 => {
+                if content.len() != 1 {
+                    self.write(":BAD BOOLEAN");
+                    dump_content = true;
+                }
+                if let Some(value) = content.first() {
+                    self.write(&format!(":{}", value));
+                }
+            }
+            4 => {
+                let printable = content
+                    .iter()
+                    .all(|&b| (b' '..=b'~').contains(&b) || matches!(b, b'\n' | b'\r' | b'\t'));
+                if printable && !content.is_empty() {
+                    self.write(":");
+                    self.out.extend_from_slice(content);
+                } else if !content.is_empty() {
+                    self.write(&format!("[HEX DUMP]:{}", hex(content)));
+                }
+            }
+            2 | 10 => match integer_magnitude(content) {
+                Some((negative, magnitude)) => {
+                    let sign = if negative { "-" } else { "" };
+                    self.write(&format!(":{}{}", sign, hex(&magnitude)));
+                }
+                None => {
+                    let name = if header.tag == 2 {
+                        "INTEGER"
+                    } else {
+                        "ENUMERATED"
+                    };
+                    self.write(&format!(":BAD {}", name));
+                    dump_content = true;
+                }
+            },
+            _ => {}
+        }
+        if dump_content {
+            self.write(&format!(":[{}]", hex(content)));
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Sign and magnitude of INTEGER content, or None where OpenSSL rejects it
+/// (empty, or padded with a redundant sign octet)
+fn integer_magnitude(content: &[u8]) -> Option<(bool, Vec<u8>)> {
+    let (&first, rest) = content.split_first()?;
+    let negative = first & 0x80 != 0;
+    let padded = match first {
+        _ if rest.is_empty() => false,
+        0x00 => true,
+        0xFF => rest.iter().any(|&b| b != 0),
+        _ => false,
+    };
+    if padded && negative == (rest[0] & 0x80 != 0) {
+        return None;
+    }
+    let bytes = if padded { rest } else { content };
+    if !negative {
+        return Some((false, bytes.to_vec()));
+    }
+
+    let mut magnitude: Vec<u8> = bytes.iter().map(|b| !b).collect();
+    for byte in magnitude.iter_mut().rev() {
+        let (sum, overflow) = byte.overflowing_add(1);
+        *byte = sum;
+        if !overflow {
+            break;
+        }
+    }
+    Some((true, magnitude))
+}
+
+/// Decimal digits, least significant first, times `factor` plus `add`
+fn multiply_add(digits: &mut Vec<u8>, factor: u32, add: u32) {
+    let mut carry = add;
+    for digit in digits.iter_mut() {
+        let value = *digit as u32 * factor + carry;
+        *digit = (value % 10) as u8;
+        carry = value / 10;
+    }
+    while carry > 0 {
+        digits.push((carry % 10) as u8);
+        carry /= 10;
+    }
+}
+
+/// OpenSSL's text for OBJECT IDENTIFIER content: the long name if known,
+/// else dotted; None for encodings OpenSSL rejects
+fn object_text(content: &[u8]) -> Option<String> {
+    if content.last().is_none_or(|&b| b & 0x80 != 0) {
+        return None;
+    }
+    if content
+        .iter()
+        .enumerate()
+        .any(|(i, &b)| b == 0x80 && (i == 0 || content[i - 1] & 0x80 == 0))
+    {
+        return None;
+    }
+
+    let mut arcs = Vec::new();
+    let mut digits = Vec::new();
+    for &byte in content {
+        multiply_add(&mut digits, 128, (byte & 0x7F) as u32);
+        if byte & 0x80 != 0 {
+            continue;
+        }
+        if digits.is_empty() {
+            digits.push(0);
+        }
+        let arc: String = digits.iter().rev().map(|d| (b'0' + d) as char).collect();
+        let arc = arc.trim_start_matches('0');
+        arcs.push(if arc.is_empty() {
+            "0".to_string()
+        } else {
+            arc.to_string()
+        });
+        digits.clear();
+    }
+
+    let first = &arcs[0];
+    let prefix = match first.parse::<u64>() {
+        Ok(value) if value < 80 => format!("{}.{}", value / 40, value % 40),
+        Ok(value) => format!("2.{}", value - 80),
+        Err(_) => format!("2.{}", subtract_80(first)),
+    };
+    let dotted = std::iter::once(prefix)
+        .chain(arcs[1..].iter().cloned())
+        .collect::<Vec<_>>()
+        .join(".");
+    Some(match OPENSSL_NAMES.iter().find(|(oid, _)| *oid == dotted) {
+        Some((_, name)) => name.to_string(),
+        None => dotted,
+    })
+}
+
+/// A decimal string of at least 20 digits, minus 80
+fn subtract_80(decimal: &str) -> String {
+    let mut digits: Vec<u8> = decimal.bytes().rev().map(|b| b - b'0').collect();
+    let mut borrow = 80u32;
+    for digit in digits.iter_mut() {
+        let subtract = borrow % 10;
+        borrow /= 10;
+        if (*digit as u32) < subtract {
+            *digit = (*digit as u32 + 10 - subtract) as u8;
+            borrow += 1;
+        } else {
+            *digit -= subtract as u8;
+        }
+    }
+    while digits.len() > 1 && digits.last() == Some(&0) {
+        digits.pop();
+    }
+    digits.iter().rev().map(|d| (b'0' + d) as char).collect()
+}
+
+/// End of the indefinite-length content starting at `pos`, after its end-of-contents
+fn find_end(data: &[u8], mut pos: usize) -> Option<usize> {
+    let mut open = 1;
+    while open > 0 {
+        if data.get(pos..pos + 2) == Some(&[0x00, 0x00]) {
+            open -= 1;
+            pos += 2;
+            continue;
+        }
+        let header = read_header(data, pos, data.len() - pos)?;
+        pos += header.header_length;
+        if header.indefinite {
+            open += 1;
+        } else {
+            pos += header.length;
+        }
+    }
+    Some(pos)
+}
+
+/// Concatenated content of a constructed string's primitive segments
+fn collect_segments(
+    data: &[u8],
+    pos: usize,
+    end: Option<usize>,
+    depth: usize,
+    collected: &mut Vec<u8>,
+) -> Option<usize> {
+    let mut pos = pos;
+    loop {
+        match end {
+            Some(end) if pos >= end => return Some(pos),
+            None if data.get(pos..pos + 2) == Some(&[0x00, 0x00]) => return Some(pos + 2),
+            _ => {}
+        }
+        let header = read_header(data, pos, end.unwrap_or(data.len()) - pos)?;
+        pos += header.header_length;
+        if header.constructed {
+            if depth >= MAX_STRING_NEST {
+                return None;
+            }
+            let inner_end = (!header.indefinite).then_some(pos + header.length);
+            pos = collect_segments(data, pos, inner_end, depth + 1, collected)?;
+        } else {
+            collected.extend_from_slice(&data[pos..pos + header.length]);
+            pos += header.length;
+        }
+    }
+}
+
+/// The octets `-strparse` continues with for the element at the start of `data`,
+/// as OpenSSL's `d2i_ASN1_TYPE` stores them
+fn strparse_value(data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let parse_error = || -> Box<dyn Error> { "Error parsing structure".into() };
+    let header = read_header(data, 0, data.len()).ok_or_else(parse_error)?;
+    let content_start = header.header_length;
+    let element_end = match header.indefinite {
+        true => find_end(data, content_start).ok_or_else(parse_error)?,
+        false => content_start + header.length,
+    };
+
+    if header.class != 0 || matches!(header.tag, 16 | 17) {
+        if header.class == 0 && !header.constructed {
+            return Err(parse_error());
+        }
+        return Ok(data[..element_end].to_vec());
+    }
+
+    let content = if header.constructed {
+        if matches!(header.tag, 1 | 2 | 5 | 6 | 10) {
+            return Err(parse_error());
+        }
+        let mut collected = Vec::new();
+        let end = (!header.indefinite).then_some(element_end);
+        collect_segments(data, content_start, end, 0, &mut collected).ok_or_else(parse_error)?;
+        collected
+    } else {
+        data[content_start..element_end].to_vec()
+    };
+
+    match header.tag {
+        1 | 5 | 6 => {
+            let valid = match header.tag {
+                1 => content.len() == 1,
+                5 => content.is_empty(),
+                _ => object_text(&content).is_some(),
+            };
+            if !valid {
+                return Err(parse_error());
+            }
+            Err(format!("Can't parse {} type", TAG_NAMES[header.tag as usize]).into())
+        }
+        2 | 10 => Ok(integer_magnitude(&content).ok_or_else(parse_error)?.1),
+        3 => {
+            let (&unused, bits) = content.split_first().ok_or_else(parse_error)?;
+            if unused > 7 {
+                return Err(parse_error());
+            }
+            let mut bits = bits.to_vec();
+            if let Some(last) = bits.last_mut() {
+                *last &= 0xFF << unused;
+            }
+            Ok(bits)
+        }
+        30 if !content.len().is_multiple_of(2) => Err(parse_error()),
+        28 if !content.len().is_multiple_of(4) => Err(parse_error()),
+        23 if content.len() < 13 => Err(parse_error()),
+        24 if content.len() < 15 => Err(parse_error()),
+        _ => Ok(content),
+    }
+}
+
+/// `openssl asn1parse` output for DER
+pub fn asn1parse(der: &[u8], options: &Asn1ParseOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut buffer = der.to_vec();
+    for &offset in &options.strparse {
+        if offset == 0 {
+            return Err("Non-positive number \"0\" for option -strparse".into());
+        }
+        // OpenSSL skips offsets past the end of the buffer
+        if offset >= buffer.len() {
+            continue;
+        }
+        buffer = strparse_value(&buffer[offset..])?;
+    }
+
+    if options.offset >= buffer.len() {
+        return Err("Error: offset out of range".into());
+    }
+    let available = buffer.len() - options.offset;
+    let length = match options.length {
+        0 => available,
+        length => length.min(available),
+    };
+
+    let data = &buffer[options.offset..options.offset + length];
+    let mut parser = Parser {
+        data,
+        indent: options.indent,
+        out: Vec::new(),
+    };
+    parser.parse(&mut 0, data.len(), 0);
+    Ok(parser.out)
+}
+
+/// `openssl asn1parse` output for a PEM document, with options given as JSON
+/// (e.g. `{"indent": true, "strparse": [19]}`)
+pub fn asn1parse_internal(pem_input: &str, options_json: &str) -> Result<String, Box<dyn Error>> {
+    let options: Asn1ParseOptions = serde_json::from_str(options_json)?;
+    let pem = pem::parse(pem_input)?;
+    Ok(String::from_utf8_lossy(&asn1parse(pem.contents(), &options)?).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{CERT_PEM, PSS_CERT_PEM};
+
+    fn parse(der: &[u8], options: &Asn1ParseOptions) -> String {
+        String::from_utf8(asn1parse(der, options).unwrap()).unwrap()
+    }
+
+    fn cert() -> Vec<u8> {
+        pem::parse(CERT_PEM).unwrap().into_contents()
+    }
+
+    #[test]
+    fn test_certificate_matches_openssl() {
+        let plain = parse(&cert(), &Asn1ParseOptions::default());
+        let lines: Vec<&str> = plain.lines().collect();
+        assert_eq!(lines[0], "    0:d=0  hl=4 l= 453 cons: SEQUENCE          ");
+        assert_eq!(lines[2], "    8:d=2  hl=2 l=   3 cons: cont [ 0 ]        ");
+        assert_eq!(lines[4], "   13:d=2  hl=2 l=  20 prim: INTEGER           :59E77699B8F0643E0E8B98F602162116FD1BAC4E");
+        assert_eq!(
+            lines[6],
+            "   37:d=3  hl=2 l=   8 prim: OBJECT            :ecdsa-with-SHA256"
+        );
+        assert_eq!(
+            lines[15],
+            "   71:d=5  hl=2 l=  11 prim: UTF8STRING        :example.com"
+        );
+        assert_eq!(
+            lines[37],
+            "  255:d=5  hl=2 l=  22 prim: OCTET STRING      [HEX DUMP]:04147E90626F86BB692B9B332FEDB288165F5732B385"
+        );
+        assert_eq!(
+            lines[43],
+            "  319:d=5  hl=2 l=   1 prim: BOOLEAN           :255"
+        );
+        assert!(plain.ends_with("  382:d=1  hl=2 l=  73 prim: BIT STRING        \n"));
+
+        let indented = parse(
+            &cert(),
+            &Asn1ParseOptions {
+                indent: true,
+                ..Default::default()
+            },
+        );
+        let lines: Vec<&str> = indented.lines().collect();
+        assert_eq!(lines[1], "    4:d=1  hl=4 l= 362 cons:  SEQUENCE          ");
+        assert_eq!(
+            lines[3],
+            "   10:d=3  hl=2 l=   1 prim:    INTEGER           :02"
+        );
+    }
+
+    #[test]
+    fn test_pss_certificate_object_names() {
+        let der = pem::parse(PSS_CERT_PEM).unwrap().into_contents();
+        let output = parse(&der, &Asn1ParseOptions::default());
+        let objects: Vec<&str> = output
+            .lines()
+            .filter_map(|line| {
+                line.split_once("prim: OBJECT            :")
+                    .map(|(_, name)| name)
+            })
+            .collect();
+        assert_eq!(
+            objects,
+            [
+                "rsassaPss",
+                "sha256",
+                "mgf1",
+                "sha256",
+                "commonName",
+                "description",
+                "commonName",
+                "description",
+                "rsaEncryption",
+                "X509v3 Subject Key Identifier",
+                "X509v3 Authority Key Identifier",
+                "X509v3 Basic Constraints",
+                "rsassaPss",
+                "sha256",
+                "mgf1",
+                "sha256",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strparse_offset_and_length() {
+        let options = Asn1ParseOptions {
+            strparse: vec![382],
+            indent: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse(&cert(), &options).lines().take(2).collect::<Vec<_>>(),
+            [
+                "    0:d=0  hl=2 l=  70 cons: SEQUENCE          ",
+                "    2:d=1  hl=2 l=  33 prim:  INTEGER           :9E4144B6E09FFBBA8AC83FE1C5C59A218FC9CEB2EF340590A1D94BD7ADBFE34D",
+            ]
+        );
+
+        let options = Asn1ParseOptions {
+            offset: 10,
+            length: 1,
+            ..Default::default()
+        };
+        assert_eq!(parse(&cert(), &options), "Error in encoding\n");
+
+        // A SEQUENCE is parsed again from its identifier; offsets past the end are skipped
+        let options = Asn1ParseOptions {
+            strparse: vec![5000, 4],
+            ..Default::default()
+        };
+        assert!(parse(&cert(), &options)
+            .starts_with("    0:d=0  hl=4 l= 362 cons: SEQUENCE          \n"));
+
+        let error =
+            |options: Asn1ParseOptions| asn1parse(&cert(), &options).unwrap_err().to_string();
+        assert_eq!(
+            error(Asn1ParseOptions {
+                strparse: vec![37],
+                ..Default::default()
+            }),
+            "Can't parse OBJECT type"
+        );
+        assert_eq!(
+            error(Asn1ParseOptions {
+                offset: 457,
+                ..Default::default()
+            }),
+            "Error: offset out of range"
+        );
+        assert!(error(Asn1ParseOptions {
+            strparse: vec![0],
+            ..Default::default()
+        })
+        .starts_with("Non-positive"));
+    }
+
+    #[test]
+    fn test_openssl_quirks() {
+        let parse = |der: &[u8]| parse(der, &Asn1ParseOptions::default());
+        assert_eq!(
+            parse(&[0x30, 0x80, 0x02, 0x01, 0x01, 0x00, 0x00, 0x05, 0x00]),
+            "    0:d=0  hl=2 l=inf  cons: SEQUENCE          \n\
+             \x20   2:d=1  hl=2 l=   1 prim: INTEGER           :01\n\
+             \x20   5:d=1  hl=2 l=   0 prim: EOC               \n\
+             \x20   7:d=0  hl=2 l=   0 prim: NULL              \n"
+        );
+        assert_eq!(
+            parse(&[0xC1, 0x01, 0x41, 0x9F, 0x1F, 0x01, 0xFF]),
+            "    0:d=0  hl=2 l=   1 prim: priv [ 1 ]        \n\
+             \x20   3:d=0  hl=3 l=   1 prim: cont [ 31 ]       \n"
+        );
+        assert_eq!(
+            parse(&[
+                0x02, 0x02, 0x00, 0x01, 0x02, 0x02, 0xFF, 0x00, 0x06, 0x02, 0x80, 0x01, 0x01, 0x02,
+                0x01, 0x02
+            ]),
+            "    0:d=0  hl=2 l=   2 prim: INTEGER           :BAD INTEGER:[0001]\n\
+             \x20   4:d=0  hl=2 l=   2 prim: INTEGER           :-0100\n\
+             \x20   8:d=0  hl=2 l=   2 prim: OBJECT            :BAD OBJECT:[8001]\n\
+             \x20  12:d=0  hl=2 l=   2 prim: BOOLEAN           :BAD BOOLEAN:1:[0102]\n"
+        );
+        // A long-form length needs an octet after it, even for empty content
+        assert_eq!(
+            parse(&[0x05, 0x00, 0x04, 0x81, 0x00]),
+            "    0:d=0  hl=2 l=   0 prim: NULL              \nError in encoding\n"
+        );
+    }
+}
@@ -0,0 +1,407 @@
+//! Compact JSON for writing structures by hand.
+//!
+//! Each element is an object with a single key naming its type, whose value is
+//! the content in native JSON:
+//!
+//! ```json
+//! {"SEQUENCE": [
+//!   {"INTEGER": 5},
+//!   {"OID": "commonName"},
+//!   {"[0]": {"BOOLEAN": true}},
+//!   {"[1] IMPLICIT IA5String": "example.com"},
+//!   {"OCTET STRING": {"SEQUENCE": [{"NULL": null}]}}
+//! ]}
+//! ```
+//!
+//! - Keys are universal type names (as accepted by [`crate::query`], `OID` included)
+//!   or tags: `[0]`, `[CONTEXT 0]`, `[APPLICATION 3]`, `[PRIVATE 1]`.
+//! - SEQUENCE and SET take an array of elements. A tag with an element or array
+//!   is explicit (constructed); with a string it is primitive, its content given
+//!   as `0x` hex or text. `[n] IMPLICIT TYPE` encodes the content as TYPE.
+//! - BOOLEAN takes `true`/`false`, NULL `null`, INTEGER and ENUMERATED a number or
+//!   a string (any size, decimal or `0x` hex), OID a dotted string or a known name.
+//! - OCTET STRING and BIT STRING take `0x` hex, or an element or array whose DER
+//!   becomes the content; BIT STRING also takes a string of `0`s and `1`s.
+//! - Strings take text (BMPString and UniversalString are encoded as UTF-16 and UTF-32),
+//!   or `{"hex": "..."}` for raw content octets.
+//! - An object with `tag_class` is a node in the full JSON model, so `raw`
+//!   overrides can be mixed in.
+//!
+//! A top-level array encodes its elements one after another.
+
+use crate::decoder::{get_universal_tag_name, oid_for_name, to_hex};
+use crate::encoder::{encode_asn1_tree, encode_value};
+use crate::path::{assign_paths, child_path};
+use crate::query::{class_for_name, universal_tag_for_name};
+use crate::Asn1Node;
+use serde_json::Value;
+use std::error::Error;
+
+/// The tag named by a key
+struct Key {
+    tag_class: &'static str,
+    tag_number: u32,
+    /// Universal type whose rules encode the content of an `IMPLICIT` tag
+    implicit: Option<u32>,
+}
+
+fn parse_key(key: &str) -> Result<Key, Box<dyn Error>> {
+    let key = key.trim();
+    let Some(rest) = key.strip_prefix('[') else {
+        let tag_number =
+            universal_tag_for_name(key).ok_or_else(|| format!("Unknown type '{}'", key))?;
+        return Ok(Key {
+            tag_class: "UNIVERSAL",
+            tag_number,
+            implicit: None,
+        });
+    };
+
+    let (tag, after) = rest
+        .split_once(']')
+        .ok_or_else(|| format!("Missing ']' in '{}'", key))?;
+    let words: Vec<&str> = tag.split_whitespace().collect();
+    let (tag_class, number) = match words[..] {
+        [number] => ("CONTEXT", number),
+        [class, number] => (
+            class_for_name(class).ok_or_else(|| format!("Unknown class in '{}'", key))?,
+            number,
+        ),
+        _ => return Err(format!("Invalid tag '{}'", key).into()),
+    };
+    let tag_number = number
+        .parse::<u32>()
+        .map_err(|_| format!("Invalid tag number in '{}'", key))?;
+
+    let after = after.trim();
+    let implicit = if after.is_empty() {
+        None
+    } else {
+        let type_name = after
+            .strip_prefix("IMPLICIT")
+            .ok_or_else(|| format!("Expected IMPLICIT and a type after the tag in '{}'", key))?;
+        Some(
+            universal_tag_for_name(type_name.trim())
+                .ok_or_else(|| format!("Unknown type in '{}'", key))?,
+        )
+    };
+    Ok(Key {
+        tag_class,
+        tag_number,
+        implicit,
+    })
+}
+
+/// `0x` followed by an even number of hex digits (spaces and colons allowed)
+fn parse_hex(value: &str) -> Option<Vec<u8>> {
+    hex_digits(value.trim().strip_prefix("0x")?)
+}
+
+/// An even number of hex digits, spaces and colons allowed
+fn hex_digits(digits: &str) -> Option<Vec<u8>> {
+    let hex: String = digits.chars().filter(|c| !matches!(c, ' ' | ':')).collect();
+    if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Build the node tree for authored JSON, under a PEM pseudo-root
+pub fn parse_authored(value: &Value) -> Result<Asn1Node, Box<dyn Error>> {
+    let elements = match value {
+        Value::Array(elements) => elements.iter().collect(),
+        element => vec![element],
+    };
+    let mut root = Asn1Node {
+        tag_class: "PEM".to_string(),
+        is_constructed: true,
+        children: elements
+            .iter()
+            .enumerate()
+            .map(|(index, element)| element_node(element, &child_path("", index)))
+            .collect::<Result<_, _>>()?,
+        ..Default::default()
+    };
+    assign_paths(&mut root, "");
+    Ok(root)
+}
+
+/// Compile authored JSON to DER
+pub fn compile_authored(json: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let value: Value = serde_json::from_str(json)?;
+    Ok(encode_asn1_tree(&parse_authored(&value)?)?)
+}
+
+fn element_node(element: &Value, path: &str) -> Result<Asn1Node, Box<dyn Error>> {
+    let object = match element {
+        Value::Object(object) if object.contains_key("tag_class") => {
+            return Ok(serde_json::from_value(element.clone())?);
+        }
+        Value::Object(object) if object.len() == 1 => object,
+        _ => {
+            return Err(format!(
+                "Expected an object with a single type key at {}",
+                display_path(path)
+            )
+            .into())
+        }
+    };
+    let (key, content) = object.iter().next().unwrap();
+    let at = |e: Box<dyn Error>| -> Box<dyn Error> {
+        format!("'{}' at {}: {}", key, display_path(path), e).into()
+    };
+    let key_info = parse_key(key).map_err(at)?;
+
+    let mut node = Asn1Node {
+        label: key.clone(),
+        tag_class: key_info.tag_class.to_string(),
+        tag_number: key_info.tag_number,
+        ..Default::default()
+    };
+    let content_type = match (key_info.tag_class, key_info.implicit) {
+        ("UNIVERSAL", _) => Some(key_info.tag_number),
+        (_, implicit) => implicit,
+    };
+
+    match content_type {
+        Some(16 | 17) => {
+            node.is_constructed = true;
+            node.children = children(content, path)?;
+        }
+        Some(tag_number) => {
+            node.content_hex = Some(to_hex(&primitive_content(tag_number, content).map_err(at)?))
+        }
+        None => match content {
+            Value::Array(_) | Value::Object(_) => {
+                node.is_constructed = true;
+                node.children = children(content, path)?;
+            }
+            Value::String(text) => {
+                let content = parse_hex(text).unwrap_or_else(|| text.as_bytes().to_vec());
+                node.content_hex = Some(to_hex(&content));
+            }
+            Value::Null => node.content_hex = Some(String::new()),
+            _ => return Err(at("A tag takes elements, a string or null".into())),
+        },
+    }
+    Ok(node)
+}
+
+fn display_path(path: &str) -> &str {
+    if path.is_empty() {
+        "the top level"
+    } else {
+        path
+    }
+}
+
+/// The elements of a SEQUENCE, SET or explicit tag: an array, or a single element
+fn children(content: &Value, path: &str) -> Result<Vec<Asn1Node>, Box<dyn Error>> {
+    match content {
+        Value::Array(elements) => elements
+            .iter()
+            .enumerate()
+            .map(|(index, element)| element_node(element, &child_path(path, index)))
+            .collect(),
+        Value::Object(_) => Ok(vec![element_node(content, &child_path(path, 0))?]),
+        _ => Err(format!("Expected an array of elements at {}", display_path(path)).into()),
+    }
+}
+
+/// DER of nested elements, for OCTET STRING and BIT STRING content
+fn encapsulated(content: &Value) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(encode_asn1_tree(&parse_authored(content)?)?)
+}
+
+fn primitive_content(tag_number: u32, content: &Value) -> Result<Vec<u8>, Box<dyn Error>> {
+    let type_name = get_universal_tag_name(tag_number);
+    let mismatch = || -> Box<dyn Error> { format!("{} cannot be {}", type_name, content).into() };
+
+    let bytes = match (tag_number, content) {
+        (1, Value::Bool(value)) => vec![if *value { 0xFF } else { 0x00 }],
+        (2 | 10, Value::Number(number)) if number.is_i64() || number.is_u64() => {
+            encode_value(2, &number.to_string())?
+        }
+        (2 | 10, Value::String(value)) => encode_value(2, value)?,
+        (5, Value::Null) => Vec::new(),
+        (6, Value::String(value)) => {
+            let dotted = if value.chars().all(|c| c.is_ascii_digit() || c == '.') {
+                value.as_str()
+            } else {
+                oid_for_name(value).ok_or_else(|| format!("Unknown OID name '{}'", value))?
+            };
+            encode_value(6, dotted)?
+        }
+        (3, Value::Array(_) | Value::Object(_)) => [vec![0x00], encapsulated(content)?].concat(),
+        (3, Value::String(value)) => match parse_hex(value) {
+            Some(bytes) => [vec![0x00], bytes].concat(),
+            None if !value.is_empty() && value.chars().all(|c| c == '0' || c == '1') => {
+                pack_bits(value)
+            }
+            None => return Err(mismatch()),
+        },
+        (4, Value::Array(_) | Value::Object(_)) => encapsulated(content)?,
+        (28, Value::String(value)) => value
+            .chars()
+            .flat_map(|c| (c as u32).to_be_bytes())
+            .collect(),
+        (30, Value::String(value)) => value
+            .encode_utf16()
+            .flat_map(|unit| unit.to_be_bytes())
+            .collect(),
+        (4, Value::String(value)) => parse_hex(value).unwrap_or_else(|| value.as_bytes().to_vec()),
+        (7 | 12 | 18..=27 | 29, Value::String(value)) => value.as_bytes().to_vec(),
+        (7 | 12 | 18..=28 | 29 | 30, Value::Object(object)) => match object.get("hex") {
+            Some(Value::String(hex)) if object.len() == 1 => {
+                hex_digits(hex).ok_or_else(|| format!("Invalid hex '{}' for {}", hex, type_name))?
+            }
+            _ => return Err(mismatch()),
+        },
+        _ => return Err(mismatch()),
+    };
+    Ok(bytes)
+}
+
+/// BIT STRING content for a string of `0`s and `1`s
+fn pack_bits(bits: &str) -> Vec<u8> {
+    let unused = (8 - bits.len() % 8) % 8;
+    let mut content = vec![unused as u8];
+    for chunk in bits.as_bytes().chunks(8) {
+        let byte = chunk.iter().enumerate().fold(0u8, |byte, (i, &bit)| {
+            byte | (((bit == b'1') as u8) << (7 - i))
+        });
+        content.push(byte);
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(json: &str) -> Vec<u8> {
+        compile_authored(json).unwrap()
+    }
+
+    #[test]
+    fn test_compile_sequence() {
+        let der = compile(
+            r#"{"SEQUENCE": [{"INTEGER": 5}, {"OID": "commonName"}, {"[0]": {"BOOLEAN": true}}]}"#,
+        );
+        assert_eq!(
+            der,
+            [
+                0x30, 0x0D, 0x02, 0x01, 0x05, 0x06, 0x03, 0x55, 0x04, 0x03, 0xA0, 0x03, 0x01, 0x01,
+                0xFF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_scalars() {
+        assert_eq!(compile(r#"{"INTEGER": -129}"#), [0x02, 0x02, 0xFF, 0x7F]);
+        assert_eq!(compile(r#"{"INTEGER": "0x80"}"#), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(compile(r#"{"Null": null}"#), [0x05, 0x00]);
+        assert_eq!(
+            compile(r#"{"OBJECT IDENTIFIER": "1.2.840.113549.1.1.11"}"#)[..2],
+            [0x06, 0x09]
+        );
+        assert_eq!(
+            compile(r#"{"OID": "subjectAltName"}"#),
+            [0x06, 0x03, 0x55, 0x1D, 0x11]
+        );
+        assert_eq!(
+            compile(r#"{"BIT STRING": "101"}"#),
+            [0x03, 0x02, 0x05, 0xA0]
+        );
+        assert_eq!(
+            compile(r#"{"BIT STRING": "0xFF00"}"#),
+            [0x03, 0x03, 0x00, 0xFF, 0x00]
+        );
+        assert_eq!(
+            compile(r#"{"OCTET STRING": "0x01 02"}"#),
+            [0x04, 0x02, 0x01, 0x02]
+        );
+        assert_eq!(
+            compile(r#"{"UTF8String": " hi "}"#),
+            [0x0C, 0x04, 0x20, 0x68, 0x69, 0x20]
+        );
+        assert_eq!(compile(r#"{"BMPString": "A"}"#), [0x1E, 0x02, 0x00, 0x41]);
+        assert_eq!(
+            compile(r#"{"OID": "2.999.3"}"#),
+            [0x06, 0x03, 0x88, 0x37, 0x03]
+        );
+    }
+
+    #[test]
+    fn test_strings_take_text_or_explicit_hex() {
+        assert_eq!(
+            compile(r#"{"UTF8String": "0x41"}"#),
+            [0x0C, 0x04, 0x30, 0x78, 0x34, 0x31]
+        );
+        assert_eq!(
+            compile(r#"{"PrintableString": "0xAB"}"#),
+            [0x13, 0x04, 0x30, 0x78, 0x41, 0x42]
+        );
+        assert_eq!(
+            compile(r#"{"UTF8String": {"hex": "C3 A9"}}"#),
+            [0x0C, 0x02, 0xC3, 0xA9]
+        );
+        assert_eq!(
+            compile(r#"{"BMPString": {"hex": "0041"}}"#),
+            [0x1E, 0x02, 0x00, 0x41]
+        );
+        assert!(compile_authored(r#"{"UTF8String": {"hex": "ABC"}}"#).is_err());
+        assert!(compile_authored(r#"{"UTF8String": {"hex": "AB", "text": "x"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_compile_tags_and_encapsulation() {
+        assert_eq!(
+            compile(r#"{"[1] IMPLICIT IA5String": "a.b"}"#),
+            [0x81, 0x03, 0x61, 0x2E, 0x62]
+        );
+        assert_eq!(
+            compile(r#"{"[APPLICATION 2] IMPLICIT SEQUENCE": []}"#),
+            [0x62, 0x00]
+        );
+        assert_eq!(
+            compile(r#"{"[PRIVATE 3]": "0xABCD"}"#),
+            [0xC3, 0x02, 0xAB, 0xCD]
+        );
+        assert_eq!(
+            compile(r#"{"OCTET STRING": {"SEQUENCE": [{"BOOLEAN": false}]}}"#),
+            [0x04, 0x05, 0x30, 0x03, 0x01, 0x01, 0x00]
+        );
+        assert_eq!(
+            compile(r#"{"BIT STRING": {"NULL": null}}"#),
+            [0x03, 0x03, 0x00, 0x05, 0x00]
+        );
+
+        // Top-level arrays and full JSON nodes with raw overrides
+        assert_eq!(
+            compile(
+                r#"[{"NULL": null}, {"tag_class": "UNIVERSAL", "tag_number": 5, "is_constructed": false, "raw": {"length": 1}}]"#
+            ),
+            [0x05, 0x00, 0x05, 0x01]
+        );
+    }
+
+    #[test]
+    fn test_errors_name_the_element() {
+        let error =
+            compile_authored(r#"{"SEQUENCE": [{"NULL": null}, {"INTEGER": true}]}"#).unwrap_err();
+        assert!(error.to_string().contains("'INTEGER' at 0.1"), "{}", error);
+        assert!(compile_authored(r#"{"OID": "notAnOid"}"#)
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown OID name"));
+        assert!(compile_authored(r#"{"FLOAT": 1}"#).is_err());
+        assert!(compile_authored(r#"{"[0] EXPLICIT INTEGER": 1}"#).is_err());
+        assert!(compile_authored(r#"{"INTEGER": 1, "NULL": null}"#).is_err());
+        assert!(compile_authored(r#"{"INTEGER": 1.5}"#).is_err());
+    }
+}
@@ -1,132 +1,145 @@
-use crate::Asn1Node;
+use crate::path::assign_paths;
+use crate::tlv::{DecodeError, Reader, TagClass, Tlv};
+use crate::{Asn1Node, SCHEMA_VERSION};
 
 pub fn decode_pem_internal(pem_input: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(decode_pem_tree(pem_input)?.to_json()?)
+}
+
+/// Decode a PEM document into a node tree rooted at a PEM pseudo-node
+pub fn decode_pem_tree(pem_input: &str) -> Result<Asn1Node, Box<dyn std::error::Error>> {
     let pem = pem::parse(pem_input)?;
-    
+    decode_der_tree(pem.tag(), pem.contents(), None)
+}
+
+/// Decode DER into a node tree rooted at a PEM pseudo-node labelled `pem_label`.
+///
+/// Top-level elements are at depth 0; with `max_depth` set, constructed nodes at
+/// that depth are left `collapsed` instead of decoding their children.
+pub fn decode_der_tree(pem_label: &str, der: &[u8], max_depth: Option<usize>) -> Result<Asn1Node, Box<dyn std::error::Error>> {
     let mut root = Asn1Node {
-        label: format!("PEM: {}", pem.tag()),
+        schema_version: Some(SCHEMA_VERSION),
+        path: String::new(),
+        label: format!("PEM: {}", pem_label),
         tag: 0,
         tag_number: 0,
         tag_class: "PEM".to_string(),
         is_constructed: true,
-        length: pem.contents().len(),
+        length: der.len(),
         value: None,
+        content_hex: None,
         children: Vec::new(),
         byte_offset: 0,
-        byte_length: pem.contents().len(),
+        byte_length: der.len(),
+        content_offset: 0,
+        header_length: 0,
+        identifier_octets: String::new(),
+        length_octets: String::new(),
+        length_form: None,
+        collapsed: false,
+        dirty: false,
+        named_bits: false,
+        profile: None,
+        raw: None,
+        source_hex: Some(to_hex(der)),
     };
     
-    decode_der_recursive(pem.contents(), &mut root.children, 0)?;
+    decode_elements(Reader::new(der), &mut root.children, max_depth)?;
+    assign_paths(&mut root, "");
     
-    Ok(serde_json::to_string_pretty(&root)?)
+    Ok(root)
 }
 
 pub fn decode_der_recursive(data: &[u8], nodes: &mut Vec<Asn1Node>, base_offset: usize) -> Result<(), Box<dyn std::error::Error>> {
-    let mut pos = 0;
-    
-    while pos < data.len() {
-        if pos >= data.len() {
-            break;
-        }
-        
-        let node_start = pos;
-        let tag = data[pos];
-        pos += 1;
-        
-        if pos >= data.len() {
-            break;
-        }
-        
-        let (length, length_bytes) = parse_length(&data[pos..])?;
-        pos += length_bytes;
-        
-        if pos + length > data.len() {
-            break;
-        }
-        
-        let tag_class = (tag & 0xC0) >> 6;
-        let is_constructed = (tag & 0x20) != 0;
-        let tag_number = tag & 0x1F;
-        
-        let tag_class_str = match tag_class {
-            0 => "UNIVERSAL",
-            1 => "APPLICATION",
-            2 => "CONTEXT",
-            3 => "PRIVATE",
-            _ => "UNKNOWN",
-        };
-        
-        let tag_type = get_universal_tag_name(tag_number);
-        
-        let label = if tag_class == 0 {
-            format!("{} (Tag {})", tag_type, tag_number)
-        } else {
-            format!("[{}] Tag {}", tag_class_str, tag_number)
-        };
-        
-        let content = &data[pos..pos + length];
-        let total_length = 1 + length_bytes + length; // tag + length bytes + content
-        
-        let mut node = Asn1Node {
-            label,
-            tag: tag_number,
-            tag_number: tag_number as u32,
-            tag_class: tag_class_str.to_string(),
-            is_constructed,
-            length,
-            value: None,
-            children: Vec::new(),
-            byte_offset: base_offset + node_start,
-            byte_length: total_length,
+    decode_elements(Reader::with_offset(data, base_offset), nodes, None)
+}
+
+/// Build nodes for every element yielded by the reader.
+///
+/// A truncated trailing element ends decoding quietly, as the tree view should
+/// still show everything before it; other malformed encodings are errors.
+fn decode_elements(reader: Reader, nodes: &mut Vec<Asn1Node>, max_depth: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    for tlv in reader {
+        let tlv = match tlv {
+            Ok(tlv) => tlv,
+            Err(DecodeError::Truncated(_)) => break,
+            Err(e) => return Err(e.into()),
         };
-        
-        if is_constructed {
-            decode_der_recursive(content, &mut node.children, base_offset + pos)?;
-        } else {
-            node.value = Some(decode_value(tag_number, content));
-        }
-        
-        // For BIT STRING, also show value even if constructed
-        if tag_number == 3 && node.value.is_none() {
-            node.value = Some(decode_bit_string(content));
-        }
-        
-        nodes.push(node);
-        pos += length;
+        nodes.push(decode_tlv_to_depth(&tlv, max_depth)?);
     }
     
     Ok(())
 }
 
-fn parse_length(data: &[u8]) -> Result<(usize, usize), Box<dyn std::error::Error>> {
-    if data.is_empty() {
-        return Err("Unexpected end of data".into());
-    }
+/// Build the node (and its subtree) for a single element
+pub fn decode_tlv(tlv: &Tlv) -> Result<Asn1Node, Box<dyn std::error::Error>> {
+    decode_tlv_to_depth(tlv, None)
+}
+
+/// Build the node for a single element and `max_depth` levels of descendants
+/// (all of them for None). A constructed node whose children were not decoded is
+/// marked `collapsed`.
+pub fn decode_tlv_to_depth(tlv: &Tlv, max_depth: Option<usize>) -> Result<Asn1Node, Box<dyn std::error::Error>> {
+    let tag = tlv.tag();
+    let tag_class_str = tag.class.name();
+    let tag_number = tag.number;
     
-    let first_byte = data[0];
+    let tag_type = get_universal_tag_name(tag_number);
     
-    if first_byte & 0x80 == 0 {
-        Ok((first_byte as usize, 1))
+    let label = if tag.class == TagClass::Universal {
+        format!("{} (Tag {})", tag_type, tag_number)
     } else {
-        let num_octets = (first_byte & 0x7F) as usize;
-        if num_octets == 0 || num_octets > 4 {
-            return Err("Invalid length encoding".into());
-        }
-        
-        if data.len() < 1 + num_octets {
-            return Err("Unexpected end of data".into());
-        }
-        
-        let mut length: usize = 0;
-        for i in 0..num_octets {
-            length = (length << 8) | (data[1 + i] as usize);
+        format!("[{}] Tag {}", tag_class_str, tag_number)
+    };
+    
+    let content = tlv.content();
+    
+    let mut node = Asn1Node {
+        schema_version: None,
+        path: String::new(),
+        label,
+        tag: tag_number,
+        tag_number,
+        tag_class: tag_class_str.to_string(),
+        is_constructed: tag.constructed,
+        length: content.len(),
+        value: None,
+        content_hex: None,
+        children: Vec::new(),
+        byte_offset: tlv.offset(),
+        byte_length: tlv.raw().len(),
+        content_offset: tlv.content_offset(),
+        header_length: tlv.header().len(),
+        identifier_octets: to_hex(tlv.identifier_octets()),
+        length_octets: to_hex(tlv.length_octets()),
+        length_form: Some(tlv.length_form()),
+        collapsed: false,
+        dirty: false,
+        named_bits: false,
+        profile: None,
+        raw: None,
+        source_hex: None,
+    };
+    
+    if tag.constructed {
+        match max_depth {
+            Some(0) => node.collapsed = true,
+            _ => decode_elements(tlv.children(), &mut node.children, max_depth.map(|depth| depth - 1))?,
         }
-        
-        Ok((length, 1 + num_octets))
+    } else {
+        node.value = Some(decode_value(tag_number, content));
+        node.content_hex = Some(to_hex(content));
     }
+    
+    // For BIT STRING, also show value even if constructed
+    if tag_number == 3 && node.value.is_none() {
+        node.value = Some(decode_bit_string(content));
+    }
+    
+    Ok(node)
 }
 
-fn decode_value(tag: u8, content: &[u8]) -> String {
+pub fn decode_value(tag: u32, content: &[u8]) -> String {
     match tag {
         1 => decode_boolean(content),
         2 => decode_integer(content),
@@ -169,9 +182,18 @@ fn decode_integer(data: &[u8]) -> String {
             value = (value << 8) | (byte as i64);
         }
         value.to_string()
+    } else if is_negative {
+        // For large integers, show the hex magnitude at the same width
+        let mut magnitude = data.to_vec();
+        let mut carry = true;
+        for byte in magnitude.iter_mut().rev() {
+            let (sum, overflow) = (!*byte).overflowing_add(carry as u8);
+            *byte = sum;
+            carry = overflow;
+        }
+        format!("-0x{}", to_hex(&magnitude))
     } else {
-        // For large integers, show hex
-        format!("0x{}", data.iter().map(|b| format!("{:02X}", b)).collect::<String>())
+        format!("0x{}", to_hex(data))
     }
 }
 
@@ -209,11 +231,15 @@ fn decode_octet_string(data: &[u8]) -> String {
         }
     }
     
-    // Otherwise show as hex
+    // Otherwise show as hex (abbreviated, the full content is kept in content_hex)
     format!("[{} bytes] {}", data.len(), 
             data.iter().take(16).map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" "))
 }
 
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
 fn decode_utf8_string(data: &[u8]) -> String {
     match std::str::from_utf8(data) {
         Ok(s) => s.to_string(),
@@ -285,41 +311,63 @@ fn decode_oid(data: &[u8]) -> String {
     }
 }
 
+/// Known OIDs: dotted form, display name and ASN.1 value name
+const OID_NAMES: &[(&str, &str, &str)] = &[
+    // Common algorithm OIDs
+    ("1.2.840.113549.1.1.1", "RSA Encryption", "rsaEncryption"),
+    ("1.2.840.113549.1.1.5", "SHA-1 with RSA", "sha1WithRSAEncryption"),
+    ("1.2.840.113549.1.1.11", "SHA-256 with RSA", "sha256WithRSAEncryption"),
+    ("1.2.840.113549.1.1.12", "SHA-384 with RSA", "sha384WithRSAEncryption"),
+    ("1.2.840.113549.1.1.13", "SHA-512 with RSA", "sha512WithRSAEncryption"),
+    ("1.2.840.10045.4.3.2", "ECDSA with SHA-256", "ecdsa-with-SHA256"),
+    ("1.2.840.10045.4.3.3", "ECDSA with SHA-384", "ecdsa-with-SHA384"),
+    ("1.2.840.10045.4.3.4", "ECDSA with SHA-512", "ecdsa-with-SHA512"),
+
+    // X.509 certificate extension OIDs
+    ("2.5.29.14", "Subject Key Identifier", "subjectKeyIdentifier"),
+    ("2.5.29.15", "Key Usage", "keyUsage"),
+    ("2.5.29.17", "Subject Alternative Name", "subjectAltName"),
+    ("2.5.29.19", "Basic Constraints", "basicConstraints"),
+    ("2.5.29.31", "CRL Distribution Points", "cRLDistributionPoints"),
+    ("2.5.29.32", "Certificate Policies", "certificatePolicies"),
+    ("2.5.29.35", "Authority Key Identifier", "authorityKeyIdentifier"),
+    ("2.5.29.37", "Extended Key Usage", "extKeyUsage"),
+
+    // X.500 attribute types
+    ("2.5.4.3", "Common Name", "commonName"),
+    ("2.5.4.6", "Country", "countryName"),
+    ("2.5.4.7", "Locality", "localityName"),
+    ("2.5.4.8", "State or Province", "stateOrProvinceName"),
+    ("2.5.4.10", "Organization", "organizationName"),
+    ("2.5.4.11", "Organizational Unit", "organizationalUnitName"),
+
+    // PKCS OIDs
+    ("1.2.840.113549.1.9.1", "Email Address", "emailAddress"),
+];
+
 fn get_oid_name(oid: &str) -> Option<&'static str> {
-    match oid {
-        // Common algorithm OIDs
-        "1.2.840.113549.1.1.1" => Some("RSA Encryption"),
-        "1.2.840.113549.1.1.5" => Some("SHA-1 with RSA"),
-        "1.2.840.113549.1.1.11" => Some("SHA-256 with RSA"),
-        "1.2.840.113549.1.1.12" => Some("SHA-384 with RSA"),
-        "1.2.840.113549.1.1.13" => Some("SHA-512 with RSA"),
-        "1.2.840.10045.4.3.2" => Some("ECDSA with SHA-256"),
-        "1.2.840.10045.4.3.3" => Some("ECDSA with SHA-384"),
-        "1.2.840.10045.4.3.4" => Some("ECDSA with SHA-512"),
-        
-        // X.509 certificate extension OIDs
-        "2.5.29.14" => Some("Subject Key Identifier"),
-        "2.5.29.15" => Some("Key Usage"),
-        "2.5.29.17" => Some("Subject Alternative Name"),
-        "2.5.29.19" => Some("Basic Constraints"),
-        "2.5.29.31" => Some("CRL Distribution Points"),
-        "2.5.29.32" => Some("Certificate Policies"),
-        "2.5.29.35" => Some("Authority Key Identifier"),
-        "2.5.29.37" => Some("Extended Key Usage"),
-        
-        // X.500 attribute types
-        "2.5.4.3" => Some("Common Name"),
-        "2.5.4.6" => Some("Country"),
-        "2.5.4.7" => Some("Locality"),
-        "2.5.4.8" => Some("State or Province"),
-        "2.5.4.10" => Some("Organization"),
-        "2.5.4.11" => Some("Organizational Unit"),
-        
-        // PKCS OIDs
-        "1.2.840.113549.1.9.1" => Some("Email Address"),
-        
-        _ => None,
-    }
+    OID_NAMES.iter().find(|(dotted, _, _)| *dotted == oid).map(|(_, name, _)| *name)
+}
+
+/// Dotted form of a known OID, looked up by its display or ASN.1 name
+/// ignoring case, spaces, `-` and `_` (e.g. "commonName" or "Common Name")
+pub(crate) fn oid_for_name(name: &str) -> Option<&'static str> {
+    let normalize = |s: &str| {
+        s.chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '_'))
+            .collect::<String>()
+            .to_ascii_lowercase()
+    };
+    let name = normalize(name);
+    OID_NAMES
+        .iter()
+        .find(|(_, display, value_name)| normalize(display) == name || normalize(value_name) == name)
+        .map(|(dotted, _, _)| *dotted)
+}
+
+/// ASN.1 value name of a known OID, e.g. "commonName" for 2.5.4.3
+pub(crate) fn oid_value_name(oid: &str) -> Option<&'static str> {
+    OID_NAMES.iter().find(|(dotted, _, _)| *dotted == oid).map(|(_, _, value_name)| *value_name)
 }
 
 fn decode_relative_oid(data: &[u8]) -> String {
@@ -355,7 +403,7 @@ fn decode_relative_oid(data: &[u8]) -> String {
     oid
 }
 
-fn get_universal_tag_name(tag: u8) -> &'static str {
+pub fn get_universal_tag_name(tag: u32) -> &'static str {
     match tag {
         1 => "BOOLEAN",
         2 => "INTEGER",
@@ -392,6 +440,7 @@ fn get_universal_tag_name(tag: u8) -> &'static str {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tlv::{read_length, Length};
 
     #[test]
     fn test_decode_integer_small() {
@@ -411,7 +460,10 @@ mod tests {
     fn test_decode_integer_large() {
         let data = vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09];
         let result = decode_integer(&data);
-        assert!(result.starts_with("0x"));
+        assert_eq!(result, "0x010203040506070809");
+
+        let data = vec![0xFF, 0x7F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
+        assert_eq!(decode_integer(&data), "-0x0080FFFFFFFFFFFFFF");
     }
 
     #[test]
@@ -447,22 +499,22 @@ mod tests {
     fn test_decode_utf8_string() {
         let data = b"Hello, World!";
         let result = decode_utf8_string(data);
-        assert_eq!(result, "\"Hello, World!\"");
+        assert_eq!(result, "Hello, World!");
     }
 
     #[test]
     fn test_parse_length_short() {
         let data = vec![0x05];
-        let (length, bytes_used) = parse_length(&data).unwrap();
-        assert_eq!(length, 5);
+        let (length, bytes_used) = read_length(&data).unwrap();
+        assert_eq!(length, Length::Definite(5));
         assert_eq!(bytes_used, 1);
     }
 
     #[test]
     fn test_parse_length_long() {
         let data = vec![0x81, 0xFF];
-        let (length, bytes_used) = parse_length(&data).unwrap();
-        assert_eq!(length, 255);
+        let (length, bytes_used) = read_length(&data).unwrap();
+        assert_eq!(length, Length::Definite(255));
         assert_eq!(bytes_used, 2);
     }
 
@@ -488,7 +540,7 @@ mod tests {
         let sequence = &parsed["children"][0];
         assert!(sequence["label"].as_str().unwrap().contains("SEQUENCE"));
         assert_eq!(sequence["tag_number"].as_u64().unwrap(), 16);
-        assert_eq!(sequence["is_constructed"].as_bool().unwrap(), true);
+        assert!(sequence["is_constructed"].as_bool().unwrap());
         
         let integer = &sequence["children"][0];
         assert!(integer["label"].as_str().unwrap().contains("INTEGER"));
@@ -547,7 +599,7 @@ mod tests {
         let node = &parsed["children"][0];
         assert_eq!(node["tag_class"].as_str().unwrap(), "CONTEXT");
         assert_eq!(node["tag_number"].as_u64().unwrap(), 0);
-        assert_eq!(node["is_constructed"].as_bool().unwrap(), true);
+        assert!(node["is_constructed"].as_bool().unwrap());
     }
 
     #[test]
@@ -619,7 +671,7 @@ mod tests {
         // The root is a PEM container, check it has children
         assert!(parsed["children"].is_array());
         let children = parsed["children"].as_array().unwrap();
-        assert!(children.len() > 0, "Should have at least one child node");
+        assert!(!children.is_empty(), "Should have at least one child node");
         
         // The first child should be a SEQUENCE containing an INTEGER
         let first_child = &children[0];
@@ -673,4 +725,80 @@ mod tests {
         let sequence = &parsed["children"][0];
         assert_eq!(sequence["length"].as_u64().unwrap(), 3);
     }
+    #[test]
+    fn test_octet_string_content_hex_is_complete() {
+        // OCTET STRING with 20 bytes of binary content
+        let content: Vec<u8> = (0u8..20).collect();
+        let mut der = vec![0x04, 20];
+        der.extend_from_slice(&content);
+
+        let mut nodes = Vec::new();
+        decode_der_recursive(&der, &mut nodes, 0).unwrap();
+
+        let value = nodes[0].value.as_ref().unwrap();
+        assert!(value.starts_with("[20 bytes]"));
+        assert_eq!(
+            nodes[0].content_hex.as_deref(),
+            Some(to_hex(&content).as_str())
+        );
+    }
+
+    #[test]
+    fn test_header_anatomy() {
+        use crate::LengthForm;
+
+        // SEQUENCE (long form length 81 03) { INTEGER 1 }
+        let der = vec![0x30, 0x81, 0x03, 0x02, 0x01, 0x01];
+        let mut nodes = Vec::new();
+        decode_der_recursive(&der, &mut nodes, 0).unwrap();
+
+        let sequence = &nodes[0];
+        assert_eq!(sequence.header_length, 3);
+        assert_eq!(sequence.content_offset, 3);
+        assert_eq!(sequence.identifier_octets, "30");
+        assert_eq!(sequence.length_octets, "8103");
+        assert_eq!(sequence.length_form, Some(LengthForm::Long));
+
+        let integer = &sequence.children[0];
+        assert_eq!(integer.header_length, 2);
+        assert_eq!(integer.content_offset, 5);
+        assert_eq!(integer.length_form, Some(LengthForm::Short));
+    }
+
+    #[test]
+    fn test_indefinite_length() {
+        use crate::LengthForm;
+
+        // SEQUENCE (indefinite) { INTEGER 1 } EOC, followed by NULL
+        let der = vec![0x30, 0x80, 0x02, 0x01, 0x01, 0x00, 0x00, 0x05, 0x00];
+        let mut nodes = Vec::new();
+        decode_der_recursive(&der, &mut nodes, 0).unwrap();
+
+        assert_eq!(nodes.len(), 2);
+        let sequence = &nodes[0];
+        assert_eq!(sequence.length_form, Some(LengthForm::Indefinite));
+        assert_eq!(sequence.length, 3);
+        assert_eq!(sequence.byte_length, 7);
+        assert_eq!(sequence.children.len(), 1);
+        assert_eq!(nodes[1].byte_offset, 7);
+    }
+
+    #[test]
+    fn test_indefinite_length_missing_eoc() {
+        let der = vec![0x30, 0x80, 0x02, 0x01, 0x01];
+        let mut nodes = Vec::new();
+        assert!(decode_der_recursive(&der, &mut nodes, 0).is_err());
+    }
+
+    #[test]
+    fn test_high_tag_number() {
+        // [CONTEXT 200] primitive: 9F 81 48, length 1, content 00
+        let der = vec![0x9F, 0x81, 0x48, 0x01, 0x00];
+        let mut nodes = Vec::new();
+        decode_der_recursive(&der, &mut nodes, 0).unwrap();
+
+        assert_eq!(nodes[0].tag_number, 200);
+        assert_eq!(nodes[0].identifier_octets, "9F8148");
+        assert_eq!(nodes[0].header_length, 4);
+    }
 }
@@ -0,0 +1,442 @@
+use crate::document::Document;
+use crate::node::is_universal;
+use crate::Asn1Node;
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// Same position but a different class, tag number or constructed bit
+    Retagged,
+    /// Same tag but different content octets
+    ValueChanged,
+}
+
+/// One side of a change: where the node is in its document
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NodeSide {
+    pub path: String,
+    pub label: String,
+    pub byte_offset: usize,
+    pub byte_length: usize,
+    pub value: Option<String>,
+}
+
+/// A difference between two trees; `old` is None for added nodes and `new` for removed ones
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub old: Option<NodeSide>,
+    pub new: Option<NodeSide>,
+}
+
+impl NodeSide {
+    fn of(node: &Asn1Node) -> NodeSide {
+        NodeSide {
+            path: node.path.clone(),
+            label: node.label.clone(),
+            byte_offset: node.byte_offset,
+            byte_length: node.byte_length,
+            value: node.value.clone(),
+        }
+    }
+}
+
+/// The DER that one tree's offsets refer to
+struct Side<'a> {
+    der: &'a [u8],
+}
+
+impl Side<'_> {
+    fn raw(&self, node: &Asn1Node) -> &[u8] {
+        self.der
+            .get(node.byte_offset..node.byte_offset + node.byte_length)
+            .unwrap_or_default()
+    }
+
+    fn content(&self, node: &Asn1Node) -> &[u8] {
+        self.der
+            .get(node.content_offset..node.content_offset + node.length)
+            .unwrap_or_default()
+    }
+}
+
+fn same_tag(a: &Asn1Node, b: &Asn1Node) -> bool {
+    a.tag_class == b.tag_class
+        && a.tag_number == b.tag_number
+        && a.is_constructed == b.is_constructed
+}
+
+/// Compare two documents.
+///
+/// Children are aligned by position, except for SET and SEQUENCE OF, whose
+/// elements are aligned by content so reordered or inserted elements are not
+/// reported as changes to every following element. A SEQUENCE is treated as a
+/// SEQUENCE OF when all its elements on both sides share one tag that is either
+/// constructed or context-specific (e.g. the extension list or GeneralNames).
+pub fn diff_documents(
+    old: &Document,
+    new: &Document,
+) -> Result<Vec<Change>, Box<dyn std::error::Error>> {
+    let old_root = old.decode(None)?;
+    let new_root = new.decode(None)?;
+    let mut changes = Vec::new();
+    diff_children(
+        (&Side { der: old.der() }, &old_root),
+        (&Side { der: new.der() }, &new_root),
+        &mut changes,
+    );
+    Ok(changes)
+}
+
+/// Diff two PEM documents, returning the changes as JSON
+pub fn diff_internal(old_pem: &str, new_pem: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let old = Document::from_pem(old_pem).map_err(|e| format!("Old document: {}", e))?;
+    let new = Document::from_pem(new_pem).map_err(|e| format!("New document: {}", e))?;
+    let changes = diff_documents(&old, &new)?;
+    Ok(serde_json::to_string_pretty(&changes)?)
+}
+
+fn diff_nodes(old: (&Side, &Asn1Node), new: (&Side, &Asn1Node), changes: &mut Vec<Change>) {
+    let (old_side, old_node) = old;
+    let (new_side, new_node) = new;
+    if old_side.raw(old_node) == new_side.raw(new_node) {
+        return;
+    }
+
+    if !same_tag(old_node, new_node) {
+        changes.push(Change {
+            kind: ChangeKind::Retagged,
+            old: Some(NodeSide::of(old_node)),
+            new: Some(NodeSide::of(new_node)),
+        });
+        if old_node.is_constructed && new_node.is_constructed {
+            diff_children(old, new, changes);
+        }
+        return;
+    }
+
+    if old_node.is_constructed {
+        diff_children(old, new, changes);
+    } else if old_side.content(old_node) != new_side.content(new_node) {
+        changes.push(Change {
+            kind: ChangeKind::ValueChanged,
+            old: Some(NodeSide::of(old_node)),
+            new: Some(NodeSide::of(new_node)),
+        });
+    }
+}
+
+fn is_sequence_of(old: &Asn1Node, new: &Asn1Node) -> bool {
+    if !is_universal(old, 16) || !is_universal(new, 16) {
+        return false;
+    }
+    let mut elements = old.children.iter().chain(&new.children);
+    let Some(first) = elements.next() else {
+        return false;
+    };
+    (first.is_constructed || first.tag_class == "CONTEXT")
+        && elements.all(|element| same_tag(element, first))
+}
+
+fn diff_children(old: (&Side, &Asn1Node), new: (&Side, &Asn1Node), changes: &mut Vec<Change>) {
+    let (old_side, old_node) = old;
+    let (new_side, new_node) = new;
+    let old_children = &old_node.children;
+    let new_children = &new_node.children;
+
+    let pairs = if is_universal(old_node, 17) && is_universal(new_node, 17) {
+        align_unordered(old, new)
+    } else if is_sequence_of(old_node, new_node) {
+        align_ordered(old, new)
+    } else {
+        (0..old_children.len().max(new_children.len()))
+            .map(|i| {
+                (
+                    Some(i).filter(|&i| i < old_children.len()),
+                    Some(i).filter(|&i| i < new_children.len()),
+                )
+            })
+            .collect()
+    };
+
+    for pair in pairs {
+        match pair {
+            (Some(i), Some(j)) => diff_nodes(
+                (old_side, &old_children[i]),
+                (new_side, &new_children[j]),
+                changes,
+            ),
+            (Some(i), None) => changes.push(Change {
+                kind: ChangeKind::Removed,
+                old: Some(NodeSide::of(&old_children[i])),
+                new: None,
+            }),
+            (None, Some(j)) => changes.push(Change {
+                kind: ChangeKind::Added,
+                old: None,
+                new: Some(NodeSide::of(&new_children[j])),
+            }),
+            (None, None) => {}
+        }
+    }
+}
+
+/// Pair up elements that did not match exactly, in order; extras are added or removed
+fn pair_leftovers(old: &[usize], new: &[usize], pairs: &mut Vec<(Option<usize>, Option<usize>)>) {
+    for k in 0..old.len().max(new.len()) {
+        pairs.push((old.get(k).copied(), new.get(k).copied()));
+    }
+}
+
+/// Match SET elements with identical encodings regardless of order, then pair the rest
+fn align_unordered(
+    old: (&Side, &Asn1Node),
+    new: (&Side, &Asn1Node),
+) -> Vec<(Option<usize>, Option<usize>)> {
+    let (old_side, old_node) = old;
+    let (new_side, new_node) = new;
+    let mut unmatched_new: Vec<usize> = (0..new_node.children.len()).collect();
+    let mut unmatched_old = Vec::new();
+
+    for (i, old_child) in old_node.children.iter().enumerate() {
+        let raw = old_side.raw(old_child);
+        match unmatched_new
+            .iter()
+            .position(|&j| new_side.raw(&new_node.children[j]) == raw)
+        {
+            Some(k) => {
+                unmatched_new.remove(k);
+            }
+            None => unmatched_old.push(i),
+        }
+    }
+
+    let mut pairs = Vec::new();
+    pair_leftovers(&unmatched_old, &unmatched_new, &mut pairs);
+    pairs
+}
+
+/// Largest LCS table `align_ordered` builds; beyond it the differing middle of a
+/// SEQUENCE OF is paired by position
+const MAX_LCS_CELLS: usize = 1 << 20;
+
+/// Align SEQUENCE OF elements on their longest common subsequence of identical
+/// encodings, pairing the elements between matches by position
+fn align_ordered(
+    old: (&Side, &Asn1Node),
+    new: (&Side, &Asn1Node),
+) -> Vec<(Option<usize>, Option<usize>)> {
+    let (old_side, old_node) = old;
+    let (new_side, new_node) = new;
+    let old_raw: Vec<_> = old_node.children.iter().map(|c| old_side.raw(c)).collect();
+    let new_raw: Vec<_> = new_node.children.iter().map(|c| new_side.raw(c)).collect();
+
+    // Identical elements at either end never need pairing
+    let prefix = old_raw
+        .iter()
+        .zip(&new_raw)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_raw[prefix..]
+        .iter()
+        .rev()
+        .zip(new_raw[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_raw = &old_raw[prefix..old_raw.len() - suffix];
+    let new_raw = &new_raw[prefix..new_raw.len() - suffix];
+    let (n, m) = (old_raw.len(), new_raw.len());
+
+    let mut pairs = Vec::new();
+    if (n + 1).saturating_mul(m + 1) > MAX_LCS_CELLS {
+        let old: Vec<usize> = (prefix..prefix + n).collect();
+        let new: Vec<usize> = (prefix..prefix + m).collect();
+        pair_leftovers(&old, &new, &mut pairs);
+        return pairs;
+    }
+
+    // lcs[i][j] = length of the LCS of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_raw[i] == new_raw[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut unmatched_old, mut unmatched_new) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_raw[i] == new_raw[j] {
+            pair_leftovers(&unmatched_old, &unmatched_new, &mut pairs);
+            unmatched_old.clear();
+            unmatched_new.clear();
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            unmatched_old.push(prefix + i);
+            i += 1;
+        } else {
+            unmatched_new.push(prefix + j);
+            j += 1;
+        }
+    }
+    pair_leftovers(&unmatched_old, &unmatched_new, &mut pairs);
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::CERT_PEM;
+
+    fn diff(old: &[u8], new: &[u8]) -> Vec<Change> {
+        diff_documents(
+            &Document::from_der("TEST", old.to_vec()),
+            &Document::from_der("TEST", new.to_vec()),
+        )
+        .unwrap()
+    }
+
+    fn summary(changes: &[Change]) -> Vec<(ChangeKind, Option<String>, Option<String>)> {
+        changes
+            .iter()
+            .map(|c| {
+                (
+                    c.kind,
+                    c.old.as_ref().map(|side| side.path.clone()),
+                    c.new.as_ref().map(|side| side.path.clone()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identical_documents() {
+        let document = Document::from_pem(CERT_PEM).unwrap();
+        assert!(diff_documents(&document, &document).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_value_changed_and_retagged() {
+        // SEQUENCE { INTEGER 1, INTEGER 2 } -> SEQUENCE { INTEGER 1, [0] 2 }, then INTEGER 3
+        let old = [0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02];
+        let retagged = [0x30, 0x06, 0x02, 0x01, 0x01, 0x80, 0x01, 0x02];
+        let changed = [0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x03];
+
+        let changes = diff(&old, &retagged);
+        assert_eq!(
+            summary(&changes),
+            vec![(ChangeKind::Retagged, Some("0.1".into()), Some("0.1".into()))]
+        );
+
+        let changes = diff(&old, &changed);
+        assert_eq!(
+            summary(&changes),
+            vec![(
+                ChangeKind::ValueChanged,
+                Some("0.1".into()),
+                Some("0.1".into())
+            )]
+        );
+        let new = changes[0].new.as_ref().unwrap();
+        assert_eq!(
+            (new.byte_offset, new.byte_length, new.value.as_deref()),
+            (5, 3, Some("3"))
+        );
+    }
+
+    #[test]
+    fn test_positional_added_and_removed() {
+        let old = [0x30, 0x03, 0x02, 0x01, 0x01];
+        let new = [0x30, 0x05, 0x02, 0x01, 0x01, 0x05, 0x00];
+        assert_eq!(
+            summary(&diff(&old, &new)),
+            vec![(ChangeKind::Added, None, Some("0.1".into()))]
+        );
+        assert_eq!(
+            summary(&diff(&new, &old)),
+            vec![(ChangeKind::Removed, Some("0.1".into()), None)]
+        );
+    }
+
+    #[test]
+    fn test_set_aligned_by_content() {
+        // SET { INTEGER 1, INTEGER 2 } -> SET { INTEGER 2, INTEGER 1 } is unchanged
+        let old = [0x31, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02];
+        let reordered = [0x31, 0x06, 0x02, 0x01, 0x02, 0x02, 0x01, 0x01];
+        assert!(diff(&old, &reordered).is_empty());
+
+        let grown = [
+            0x31, 0x09, 0x02, 0x01, 0x03, 0x02, 0x01, 0x02, 0x02, 0x01, 0x01,
+        ];
+        assert_eq!(
+            summary(&diff(&old, &grown)),
+            vec![(ChangeKind::Added, None, Some("0.0".into()))]
+        );
+    }
+
+    #[test]
+    fn test_sequence_of_aligned_by_content() {
+        // SEQUENCE OF SEQUENCE { INTEGER }: inserting an element at the front only adds it
+        let old = [
+            0x30, 0x0A, 0x30, 0x03, 0x02, 0x01, 0x01, 0x30, 0x03, 0x02, 0x01, 0x02,
+        ];
+        let new = [
+            0x30, 0x0F, 0x30, 0x03, 0x02, 0x01, 0x09, 0x30, 0x03, 0x02, 0x01, 0x01, 0x30, 0x03,
+            0x02, 0x01, 0x02,
+        ];
+        assert_eq!(
+            summary(&diff(&old, &new)),
+            vec![(ChangeKind::Added, None, Some("0.0".into()))]
+        );
+
+        // Changing the middle element pairs it up instead of adding and removing
+        let changed = [
+            0x30, 0x0F, 0x30, 0x03, 0x02, 0x01, 0x09, 0x30, 0x03, 0x02, 0x01, 0x05, 0x30, 0x03,
+            0x02, 0x01, 0x02,
+        ];
+        assert_eq!(
+            summary(&diff(&new, &changed)),
+            vec![(
+                ChangeKind::ValueChanged,
+                Some("0.1.0".into()),
+                Some("0.1.0".into())
+            )]
+        );
+    }
+
+    /// SEQUENCE OF `[0] { INTEGER }` with one element per value
+    fn sequence_of(values: impl Iterator<Item = u16>) -> Vec<u8> {
+        let content: Vec<u8> = values
+            .flat_map(|v| [0xA0, 0x04, 0x02, 0x02, (v >> 8) as u8, v as u8])
+            .collect();
+        let mut der = vec![0x30, 0x83];
+        der.extend_from_slice(&(content.len() as u32).to_be_bytes()[1..]);
+        der.extend_from_slice(&content);
+        der
+    }
+
+    #[test]
+    fn test_long_sequence_of() {
+        // Only the differing middle is aligned: one element inserted among 20,000
+        let old = sequence_of(0..20_000);
+        let new = sequence_of((0..10_000).chain([0xFFFF]).chain(10_000..20_000));
+        assert_eq!(
+            summary(&diff(&old, &new)),
+            vec![(ChangeKind::Added, None, Some("0.10000".into()))]
+        );
+
+        // A middle too large for the LCS table is paired by position
+        let new = sequence_of((1..2000).chain([0]));
+        let changes = diff(&sequence_of(0..2000), &new);
+        assert_eq!(changes.len(), 2000);
+        assert!(changes.iter().all(|c| c.kind == ChangeKind::ValueChanged));
+    }
+}
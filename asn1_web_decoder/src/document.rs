@@ -0,0 +1,247 @@
+use crate::decoder::{decode_der_tree, decode_tlv_to_depth};
+use crate::path::{
+    assign_paths, byte_part, child_path, parent_path, parse_path, BytePart, OffsetLocation,
+};
+use crate::tlv::{Reader, Tlv};
+use crate::Asn1Node;
+
+/// A decoded document that keeps its DER so subtrees can be decoded on demand
+#[derive(Debug, Clone)]
+pub struct Document {
+    label: String,
+    der: Vec<u8>,
+}
+
+impl Document {
+    pub fn from_pem(pem_input: &str) -> Result<Document, Box<dyn std::error::Error>> {
+        let pem = pem::parse(pem_input)?;
+        let label = pem.tag().to_string();
+        Ok(Document::from_der(&label, pem.into_contents()))
+    }
+
+    pub fn from_der(label: &str, der: Vec<u8>) -> Document {
+        Document {
+            label: label.to_string(),
+            der,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    pub(crate) fn set_der(&mut self, der: Vec<u8>) {
+        self.der = der;
+    }
+
+    pub fn to_pem(&self) -> String {
+        pem::encode(&pem::Pem::new(self.label.clone(), self.der.clone()))
+    }
+
+    /// Decode the document; with `max_depth` set, constructed nodes at that depth
+    /// (top-level elements are depth 0) are left collapsed
+    pub fn decode(&self, max_depth: Option<usize>) -> Result<Asn1Node, Box<dyn std::error::Error>> {
+        decode_der_tree(&self.label, &self.der, max_depth)
+    }
+
+    /// Decode the subtree at `path` (e.g. "0.2"); the node itself is depth 0, so
+    /// `Some(1)` decodes its children and leaves theirs collapsed
+    pub fn expand(
+        &self,
+        path: &str,
+        max_depth: Option<usize>,
+    ) -> Result<Asn1Node, Box<dyn std::error::Error>> {
+        let indices = parse_path(path)?;
+        if indices.is_empty() {
+            return self.decode(max_depth);
+        }
+
+        let tlv = self
+            .tlv_at(&indices)
+            .ok_or_else(|| format!("No node at path '{}'", path))?;
+        let mut node = decode_tlv_to_depth(&tlv, max_depth)?;
+        assign_paths(&mut node, path.trim());
+        Ok(node)
+    }
+
+    /// Decode the complete subtree at `path`
+    pub fn node(&self, path: &str) -> Result<Asn1Node, Box<dyn std::error::Error>> {
+        self.expand(path, None)
+    }
+
+    /// Decode the complete subtree of the parent of the node at `path`
+    pub fn parent(&self, path: &str) -> Result<Asn1Node, Box<dyn std::error::Error>> {
+        self.raw_bytes(path)?;
+        let parent = parent_path(path).ok_or("The root node has no parent")?;
+        self.expand(&parent, None)
+    }
+
+    /// The original encoding (header, content and any end-of-contents octets) of the node at `path`
+    pub fn raw_bytes(&self, path: &str) -> Result<&[u8], Box<dyn std::error::Error>> {
+        let indices = parse_path(path)?;
+        if indices.is_empty() {
+            return Ok(&self.der);
+        }
+        let tlv = self
+            .tlv_at(&indices)
+            .ok_or_else(|| format!("No node at path '{}'", path))?;
+        Ok(tlv.raw())
+    }
+
+    /// Find the innermost element containing `offset` by walking the raw encoding,
+    /// so it works regardless of how much of the tree has been decoded. Fails if
+    /// an element on the way to `offset` does not parse.
+    pub fn resolve_offset(
+        &self,
+        offset: usize,
+    ) -> Result<Option<OffsetLocation>, Box<dyn std::error::Error>> {
+        let contains =
+            |tlv: &Tlv| offset >= tlv.offset() && offset < tlv.offset() + tlv.raw().len();
+        let mut path = String::new();
+        let mut reader = Reader::new(&self.der);
+        let mut index = 0;
+
+        while let Some(tlv) = reader.next() {
+            let tlv = tlv?;
+            if !contains(&tlv) {
+                index += 1;
+                continue;
+            }
+
+            path = child_path(&path, index);
+            let part = byte_part(
+                offset - tlv.offset(),
+                tlv.identifier_octets().len(),
+                tlv.header().len(),
+                tlv.content().len(),
+            );
+            if part == BytePart::Content && tlv.is_constructed() {
+                let mut inside_child = false;
+                for child in tlv.children() {
+                    if contains(&child?) {
+                        inside_child = true;
+                        break;
+                    }
+                }
+                if inside_child {
+                    reader = tlv.children();
+                    index = 0;
+                    continue;
+                }
+            }
+
+            return Ok(Some(OffsetLocation {
+                path,
+                part,
+                byte_offset: tlv.offset(),
+                byte_length: tlv.raw().len(),
+            }));
+        }
+
+        Ok(None)
+    }
+
+    /// Walk the raw encoding to the element at the given child indices
+    fn tlv_at(&self, indices: &[usize]) -> Option<Tlv<'_>> {
+        let (&first, rest) = indices.split_first()?;
+        let mut tlv = Reader::new(&self.der).nth(first)?.ok()?;
+        for &index in rest {
+            tlv = tlv.children().nth(index)?.ok()?;
+        }
+        Some(tlv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode_pem_tree;
+    use crate::path::node_at_path;
+    use crate::test_data::CERT_PEM;
+
+    #[test]
+    fn test_decode_to_depth() {
+        let document = Document::from_pem(CERT_PEM).unwrap();
+        let root = document.decode(Some(1)).unwrap();
+
+        let certificate = &root.children[0];
+        assert!(!certificate.collapsed);
+        assert_eq!(certificate.children.len(), 3);
+
+        let tbs = &certificate.children[0];
+        assert!(tbs.collapsed);
+        assert!(tbs.children.is_empty());
+
+        // Primitives are never collapsed
+        let signature = &certificate.children[2];
+        assert!(!signature.collapsed);
+        assert!(signature.value.is_some());
+    }
+
+    #[test]
+    fn test_expand_matches_full_decode() {
+        let document = Document::from_pem(CERT_PEM).unwrap();
+        let full = decode_pem_tree(CERT_PEM).unwrap();
+
+        let expanded = document.expand("0.0", None).unwrap();
+        assert_eq!(&expanded, node_at_path(&full, &[0, 0]).unwrap());
+
+        let shallow = document.expand("0.0", Some(1)).unwrap();
+        assert!(shallow
+            .children
+            .iter()
+            .filter(|c| c.is_constructed)
+            .all(|c| c.collapsed));
+        assert_eq!(shallow.children.len(), expanded.children.len());
+        assert!(document.expand("0.0", Some(0)).unwrap().collapsed);
+
+        assert!(document.expand("0.9", None).is_err());
+        assert!(document.expand("x", None).is_err());
+    }
+
+    #[test]
+    fn test_lookup_by_path() {
+        let document = Document::from_pem(CERT_PEM).unwrap();
+
+        let serial = document.node("0.0.1").unwrap();
+        assert_eq!(serial.path, "0.0.1");
+        assert_eq!(serial.tag_number, 2);
+
+        let raw = document.raw_bytes("0.0.1").unwrap();
+        assert_eq!(raw.len(), serial.byte_length);
+        assert_eq!(
+            raw,
+            &document.der()[serial.byte_offset..serial.byte_offset + serial.byte_length]
+        );
+
+        let tbs = document.parent("0.0.1").unwrap();
+        assert_eq!(tbs.path, "0.0");
+        assert_eq!(tbs.children[1], serial);
+
+        assert_eq!(document.raw_bytes("").unwrap(), document.der());
+        assert!(document.parent("").is_err());
+        assert!(document.parent("0.0.99").is_err());
+    }
+
+    #[test]
+    fn test_resolve_offset_matches_tree() {
+        let document = Document::from_pem(CERT_PEM).unwrap();
+        let root = document.decode(None).unwrap();
+
+        for offset in 0..document.der().len() + 1 {
+            assert_eq!(
+                document.resolve_offset(offset).unwrap(),
+                crate::path::resolve_offset(&root, offset)
+            );
+        }
+
+        // SEQUENCE { INTEGER 1 } followed by a truncated element
+        let document = Document::from_der("TEST", vec![0x30, 0x03, 0x02, 0x01, 0x01, 0x04, 0x05]);
+        assert_eq!(document.resolve_offset(4).unwrap().unwrap().path, "0.0");
+        assert!(document.resolve_offset(6).is_err());
+    }
+}
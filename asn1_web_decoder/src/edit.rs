@@ -0,0 +1,604 @@
+//! Editing operations on a [`Document`], addressed by node path.
+//!
+//! Each operation builds the new encoding of the element it changes and splices
+//! it into the DER, rebuilding the headers of all ancestors so their lengths stay
+//! correct. Everything outside the ancestor chain is copied unchanged. Definite
+//! lengths on the chain are rewritten in minimal DER form; indefinite-length
+//! ancestors stay indefinite.
+
+use crate::document::Document;
+use crate::encoder::{
+    encode_asn1_tree, encode_length, encode_tag, encode_untyped_value, encode_value,
+};
+use crate::path::{child_path, parse_path};
+use crate::tlv::{Reader, TagClass, Tlv};
+use crate::{Asn1Node, LengthForm};
+use serde::Serialize;
+use std::error::Error;
+
+/// Position of one element after an edit
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NodeRange {
+    pub path: String,
+    pub byte_offset: usize,
+    pub header_length: usize,
+    pub byte_length: usize,
+}
+
+/// Outcome of an edit
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Edit {
+    /// Path of the node the operation changed or created; None after a delete
+    pub path: Option<String>,
+    /// New positions of that node (after a delete, its former parent) and its
+    /// ancestors, outermost first
+    pub nodes: Vec<NodeRange>,
+    /// Change in the size of the whole document
+    pub size_delta: isize,
+}
+
+/// An element on the path being edited, copied out of the DER so the buffer can be replaced
+#[derive(Debug, Clone)]
+struct Span {
+    tag_class: TagClass,
+    tag_number: u32,
+    constructed: bool,
+    offset: usize,
+    identifier: Vec<u8>,
+    content_offset: usize,
+    content_end: usize,
+    end: usize,
+    indefinite: bool,
+}
+
+impl Span {
+    fn of(tlv: &Tlv) -> Span {
+        let content_offset = tlv.content_offset();
+        Span {
+            tag_class: tlv.tag().class,
+            tag_number: tlv.tag().number,
+            constructed: tlv.is_constructed(),
+            offset: tlv.offset(),
+            identifier: tlv.identifier_octets().to_vec(),
+            content_offset,
+            content_end: content_offset + tlv.content().len(),
+            end: tlv.offset() + tlv.raw().len(),
+            indefinite: tlv.length_form() == LengthForm::Indefinite,
+        }
+    }
+}
+
+/// The elements from the top level down to the node at `indices`
+fn spans(der: &[u8], indices: &[usize]) -> Result<Vec<Span>, Box<dyn Error>> {
+    let mut spans = Vec::new();
+    let mut reader = Reader::new(der);
+    for (depth, &index) in indices.iter().enumerate() {
+        let tlv = reader.nth(index).transpose()?.ok_or_else(|| {
+            let path: Vec<String> = indices[..=depth].iter().map(|i| i.to_string()).collect();
+            format!("No node at path '{}'", path.join("."))
+        })?;
+        spans.push(Span::of(&tlv));
+        reader = tlv.children();
+    }
+    Ok(spans)
+}
+
+/// Offsets of the children of the element at the end of `spans` (the top level if empty)
+fn child_offsets(der: &[u8], spans: &[Span]) -> Result<Vec<usize>, Box<dyn Error>> {
+    let reader = match spans.last() {
+        Some(parent) => Reader::with_offset(
+            &der[parent.content_offset..parent.content_end],
+            parent.content_offset,
+        ),
+        None => Reader::new(der),
+    };
+    reader.map(|tlv| Ok(tlv?.offset())).collect()
+}
+
+fn path_of(indices: &[usize]) -> String {
+    indices
+        .iter()
+        .fold(String::new(), |path, &index| child_path(&path, index))
+}
+
+fn element(identifier: &[u8], content: &[u8], indefinite: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut element = identifier.to_vec();
+    if indefinite {
+        element.push(0x80);
+        element.extend_from_slice(content);
+        element.extend_from_slice(&[0x00, 0x00]);
+    } else {
+        element.extend_from_slice(&encode_length(content.len())?);
+        element.extend_from_slice(content);
+    }
+    Ok(element)
+}
+
+/// Add `delta` to a big-endian two's complement integer, returning the minimal encoding
+fn add_signed(value: &[u8], delta: i64) -> Vec<u8> {
+    let width = value.len().max(8) + 1;
+    let sign_extend = |bytes: &[u8], negative: bool| {
+        let mut extended = vec![if negative { 0xFF } else { 0x00 }; width - bytes.len()];
+        extended.extend_from_slice(bytes);
+        extended
+    };
+    let a = sign_extend(value, value.first().is_some_and(|b| b & 0x80 != 0));
+    let b = sign_extend(&delta.to_be_bytes(), delta < 0);
+
+    let mut sum = vec![0u8; width];
+    let mut carry = 0u16;
+    for i in (0..width).rev() {
+        let total = a[i] as u16 + b[i] as u16 + carry;
+        sum[i] = total as u8;
+        carry = total >> 8;
+    }
+
+    let redundant = sum
+        .windows(2)
+        .take_while(|pair| {
+            (pair[0] == 0x00 && pair[1] & 0x80 == 0) || (pair[0] == 0xFF && pair[1] & 0x80 != 0)
+        })
+        .count();
+    sum.split_off(redundant)
+}
+
+impl Document {
+    /// Replace `der[start..end]`, which lies inside the last of `ancestors`, and
+    /// rebuild every ancestor around it
+    fn splice(
+        &mut self,
+        ancestors: &[Span],
+        start: usize,
+        end: usize,
+        replacement: Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        let der = self.der();
+        let (mut start, mut end, mut bytes) = (start, end, replacement);
+        for span in ancestors.iter().rev() {
+            let mut content = der[span.content_offset..start].to_vec();
+            content.extend_from_slice(&bytes);
+            content.extend_from_slice(&der[end..span.content_end]);
+            bytes = element(&span.identifier, &content, span.indefinite)?;
+            start = span.offset;
+            end = span.end;
+        }
+
+        let mut new_der = der[..start].to_vec();
+        new_der.extend_from_slice(&bytes);
+        new_der.extend_from_slice(&der[end..]);
+        self.set_der(new_der);
+        Ok(())
+    }
+
+    fn finish_edit(
+        &self,
+        indices: &[usize],
+        old_size: usize,
+        changed: bool,
+    ) -> Result<Edit, Box<dyn Error>> {
+        let nodes = spans(self.der(), indices)?
+            .iter()
+            .enumerate()
+            .map(|(depth, span)| NodeRange {
+                path: path_of(&indices[..=depth]),
+                byte_offset: span.offset,
+                header_length: span.content_offset - span.offset,
+                byte_length: span.end - span.offset,
+            })
+            .collect();
+        Ok(Edit {
+            path: changed.then(|| path_of(indices)),
+            nodes,
+            size_delta: self.der().len() as isize - old_size as isize,
+        })
+    }
+
+    /// Replace the element at `indices` with `replacement`
+    fn replace_element(
+        &mut self,
+        indices: &[usize],
+        replacement: Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        let spans = spans(self.der(), indices)?;
+        let (target, ancestors) = spans.split_last().ok_or("The root node cannot be edited")?;
+        self.splice(ancestors, target.offset, target.end, replacement)
+    }
+
+    fn insert_raw(
+        &mut self,
+        parent: &[usize],
+        index: usize,
+        bytes: Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        let spans = spans(self.der(), parent)?;
+        if spans.last().is_some_and(|span| !span.constructed) {
+            return Err(format!(
+                "Node at path '{}' is primitive and cannot have children",
+                path_of(parent)
+            )
+            .into());
+        }
+
+        let children = child_offsets(self.der(), &spans)?;
+        let position = match (children.get(index), spans.last()) {
+            (Some(&offset), _) => offset,
+            (None, _) if index > children.len() => {
+                return Err(format!(
+                    "Cannot insert at index {}: node at path '{}' has {} children",
+                    index,
+                    path_of(parent),
+                    children.len()
+                )
+                .into())
+            }
+            (None, Some(parent)) => parent.content_end,
+            (None, None) => self.der().len(),
+        };
+        self.splice(&spans, position, position, bytes)
+    }
+
+    /// Set the value of the primitive node at `path`, using the same value syntax
+    /// as the JSON encoder (e.g. "42" or "0x2A" for an INTEGER, an OID in dotted form)
+    pub fn set_value(&mut self, path: &str, value: &str) -> Result<Edit, Box<dyn Error>> {
+        let indices = parse_path(path)?;
+        let old_size = self.der().len();
+        let spans = spans(self.der(), &indices)?;
+        let target = spans.last().ok_or("The root node has no value")?;
+        if target.constructed {
+            return Err(format!(
+                "Node at path '{}' is constructed and has no value of its own",
+                path
+            )
+            .into());
+        }
+
+        let content = if target.tag_class == TagClass::Universal {
+            encode_value(target.tag_number, value)?
+        } else {
+            encode_untyped_value(value)?
+        };
+        self.replace_element(&indices, element(&target.identifier, &content, false)?)?;
+        self.finish_edit(&indices, old_size, true)
+    }
+
+    /// Add `delta` to the INTEGER or ENUMERATED at `path`, whatever its size
+    /// (e.g. to bump a 20-byte serial number)
+    pub fn increment(&mut self, path: &str, delta: i64) -> Result<Edit, Box<dyn Error>> {
+        let indices = parse_path(path)?;
+        let old_size = self.der().len();
+        let spans = spans(self.der(), &indices)?;
+        let target = spans.last().ok_or("The root node has no value")?;
+        if target.tag_class != TagClass::Universal
+            || !matches!(target.tag_number, 2 | 10)
+            || target.constructed
+        {
+            return Err(format!("Node at path '{}' is not an INTEGER or ENUMERATED", path).into());
+        }
+
+        let content = add_signed(
+            &self.der()[target.content_offset..target.content_end],
+            delta,
+        );
+        self.replace_element(&indices, element(&target.identifier, &content, false)?)?;
+        self.finish_edit(&indices, old_size, true)
+    }
+
+    /// Number of children of the node at `path` ("" for the number of top-level elements)
+    pub fn child_count(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        let spans = spans(self.der(), &parse_path(path)?)?;
+        Ok(child_offsets(self.der(), &spans)?.len())
+    }
+
+    /// Change the class and tag number of the node at `path`, keeping its content
+    pub fn set_tag(
+        &mut self,
+        path: &str,
+        tag_class: &str,
+        tag_number: u32,
+    ) -> Result<Edit, Box<dyn Error>> {
+        let indices = parse_path(path)?;
+        let old_size = self.der().len();
+        let spans = spans(self.der(), &indices)?;
+        let target = spans.last().ok_or("The root node has no tag")?;
+
+        let mut bytes = encode_tag(tag_class, tag_number, target.constructed)?;
+        bytes.extend_from_slice(&self.der()[target.offset + target.identifier.len()..target.end]);
+        self.replace_element(&indices, bytes)?;
+        self.finish_edit(&indices, old_size, true)
+    }
+
+    /// Wrap the node at `path` in a new constructed element, e.g. an explicit
+    /// `[CONTEXT 0]` tag; the wrapper takes the node's path and the node moves to `path.0`
+    pub fn wrap(
+        &mut self,
+        path: &str,
+        tag_class: &str,
+        tag_number: u32,
+    ) -> Result<Edit, Box<dyn Error>> {
+        let indices = parse_path(path)?;
+        let old_size = self.der().len();
+        let spans = spans(self.der(), &indices)?;
+        let target = spans.last().ok_or("The root node cannot be wrapped")?;
+
+        let identifier = encode_tag(tag_class, tag_number, true)?;
+        let wrapped = element(&identifier, &self.der()[target.offset..target.end], false)?;
+        self.replace_element(&indices, wrapped)?;
+        self.finish_edit(&indices, old_size, true)
+    }
+
+    /// Remove the node at `path`
+    pub fn delete(&mut self, path: &str) -> Result<Edit, Box<dyn Error>> {
+        let indices = parse_path(path)?;
+        let old_size = self.der().len();
+        self.replace_element(&indices, Vec::new())?;
+        self.finish_edit(&indices[..indices.len() - 1], old_size, false)
+    }
+
+    /// Encode `node` and insert it as child `index` of the node at `parent_path`
+    /// ("" inserts a top-level element); `index` may equal the number of children to append
+    pub fn insert_child(
+        &mut self,
+        parent_path: &str,
+        index: usize,
+        node: &Asn1Node,
+    ) -> Result<Edit, Box<dyn Error>> {
+        let parent = parse_path(parent_path)?;
+        let old_size = self.der().len();
+        self.insert_raw(&parent, index, encode_asn1_tree(node)?)?;
+
+        let mut indices = parent;
+        indices.push(index);
+        self.finish_edit(&indices, old_size, true)
+    }
+
+    /// Move the node at `path` to child `index` of `new_parent_path`, keeping its
+    /// encoding. Both the destination parent and `index` refer to the tree before
+    /// the move: the node lands before the element now at `index`, or last when
+    /// `index` is the parent's child count.
+    pub fn move_node(
+        &mut self,
+        path: &str,
+        new_parent_path: &str,
+        index: usize,
+    ) -> Result<Edit, Box<dyn Error>> {
+        let indices = parse_path(path)?;
+        let mut parent = parse_path(new_parent_path)?;
+        if indices.is_empty() {
+            return Err("The root node cannot be moved".into());
+        }
+        if parent.starts_with(&indices) {
+            return Err(format!("Cannot move node '{}' into itself", path).into());
+        }
+        // Positions after the node move up by one once it is removed, both for a
+        // destination inside a later sibling and for a later index in the same parent
+        let depth = indices.len() - 1;
+        let mut index = index;
+        if parent.len() > depth
+            && parent.starts_with(&indices[..depth])
+            && parent[depth] > indices[depth]
+        {
+            parent[depth] -= 1;
+        } else if parent == indices[..depth] && index > indices[depth] {
+            index -= 1;
+        }
+
+        let old_size = self.der().len();
+        let bytes = self.raw_bytes(path)?.to_vec();
+        let original = self.der().to_vec();
+        self.replace_element(&indices, Vec::new())?;
+        if let Err(e) = self.insert_raw(&parent, index, bytes) {
+            self.set_der(original);
+            return Err(e);
+        }
+
+        let mut destination = parent;
+        destination.push(index);
+        self.finish_edit(&destination, old_size, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode_der_tree;
+    use crate::test_data::CERT_PEM;
+
+    // SEQUENCE { INTEGER 1, SEQUENCE { NULL } }
+    const DER: [u8; 9] = [0x30, 0x07, 0x02, 0x01, 0x01, 0x30, 0x02, 0x05, 0x00];
+
+    fn document() -> Document {
+        Document::from_der("TEST", DER.to_vec())
+    }
+
+    #[test]
+    fn test_set_value_fixes_ancestor_lengths() {
+        let mut document = document();
+        let edit = document.set_value("0.0", "256").unwrap();
+        assert_eq!(
+            document.der(),
+            [0x30, 0x08, 0x02, 0x02, 0x01, 0x00, 0x30, 0x02, 0x05, 0x00]
+        );
+        assert_eq!(edit.path.as_deref(), Some("0.0"));
+        assert_eq!(edit.size_delta, 1);
+        assert_eq!(
+            edit.nodes,
+            vec![
+                NodeRange {
+                    path: "0".into(),
+                    byte_offset: 0,
+                    header_length: 2,
+                    byte_length: 10
+                },
+                NodeRange {
+                    path: "0.0".into(),
+                    byte_offset: 2,
+                    header_length: 2,
+                    byte_length: 4
+                },
+            ]
+        );
+
+        assert!(document.set_value("0.1", "1").is_err());
+        assert!(document.set_value("0.0", "not a number").is_err());
+    }
+
+    #[test]
+    fn test_length_crosses_into_long_form() {
+        let mut document = Document::from_der("TEST", vec![0x30, 0x03, 0x02, 0x01, 0x00]);
+        document
+            .set_value("0.0", &format!("0x{}", "7F".repeat(200)))
+            .unwrap();
+        let root = decode_der_tree("TEST", document.der(), None).unwrap();
+        assert_eq!(root.children[0].length_form, Some(LengthForm::Long));
+        assert_eq!(root.children[0].children[0].length, 200);
+    }
+
+    #[test]
+    fn test_insert_delete_and_move() {
+        let mut document = document();
+        let node = Asn1Node {
+            tag_class: "UNIVERSAL".to_string(),
+            tag_number: 1,
+            value: Some("true".to_string()),
+            ..Default::default()
+        };
+        document.insert_child("0.1", 1, &node).unwrap();
+        assert_eq!(
+            document.der(),
+            [0x30, 0x0A, 0x02, 0x01, 0x01, 0x30, 0x05, 0x05, 0x00, 0x01, 0x01, 0xFF]
+        );
+        assert!(document.insert_child("0.1", 5, &node).is_err());
+        assert!(document.insert_child("0.0", 0, &node).is_err());
+
+        let edit = document.move_node("0.1.1", "0", 0).unwrap();
+        assert_eq!(
+            document.der(),
+            [0x30, 0x0A, 0x01, 0x01, 0xFF, 0x02, 0x01, 0x01, 0x30, 0x02, 0x05, 0x00]
+        );
+        assert_eq!(edit.path.as_deref(), Some("0.0"));
+        assert_eq!(edit.size_delta, 0);
+        assert!(document.move_node("0", "0.2", 0).is_err());
+
+        let edit = document.delete("0.0").unwrap();
+        assert_eq!(document.der(), DER);
+        assert_eq!(edit.path, None);
+        assert_eq!(edit.nodes.len(), 1);
+        assert_eq!(edit.size_delta, -3);
+    }
+
+    #[test]
+    fn test_wrap_and_set_tag() {
+        let mut document = document();
+        document.wrap("0.0", "CONTEXT", 0).unwrap();
+        assert_eq!(
+            document.der(),
+            [0x30, 0x09, 0xA0, 0x03, 0x02, 0x01, 0x01, 0x30, 0x02, 0x05, 0x00]
+        );
+
+        document.set_tag("0.1", "APPLICATION", 5).unwrap();
+        assert_eq!(
+            document.der(),
+            [0x30, 0x09, 0xA0, 0x03, 0x02, 0x01, 0x01, 0x65, 0x02, 0x05, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_wrap_and_set_tag_with_high_tag_numbers() {
+        let mut document = document();
+        document.wrap("0.0", "CONTEXT", 200).unwrap();
+        assert_eq!(
+            document.der(),
+            [0x30, 0x0B, 0xBF, 0x81, 0x48, 0x03, 0x02, 0x01, 0x01, 0x30, 0x02, 0x05, 0x00]
+        );
+
+        document.set_tag("0.1", "PRIVATE", 31).unwrap();
+        assert_eq!(
+            document.der(),
+            [0x30, 0x0C, 0xBF, 0x81, 0x48, 0x03, 0x02, 0x01, 0x01, 0xFF, 0x1F, 0x02, 0x05, 0x00]
+        );
+        let root = decode_der_tree("TEST", document.der(), None).unwrap();
+        assert_eq!(root.children[0].children[0].tag_number, 200);
+        assert_eq!(root.children[0].children[1].tag_number, 31);
+    }
+
+    #[test]
+    fn test_move_node_into_later_sibling() {
+        let mut document = document();
+        let edit = document.move_node("0.0", "0.1", 1).unwrap();
+        // SEQUENCE { SEQUENCE { NULL, INTEGER 1 } }
+        assert_eq!(
+            document.der(),
+            [0x30, 0x07, 0x30, 0x05, 0x05, 0x00, 0x02, 0x01, 0x01]
+        );
+        assert_eq!(edit.path.as_deref(), Some("0.0.1"));
+    }
+
+    #[test]
+    fn test_move_node_forward_within_parent() {
+        // SEQUENCE { INTEGER 1, INTEGER 2, INTEGER 3 }
+        let der = vec![
+            0x30, 0x09, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02, 0x02, 0x01, 0x03,
+        ];
+        let mut document = Document::from_der("TEST", der.clone());
+        let edit = document.move_node("0.0", "0", 3).unwrap();
+        assert_eq!(
+            document.der(),
+            [0x30, 0x09, 0x02, 0x01, 0x02, 0x02, 0x01, 0x03, 0x02, 0x01, 0x01]
+        );
+        assert_eq!(edit.path.as_deref(), Some("0.2"));
+
+        // Before the element now at index 2
+        let mut document = Document::from_der("TEST", der.clone());
+        document.move_node("0.0", "0", 2).unwrap();
+        assert_eq!(
+            document.der(),
+            [0x30, 0x09, 0x02, 0x01, 0x02, 0x02, 0x01, 0x01, 0x02, 0x01, 0x03]
+        );
+
+        // Moving a node back keeps the same meaning
+        let mut document = Document::from_der("TEST", der);
+        document.move_node("0.2", "0", 0).unwrap();
+        assert_eq!(
+            document.der(),
+            [0x30, 0x09, 0x02, 0x01, 0x03, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02]
+        );
+    }
+
+    #[test]
+    fn test_indefinite_ancestor_stays_indefinite() {
+        // [0] (indefinite) { INTEGER 1 } EOC
+        let mut document =
+            Document::from_der("TEST", vec![0xA0, 0x80, 0x02, 0x01, 0x01, 0x00, 0x00]);
+        document.set_value("0.0", "2").unwrap();
+        assert_eq!(document.der(), [0xA0, 0x80, 0x02, 0x01, 0x02, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_edit_outside_signed_part_keeps_tbs_bytes() {
+        let mut document = Document::from_pem(CERT_PEM).unwrap();
+        let tbs = document.raw_bytes("0.0").unwrap().to_vec();
+        document.set_value("0.1.0", "1.2.840.10045.4.3.3").unwrap();
+        assert_eq!(document.raw_bytes("0.0").unwrap(), tbs.as_slice());
+        assert!(document.decode(None).is_ok());
+    }
+
+    #[test]
+    fn test_increment() {
+        assert_eq!(add_signed(&[0x01], 1), [0x02]);
+        assert_eq!(add_signed(&[0x7F], 1), [0x00, 0x80]);
+        assert_eq!(add_signed(&[0x00, 0x80], -1), [0x7F]);
+        assert_eq!(add_signed(&[0x00], -1), [0xFF]);
+        assert_eq!(add_signed(&[0xFF; 20], 1), [0x00]);
+        assert_eq!(add_signed(&[0x7F; 20], 1)[..2], [0x7F, 0x7F]);
+
+        let mut document = document();
+        document.increment("0.0", 255).unwrap();
+        assert_eq!(
+            document.der(),
+            [0x30, 0x08, 0x02, 0x02, 0x01, 0x00, 0x30, 0x02, 0x05, 0x00]
+        );
+        assert!(document.increment("0.1", 1).is_err());
+        assert_eq!(document.child_count("0").unwrap(), 2);
+        assert_eq!(document.child_count("").unwrap(), 1);
+    }
+}
@@ -1,3 +1,9 @@
+use crate::decoder::{decode_tlv_to_depth, decode_value, to_hex};
+use crate::node::is_universal;
+use crate::path::{assign_paths, parse_path};
+use crate::tlv::Tlv;
+use crate::{LengthForm, Profile, RawOverrides};
+use crate::Asn1Node;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
@@ -8,6 +14,72 @@ pub enum EncodeError {
     InvalidLength(String),
     InvalidTag(String),
     UnsupportedType(String),
+    /// Every error found in a tree, each at the node that caused it, in document order
+    Nodes(Vec<NodeError>),
+}
+
+/// An encoding error at one node of the tree
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NodeError {
+    pub path: String,
+    pub label: String,
+    pub tag_class: String,
+    pub tag_number: u32,
+    /// Node field that could not be encoded, e.g. "value", "tag_number" or "raw.content_hex"
+    pub field: &'static str,
+    /// "invalid_value", "invalid_length", "invalid_tag" or "unsupported_type"
+    pub kind: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.path.is_empty() { "root" } else { self.path.as_str() };
+        write!(f, "{} at {} ({}), field {}", self.message, path, self.label, self.field)
+    }
+}
+
+impl EncodeError {
+    /// Attach the node and field an error was found at; errors that are already
+    /// located keep their own location
+    fn at(self, node: &Asn1Node, field: &'static str) -> EncodeError {
+        EncodeError::Nodes(self.into_node_errors(node, field))
+    }
+
+    fn into_node_errors(self, node: &Asn1Node, field: &'static str) -> Vec<NodeError> {
+        let (kind, message) = match self {
+            EncodeError::InvalidValue(msg) => ("invalid_value", msg),
+            EncodeError::InvalidLength(msg) => ("invalid_length", msg),
+            EncodeError::InvalidTag(msg) => ("invalid_tag", msg),
+            EncodeError::UnsupportedType(msg) => ("unsupported_type", msg),
+            EncodeError::Nodes(errors) => return errors,
+        };
+        vec![NodeError {
+            path: node.path.clone(),
+            label: node.label.clone(),
+            tag_class: node.tag_class.clone(),
+            tag_number: node.tag_number,
+            field,
+            kind,
+            message,
+        }]
+    }
+
+    /// The located errors, empty for an error that is not tied to a node
+    pub fn node_errors(&self) -> &[NodeError] {
+        match self {
+            EncodeError::Nodes(errors) => errors,
+            _ => &[],
+        }
+    }
+}
+
+/// Field blamed for an `encode_tag` error
+fn tag_field(error: &EncodeError) -> &'static str {
+    match error {
+        EncodeError::InvalidTag(_) => "tag_class",
+        _ => "tag_number",
+    }
 }
 
 impl fmt::Display for EncodeError {
@@ -17,50 +89,372 @@ impl fmt::Display for EncodeError {
             EncodeError::InvalidLength(msg) => write!(f, "Invalid length: {}", msg),
             EncodeError::InvalidTag(msg) => write!(f, "Invalid tag: {}", msg),
             EncodeError::UnsupportedType(msg) => write!(f, "Unsupported type: {}", msg),
+            EncodeError::Nodes(errors) => {
+                let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", messages.join("; "))
+            }
         }
     }
 }
 
 impl Error for EncodeError {}
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Asn1Node {
+/// Encode an ASN.1 node tree back to DER bytes
+///
+/// A PEM pseudo-root encodes as the concatenation of its children. When the root
+/// carries the original DER in `source_hex`, every node whose tag and content are
+/// unchanged is emitted byte-for-byte from the source, so untouched subtrees keep
+/// their exact encoding (and signatures over them stay valid).
+pub fn encode_asn1_tree(node: &Asn1Node) -> Result<Vec<u8>, EncodeError> {
+    Ok(encode_asn1_tree_with_options(node, &EncodeOptions::default())?.der)
+}
+
+/// Options for `encode_asn1_tree_with_options`
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EncodeOptions {
+    /// Emit canonical DER: sort SET elements, minimize INTEGER and length octets,
+    /// encode TRUE as 0xFF, clear unused BIT STRING bits and drop trailing zero
+    /// bits from nodes marked `named_bits`. Original encodings are not reused.
+    pub canonical: bool,
+    /// Length and string form for nodes without a `profile` of their own
+    pub profile: Profile,
+}
+
+/// A change made to reach canonical DER
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Normalization {
+    pub path: String,
     pub label: String,
-    pub tag_class: String,
-    pub tag_number: u32,
-    pub is_constructed: bool,
-    pub byte_offset: usize,
-    pub byte_length: usize,
-    pub length: usize,
-    pub value: Option<String>,
-    pub children: Option<Vec<Asn1Node>>,
+    /// "set_order", "integer", "boolean", "bit_string", "named_bits" or "length"
+    pub rule: &'static str,
+    pub detail: String,
 }
 
-/// Encode an ASN.1 node tree back to DER bytes
-pub fn encode_asn1_tree(node: &Asn1Node) -> Result<Vec<u8>, EncodeError> {
-    let mut result = Vec::new();
+#[derive(Debug, Clone)]
+pub struct Encoded {
+    pub der: Vec<u8>,
+    pub normalizations: Vec<Normalization>,
+}
 
-    // Encode tag
-    let tag_byte = encode_tag(node.tag_class.as_str(), node.tag_number, node.is_constructed)?;
-    result.push(tag_byte);
-
-    // Get content bytes
-    let content = if node.is_constructed && node.children.is_some() {
-        // Encode all children
-        let mut child_bytes = Vec::new();
-        for child in node.children.as_ref().unwrap() {
-            child_bytes.extend_from_slice(&encode_asn1_tree(child)?);
-        }
-        child_bytes
-    } else if let Some(ref value) = node.value {
-        // Encode primitive value
-        encode_value(node.tag_number, value)?
+/// State threaded through one encoding
+struct Context<'a> {
+    source: Option<&'a [u8]>,
+    /// Profile in effect for the node being encoded
+    profile: Profile,
+    options: EncodeOptions,
+    normalizations: Vec<Normalization>,
+    /// Errors in subtrees that have already been reported, so encoding can go on
+    errors: Vec<NodeError>,
+}
+
+impl<'a> Context<'a> {
+    fn new(source: Option<&'a [u8]>, options: EncodeOptions) -> Context<'a> {
+        Context { source, profile: options.profile, options, normalizations: Vec::new(), errors: Vec::new() }
+    }
+
+    fn report(&mut self, node: &Asn1Node, rule: &'static str, detail: String) {
+        self.normalizations.push(Normalization {
+            path: node.path.clone(),
+            label: node.label.clone(),
+            rule,
+            detail,
+        });
+    }
+}
+
+/// Encode a node tree with the given options, reporting any normalizations.
+/// On failure the error is `EncodeError::Nodes` with every error in the tree.
+pub fn encode_asn1_tree_with_options(node: &Asn1Node, options: &EncodeOptions) -> Result<Encoded, EncodeError> {
+    let source = match node.source_hex {
+        Some(ref hex) => Some(hex_to_bytes(hex).map_err(|e| e.at(node, "source_hex"))?),
+        None => None,
+    };
+    let mut ctx = Context::new(source.as_deref(), options.clone());
+    let der = encode_tree(node, &mut ctx)?;
+    Ok(Encoded { der, normalizations: ctx.normalizations })
+}
+
+/// Encode a whole tree, gathering the errors of every subtree that failed
+fn encode_tree(node: &Asn1Node, ctx: &mut Context) -> Result<Vec<u8>, EncodeError> {
+    let result = encode_node(node, ctx);
+    let mut errors = std::mem::take(&mut ctx.errors);
+    match result {
+        Ok(der) if errors.is_empty() => return Ok(der),
+        Ok(_) => {}
+        Err(e) => errors.extend(e.into_node_errors(node, "value")),
+    }
+    errors.sort_by_key(|e| parse_path(&e.path).unwrap_or_default());
+    Err(EncodeError::Nodes(errors))
+}
+
+/// Encode each child, recording the errors of those that fail and carrying on
+fn encode_children(node: &Asn1Node, ctx: &mut Context) -> Vec<Vec<u8>> {
+    let mut children = Vec::new();
+    for child in &node.children {
+        match encode_node(child, ctx) {
+            Ok(bytes) => children.push(bytes),
+            Err(e) => {
+                let errors = e.into_node_errors(child, "value");
+                ctx.errors.extend(errors);
+            }
+        }
+    }
+    children
+}
+
+/// Re-encode a decoded tree after edits, touching only what changed.
+///
+/// Nodes marked `dirty` are re-serialized (reusing unchanged descendants as
+/// `encode_asn1_tree` does) and re-decoded; their ancestors get new length octets
+/// around the re-encoded content; every other subtree is copied from
+/// `source_hex` and only has its offsets shifted. Afterwards the tree describes
+/// the new encoding (offsets updated, dirty flags cleared, `source_hex` replaced),
+/// so it can be edited again without decoding the document from scratch. On
+/// error the tree is left unchanged.
+pub fn encode_changes(root: &mut Asn1Node) -> Result<Vec<u8>, EncodeError> {
+    if !root.is_pem_root() {
+        let error = EncodeError::InvalidValue("Changes can only be encoded from the decoded PEM root".to_string());
+        return Err(error.at(root, "tag_class"));
+    }
+    let source = match root.source_hex {
+        Some(ref hex) => hex_to_bytes(hex).map_err(|e| e.at(root, "source_hex"))?,
+        None => {
+            let error = EncodeError::InvalidValue("The root has no source_hex to copy unchanged nodes from".to_string());
+            return Err(error.at(root, "source_hex"));
+        }
+    };
+
+    // Work on a copy so that a failed encoding leaves the tree as it was
+    let mut tree = root.clone();
+    let mut der = Vec::new();
+    let mut errors = Vec::new();
+    for child in &mut tree.children {
+        match encode_changed(child, &source) {
+            Ok(bytes) => {
+                shift_offsets(child, der.len() as isize);
+                der.extend_from_slice(&bytes);
+            }
+            Err(e) => errors.extend(e.into_node_errors(child, "value")),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(EncodeError::Nodes(errors));
+    }
+
+    if tree.dirty {
+        assign_paths(&mut tree, "");
+        tree.dirty = false;
+    }
+    tree.length = der.len();
+    tree.byte_length = der.len();
+    tree.source_hex = Some(to_hex(&der));
+    *root = tree;
+    Ok(der)
+}
+
+fn has_dirty_node(node: &Asn1Node) -> bool {
+    node.dirty || node.children.iter().any(has_dirty_node)
+}
+
+fn shift_offsets(node: &mut Asn1Node, delta: isize) {
+    node.byte_offset = node.byte_offset.wrapping_add_signed(delta);
+    node.content_offset = node.content_offset.wrapping_add_signed(delta);
+    for child in &mut node.children {
+        shift_offsets(child, delta);
+    }
+}
+
+/// The bytes of `node` in `source`, if its offsets still point at an element
+/// with the same tag and content and, when `deep`, so do its descendants'.
+/// Nodes inserted by an editor have either no span or one left over from
+/// another document.
+fn source_span<'a>(node: &Asn1Node, source: &'a [u8], deep: bool) -> Option<&'a [u8]> {
+    if node.byte_length == 0 {
+        return None;
+    }
+    let (raw, content) = original_tlv(node, source)?;
+    if raw.len() != node.byte_length {
+        return None;
+    }
+    if !node.is_constructed {
+        return match node.content_hex {
+            Some(ref hex) if !hex.eq_ignore_ascii_case(&to_hex(content)) => None,
+            _ => Some(raw),
+        };
+    }
+    if node.collapsed {
+        return Some(raw);
+    }
+    let elements: Vec<_> = Tlv::read(raw, node.byte_offset).ok()?.children().collect::<Result<_, _>>().ok()?;
+    let matches = elements.len() == node.children.len()
+        && node.children.iter().zip(&elements).all(|(child, element)| {
+            child.byte_offset == element.offset()
+                && child.byte_length == element.raw().len()
+                && (!deep || source_span(child, source, true).is_some())
+        });
+    matches.then_some(raw)
+}
+
+/// Encode `node` for `encode_changes`, leaving the offsets in its subtree
+/// relative to the start of the returned bytes
+fn encode_changed(node: &mut Asn1Node, source: &[u8]) -> Result<Vec<u8>, EncodeError> {
+    let dirty = has_dirty_node(node);
+    if let Some(bytes) = (!dirty).then(|| source_span(node, source, true)).flatten() {
+        shift_offsets(node, -(node.byte_offset as isize));
+        return Ok(bytes.to_vec());
+    }
+    let span = source_span(node, source, false);
+
+    let path = node.path.clone();
+    let redecode = |bytes: &[u8], max_depth| {
+        Tlv::read(bytes, 0)
+            .map_err(|e| e.into())
+            .and_then(|tlv| decode_tlv_to_depth(&tlv, max_depth))
+            .map_err(|e| EncodeError::InvalidValue(format!("Re-encoded node does not decode: {}", e)))
+    };
+
+    // A node added under a dirty parent has no span of its own in the source
+    if !node.is_constructed || node.collapsed || !dirty {
+        let bytes = encode_tree(node, &mut Context::new(Some(source), EncodeOptions::default()))?;
+        *node = redecode(&bytes, None).map_err(|e| e.at(node, "value"))?;
+        assign_paths(node, &path);
+        return Ok(bytes);
+    }
+
+    // A constructed node only needs a new header around its children, each of
+    // which is copied or re-encoded on its own. An unchanged node keeps its
+    // identifier octets and indefinite length.
+    let mut content = Vec::new();
+    let mut positions = Vec::new();
+    let mut errors = Vec::new();
+    for child in &mut node.children {
+        positions.push(content.len());
+        match encode_changed(child, source) {
+            Ok(bytes) => content.extend_from_slice(&bytes),
+            Err(e) => errors.extend(e.into_node_errors(child, "value")),
+        }
+    }
+
+    let identifier = if node.dirty || span.is_none() {
+        encode_tag(&node.tag_class, node.tag_number, true).map_err(|e| {
+            let field = tag_field(&e);
+            e.at(node, field)
+        })
     } else {
-        Vec::new()
+        hex_to_bytes(&node.identifier_octets).map_err(|e| e.at(node, "identifier_octets"))
+    };
+    let mut bytes = match identifier {
+        Ok(bytes) if errors.is_empty() => bytes,
+        Ok(_) => return Err(EncodeError::Nodes(errors)),
+        Err(e) => {
+            errors.extend(e.into_node_errors(node, "identifier_octets"));
+            return Err(EncodeError::Nodes(errors));
+        }
     };
+    let indefinite = node.length_form == Some(LengthForm::Indefinite);
+    if indefinite {
+        bytes.push(0x80);
+    } else {
+        bytes.extend_from_slice(&encode_length(content.len()).map_err(|e| e.at(node, "length"))?);
+    }
+    let header_length = bytes.len();
+    bytes.extend_from_slice(&content);
+    if indefinite {
+        bytes.extend_from_slice(&[0x00, 0x00]);
+    }
+
+    let mut children = std::mem::take(&mut node.children);
+    for (child, position) in children.iter_mut().zip(positions) {
+        shift_offsets(child, (header_length + position) as isize);
+    }
+    let children_changed = node.dirty;
+    let header = redecode(&bytes, Some(0)).map_err(|e| e.at(node, "value"))?;
+    *node = Asn1Node { path, children, collapsed: false, ..header };
+    if children_changed {
+        let path = node.path.clone();
+        assign_paths(node, &path);
+    }
+    Ok(bytes)
+}
+
+/// Encode a node under its own `profile`, or the one it inherits
+fn encode_node(node: &Asn1Node, ctx: &mut Context) -> Result<Vec<u8>, EncodeError> {
+    let inherited = ctx.profile;
+    ctx.profile = node.profile.unwrap_or(inherited);
+    let result = encode_element(node, ctx);
+    ctx.profile = inherited;
+    result
+}
+
+fn encode_element(node: &Asn1Node, ctx: &mut Context) -> Result<Vec<u8>, EncodeError> {
+    let mut result = Vec::new();
+
+    if node.is_pem_root() {
+        return Ok(encode_children(node, ctx).concat());
+    }
+
+    // A collapsed node's children were never decoded, so only its original bytes can be used
+    if node.collapsed {
+        let end = node.byte_offset.saturating_add(node.byte_length);
+        let tlv = ctx.source
+            .and_then(|s| s.get(node.byte_offset..end))
+            .ok_or_else(|| {
+                EncodeError::InvalidValue("Collapsed node cannot be encoded without the source document".to_string())
+                    .at(node, "collapsed")
+            })?;
+        let retagged = ctx.source.and_then(|s| original_tlv(node, s)).is_none();
+        if !ctx.options.canonical && ctx.profile == Profile::Der && node.raw.is_none() && !retagged {
+            return Ok(tlv.to_vec());
+        }
+        // Canonical, BER/CER, retagged or overridden output has to look inside, so decode the subtree after all
+        let mut expanded = Tlv::read(tlv, node.byte_offset)
+            .map_err(|e| e.into())
+            .and_then(|tlv| decode_tlv_to_depth(&tlv, None))
+            .map_err(|e| EncodeError::InvalidValue(format!("Collapsed node does not decode: {}", e)).at(node, "collapsed"))?;
+        assign_paths(&mut expanded, &node.path);
+        expanded.tag_class = node.tag_class.clone();
+        expanded.tag_number = node.tag_number;
+        expanded.raw = node.raw.clone();
+        return encode_node(&expanded, ctx);
+    }
+
+    if let Some(ref raw) = node.raw {
+        return encode_raw(node, raw, ctx);
+    }
+
+    // Get content bytes, then the tag, so errors in the children are found first
+    let content = encode_content(node, ctx)?;
+
+    // Encode tag
+    let identifier = encode_tag(node.tag_class.as_str(), node.tag_number, node.is_constructed).map_err(|e| {
+        let field = tag_field(&e);
+        e.at(node, field)
+    })?;
+
+    if ctx.profile != Profile::Der {
+        if node.is_constructed {
+            return Ok(indefinite_element(&identifier, &content));
+        }
+        if ctx.profile == Profile::Cer && content.len() > CER_SEGMENT_LENGTH && is_string_type(node) {
+            return segmented_string(node, &identifier, &content).map_err(|e| e.at(node, "length"));
+        }
+    }
+
+    if ctx.options.canonical {
+        report_non_minimal_length(node, ctx);
+    } else if let Some((tlv, original_content)) = ctx.source.filter(|_| !node.dirty).and_then(|s| original_tlv(node, s)) {
+        // Untouched nodes keep their original bytes: identifier, length form and
+        // end-of-contents octets included
+        if original_content == content.as_slice() {
+            return Ok(tlv.to_vec());
+        }
+    }
+
+    result.extend_from_slice(&identifier);
 
     // Encode length
-    let length_bytes = encode_length(content.len())?;
+    let length_bytes = encode_length(content.len()).map_err(|e| e.at(node, "length"))?;
     result.extend_from_slice(&length_bytes);
 
     // Add content
@@ -69,8 +463,291 @@ pub fn encode_asn1_tree(node: &Asn1Node) -> Result<Vec<u8>, EncodeError> {
     Ok(result)
 }
 
-/// Encode the tag byte
-fn encode_tag(tag_class: &str, tag_number: u32, is_constructed: bool) -> Result<u8, EncodeError> {
+/// Content octets of a node: its encoded children, or its primitive value
+fn encode_content(node: &Asn1Node, ctx: &mut Context) -> Result<Vec<u8>, EncodeError> {
+    if node.is_constructed {
+        let mut children = encode_children(node, ctx);
+        if ctx.options.canonical && is_universal(node, 17) {
+            sort_set_elements(node, &mut children, ctx);
+        }
+        Ok(children.concat())
+    } else {
+        let content = encode_primitive_content(node)?;
+        if ctx.options.canonical {
+            canonical_primitive_content(node, content, ctx).map_err(|e| e.at(node, "value"))
+        } else {
+            Ok(content)
+        }
+    }
+}
+
+/// Encode a node with `raw` overrides, without the usual tag and length checks
+fn encode_raw(node: &Asn1Node, raw: &RawOverrides, ctx: &mut Context) -> Result<Vec<u8>, EncodeError> {
+    let raw_hex = |field: &'static str, hex: &str| {
+        let hex = hex.replace([' ', ':'], "");
+        if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(EncodeError::InvalidValue(format!("Not hex: {}", hex)).at(node, field));
+        }
+        hex_to_bytes(&hex)
+    };
+
+    let content = match raw.content_hex {
+        Some(ref hex) => raw_hex("raw.content_hex", hex)?,
+        None => encode_content(node, ctx)?,
+    };
+    let mut result = match raw.identifier_hex {
+        Some(ref hex) => raw_hex("raw.identifier_hex", hex)?,
+        None => encode_tag(node.tag_class.as_str(), node.tag_number, node.is_constructed).map_err(|e| {
+            let field = tag_field(&e);
+            e.at(node, field)
+        })?,
+    };
+
+    let length = raw.length.unwrap_or(content.len());
+    let indefinite = raw.length_form == Some(LengthForm::Indefinite);
+    if indefinite {
+        result.push(0x80);
+    } else if raw.length_form == Some(LengthForm::Short) && length > 127 {
+        let error = EncodeError::InvalidLength(format!("Length {} does not fit the short form", length));
+        return Err(error.at(node, "raw.length_form"));
+    } else if raw.length_form == Some(LengthForm::Long) || raw.long_form_octets.is_some() || length > 127 {
+        let minimal: Vec<u8> = length.to_be_bytes().into_iter().skip_while(|&b| b == 0).collect();
+        let octets = raw.long_form_octets.unwrap_or(minimal.len().max(1));
+        if octets < minimal.len() || !(1..=127).contains(&octets) {
+            let error = EncodeError::InvalidLength(format!("{} long-form octets cannot hold length {}", octets, length));
+            return Err(error.at(node, "raw.long_form_octets"));
+        }
+        result.push(0x80 | octets as u8);
+        result.extend(std::iter::repeat_n(0, octets - minimal.len()));
+        result.extend_from_slice(&minimal);
+    } else {
+        result.push(length as u8);
+    }
+
+    result.extend_from_slice(&content);
+    if indefinite {
+        result.extend_from_slice(&[0x00, 0x00]);
+    }
+    if let Some(ref hex) = raw.trailing_hex {
+        result.extend_from_slice(&raw_hex("raw.trailing_hex", hex)?);
+    }
+    Ok(result)
+}
+
+/// Content octets per primitive segment of a long string in CER
+const CER_SEGMENT_LENGTH: usize = 1000;
+
+fn indefinite_element(identifier: &[u8], content: &[u8]) -> Vec<u8> {
+    let mut result = identifier.to_vec();
+    result[0] |= 0x20;
+    result.push(0x80);
+    result.extend_from_slice(content);
+    result.extend_from_slice(&[0x00, 0x00]);
+    result
+}
+
+/// BIT STRING, OCTET STRING and the restricted character string types, which CER
+/// splits into segments
+fn is_string_type(node: &Asn1Node) -> bool {
+    matches!(node.tag_number, 3 | 4 | 7 | 12 | 18..=22 | 25..=30) && is_universal(node, node.tag_number)
+}
+
+/// Encode a long string as CER requires: constructed, indefinite length, made of
+/// primitive segments of 1000 content octets each except the last. BIT STRING
+/// segments are BIT STRINGs (only the last may have unused bits); all other
+/// types are split into OCTET STRINGs.
+fn segmented_string(node: &Asn1Node, identifier: &[u8], content: &[u8]) -> Result<Vec<u8>, EncodeError> {
+    let mut segments = Vec::new();
+    if node.tag_number == 3 {
+        let (unused, data) = content.split_first().unwrap_or((&0, &[]));
+        let chunks: Vec<&[u8]> = data.chunks(CER_SEGMENT_LENGTH - 1).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut segment = vec![if i + 1 == chunks.len() { *unused } else { 0 }];
+            segment.extend_from_slice(chunk);
+            segments.push(0x03);
+            segments.extend_from_slice(&encode_length(segment.len())?);
+            segments.extend_from_slice(&segment);
+        }
+    } else {
+        for chunk in content.chunks(CER_SEGMENT_LENGTH) {
+            segments.push(0x04);
+            segments.extend_from_slice(&encode_length(chunk.len())?);
+            segments.extend_from_slice(chunk);
+        }
+    }
+    Ok(indefinite_element(identifier, &segments))
+}
+
+/// DER orders SET elements by their encodings, compared as octet strings with
+/// the shorter one padded with trailing zero octets
+fn sort_set_elements(node: &Asn1Node, children: &mut [Vec<u8>], ctx: &mut Context) {
+    let padded_cmp = |a: &Vec<u8>, b: &Vec<u8>| {
+        let length = a.len().max(b.len());
+        let padded = |v: &Vec<u8>| v.iter().copied().chain(std::iter::repeat(0)).take(length).collect::<Vec<u8>>();
+        padded(a).cmp(&padded(b))
+    };
+    if children.windows(2).any(|pair| padded_cmp(&pair[0], &pair[1]).is_gt()) {
+        children.sort_by(padded_cmp);
+        ctx.report(node, "set_order", "SET elements sorted by their encodings".to_string());
+    }
+}
+
+fn report_non_minimal_length(node: &Asn1Node, ctx: &mut Context) {
+    match node.length_form {
+        Some(LengthForm::Indefinite) => {
+            ctx.report(node, "length", "indefinite length replaced by a definite length".to_string())
+        }
+        Some(_) => {
+            let minimal = encode_length(node.length).map(|octets| to_hex(&octets));
+            if minimal.is_ok_and(|minimal| !minimal.eq_ignore_ascii_case(&node.length_octets)) {
+                ctx.report(node, "length", format!("length octets {} minimized", node.length_octets));
+            }
+        }
+        None => {}
+    }
+}
+
+/// Apply the DER rules for INTEGER, BOOLEAN and BIT STRING content
+fn canonical_primitive_content(node: &Asn1Node, mut content: Vec<u8>, ctx: &mut Context) -> Result<Vec<u8>, EncodeError> {
+    if !is_universal(node, node.tag_number) {
+        return Ok(content);
+    }
+
+    match node.tag_number {
+        1 if content.len() == 1 && content[0] != 0x00 && content[0] != 0xFF => {
+            ctx.report(node, "boolean", format!("TRUE encoded as 0x{:02X} changed to 0xFF", content[0]));
+            content[0] = 0xFF;
+        }
+        2 | 10 => {
+            if content.is_empty() {
+                ctx.report(node, "integer", "empty content encoded as 0".to_string());
+                content.push(0x00);
+            }
+            let redundant = content
+                .windows(2)
+                .take_while(|pair| (pair[0] == 0x00 && pair[1] & 0x80 == 0) || (pair[0] == 0xFF && pair[1] & 0x80 != 0))
+                .count();
+            if redundant > 0 {
+                ctx.report(node, "integer", format!("removed {} redundant leading octet(s)", redundant));
+                content.drain(..redundant);
+            }
+        }
+        3 if !content.is_empty() => canonical_bit_string(node, &mut content, ctx),
+        _ => {}
+    }
+    Ok(content)
+}
+
+fn canonical_bit_string(node: &Asn1Node, content: &mut Vec<u8>, ctx: &mut Context) {
+    let unused = content[0];
+    if content.len() == 1 {
+        if unused != 0 {
+            ctx.report(node, "bit_string", format!("empty BIT STRING declared {} unused bits", unused));
+            content[0] = 0;
+        }
+        return;
+    }
+    if unused > 7 {
+        return;
+    }
+
+    let last = content.len() - 1;
+    let mask = 0xFFu8 << unused;
+    if content[last] & !mask != 0 {
+        ctx.report(node, "bit_string", format!("cleared {} unused bit(s) that were set", (content[last] & !mask).count_ones()));
+        content[last] &= mask;
+    }
+
+    if node.named_bits {
+        let used = content[1..].iter().rposition(|&byte| byte != 0).map_or(0, |i| i + 1);
+        let new_unused = if used == 0 { 0 } else { content[used].trailing_zeros() as u8 };
+        if used + 1 != content.len() || new_unused != unused {
+            let bits = |length: usize, unused: u8| (length * 8).saturating_sub(unused as usize);
+            ctx.report(
+                node,
+                "named_bits",
+                format!("trailing zero bits removed ({} bits -> {})", bits(content.len() - 1, unused), bits(used, new_unused)),
+            );
+            content.truncate(used + 1);
+            content[0] = new_unused;
+        }
+    }
+}
+
+/// Locate a node's original TLV in the source, returning it with its content
+/// (without end-of-contents octets) if its class, number and form still match.
+/// The identifier is compared by meaning, so non-minimal identifier octets are kept.
+fn original_tlv<'a>(node: &Asn1Node, source: &'a [u8]) -> Option<(&'a [u8], &'a [u8])> {
+    let end = node.byte_offset.checked_add(node.byte_length)?;
+    let tlv = Tlv::read(source.get(node.byte_offset..end)?, node.byte_offset).ok()?;
+    let tag = tlv.tag();
+    if !tag.class.name().eq_ignore_ascii_case(&node.tag_class)
+        || tag.number != node.tag_number
+        || tag.constructed != node.is_constructed
+    {
+        return None;
+    }
+    Some((tlv.raw(), tlv.content()))
+}
+
+/// Encode the content of a primitive node.
+///
+/// The decoder abbreviates some values for display (e.g. long OCTET STRINGs), so
+/// when the value is unchanged from what `content_hex` decodes to, the complete
+/// original content is used instead of the display text.
+fn encode_primitive_content(node: &Asn1Node) -> Result<Vec<u8>, EncodeError> {
+    let original = match node.content_hex {
+        Some(ref hex) => Some(hex_to_bytes(hex).map_err(|e| e.at(node, "content_hex"))?),
+        None => None,
+    };
+
+    match (&node.value, original) {
+        (Some(value), Some(original)) if decode_value(node.tag_number, &original) == *value => {
+            Ok(original)
+        }
+        (Some(value), _) => {
+            if is_truncated_display(value) {
+                let error = EncodeError::InvalidValue(format!(
+                    "Value is an abbreviated display string and cannot be encoded: {}",
+                    value
+                ));
+                return Err(error.at(node, "value"));
+            }
+            encode_value(node.tag_number, value).map_err(|e| e.at(node, "value"))
+        }
+        (None, Some(original)) => Ok(original),
+        (None, None) => Ok(Vec::new()),
+    }
+}
+
+/// Check whether a value is a "[N bytes] ..." display string that lists fewer
+/// than N bytes, such as the abbreviated hex shown for long OCTET STRINGs.
+fn is_truncated_display(value: &str) -> bool {
+    let value = value.trim();
+    if !value.starts_with('[') {
+        return false;
+    }
+    let Some(close) = value.find(" bytes]") else {
+        return false;
+    };
+    let declared = value[1..close]
+        .rsplit([' ', ':'])
+        .next()
+        .and_then(|n| n.parse::<usize>().ok());
+    let Some(declared) = declared else {
+        return false;
+    };
+
+    let listed = value[close + " bytes]".len()..]
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .count()
+        / 2;
+    listed != declared
+}
+
+/// Encode the identifier octets, using the high-tag-number form for tags above 30
+pub(crate) fn encode_tag(tag_class: &str, tag_number: u32, is_constructed: bool) -> Result<Vec<u8>, EncodeError> {
     let tag_class_upper = tag_class.to_uppercase();
     let class_bits = match tag_class_upper.as_str() {
         "UNIVERSAL" => 0b00000000,
@@ -83,16 +760,27 @@ fn encode_tag(tag_class: &str, tag_number: u32, is_constructed: bool) -> Result<
 
     let constructed_bit = if is_constructed { 0b00100000 } else { 0b00000000 };
 
-    if tag_number > 30 {
-        // Long form tag encoding (not implemented for simplicity)
-        return Err(EncodeError::UnsupportedType("Tag numbers > 30 not yet supported".to_string()));
-    }
+    Ok(identifier_octets(class_bits | constructed_bit, tag_number))
+}
 
-    Ok(class_bits | constructed_bit | (tag_number as u8))
+/// Identifier octets for a tag number, given the class and constructed bits
+/// of the first octet. Tags above 30 use base-128 subsequent octets.
+pub(crate) fn identifier_octets(leading_bits: u8, number: u32) -> Vec<u8> {
+    if number <= 30 {
+        return vec![leading_bits | number as u8];
+    }
+    let mut octets = vec![(number & 0x7F) as u8];
+    let mut rest = number >> 7;
+    while rest > 0 {
+        octets.insert(0, 0x80 | (rest & 0x7F) as u8);
+        rest >>= 7;
+    }
+    octets.insert(0, leading_bits | 0x1F);
+    octets
 }
 
 /// Encode the length in DER format
-fn encode_length(length: usize) -> Result<Vec<u8>, EncodeError> {
+pub(crate) fn encode_length(length: usize) -> Result<Vec<u8>, EncodeError> {
     if length < 128 {
         // Short form
         Ok(vec![length as u8])
@@ -118,7 +806,7 @@ fn encode_length(length: usize) -> Result<Vec<u8>, EncodeError> {
 }
 
 /// Encode a primitive value based on tag number
-fn encode_value(tag_number: u32, value: &str) -> Result<Vec<u8>, EncodeError> {
+pub(crate) fn encode_value(tag_number: u32, value: &str) -> Result<Vec<u8>, EncodeError> {
     match tag_number {
         1 => encode_boolean(value),
         2 => encode_integer(value),
@@ -127,14 +815,17 @@ fn encode_value(tag_number: u32, value: &str) -> Result<Vec<u8>, EncodeError> {
         5 => encode_null(value),
         6 => encode_object_identifier(value),
         12 | 19 | 22 | 23 | 24 => encode_string(value), // UTF8String, PrintableString, IA5String, UTCTime, GeneralizedTime
-        _ => {
-            // For unknown types, try to decode hex if present
-            if value.starts_with("0x") {
-                hex_to_bytes(&value[2..])
-            } else {
-                encode_string(value)
-            }
-        }
+        _ => encode_untyped_value(value),
+    }
+}
+
+/// Encode a value whose type is unknown (e.g. an implicitly tagged one): "0x"
+/// followed by hex gives the raw content, anything else is taken as a string
+pub(crate) fn encode_untyped_value(value: &str) -> Result<Vec<u8>, EncodeError> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        hex_to_bytes(hex)
+    } else {
+        encode_string(value)
     }
 }
 
@@ -146,50 +837,74 @@ fn encode_boolean(value: &str) -> Result<Vec<u8>, EncodeError> {
     }
 }
 
+/// Encode an INTEGER given in decimal of any size, or as "0x"/"-0x" followed by
+/// the hex magnitude. Hex keeps its leading zero octets so decoded values
+/// round-trip; a 0x00 (or 0xFF) sign octet is added only when needed.
 fn encode_integer(value: &str) -> Result<Vec<u8>, EncodeError> {
-    // Try to parse as hex first (common for serial numbers, etc.)
-    if value.starts_with("0x") {
-        return hex_to_bytes(&value[2..]);
-    }
+    let invalid = || EncodeError::InvalidValue(format!("Invalid integer value: {}", value));
+    let trimmed = value.trim();
+    let (negative, unsigned) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
 
-    // Try to parse as decimal integer
-    if let Ok(num) = value.parse::<i64>() {
-        if num == 0 {
-            return Ok(vec![0]);
+    let magnitude = if let Some(hex) = unsigned.strip_prefix("0x").or_else(|| unsigned.strip_prefix("0X")) {
+        let hex = hex.replace([' ', ':'], "");
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
         }
+        // An odd number of digits gets a leading zero nibble
+        let hex = if hex.len() % 2 == 1 { format!("0{}", hex) } else { hex };
+        hex_to_bytes(&hex)?
+    } else {
+        if unsigned.is_empty() || !unsigned.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        decimal_to_bytes(unsigned)
+    };
 
-        if num > 0 {
-            // Positive number encoding
-            let mut bytes = Vec::new();
-            let mut n = num;
-            
-            while n > 0 {
-                bytes.insert(0, (n & 0xFF) as u8);
-                n >>= 8;
-            }
-
-            // Add padding byte if high bit is set (to maintain sign)
-            if bytes[0] & 0x80 != 0 {
-                bytes.insert(0, 0);
-            }
+    Ok(twos_complement(magnitude, negative))
+}
 
-            Ok(bytes)
-        } else {
-            // Negative number encoding using two's complement
-            let mut bytes = Vec::new();
-            let mut n = num;
-            
-            // Convert to bytes (little-endian style processing)
-            while n != -1 || (bytes.is_empty() || bytes[0] & 0x80 == 0) {
-                bytes.insert(0, (n & 0xFF) as u8);
-                n >>= 8;
-            }
+/// Big-endian magnitude of a string of decimal digits, at least one octet long
+fn decimal_to_bytes(digits: &str) -> Vec<u8> {
+    let mut bytes = vec![0u8];
+    for digit in digits.bytes() {
+        let mut carry = (digit - b'0') as u32;
+        for byte in bytes.iter_mut().rev() {
+            let product = *byte as u32 * 10 + carry;
+            *byte = product as u8;
+            carry = product >> 8;
+        }
+        if carry > 0 {
+            bytes.insert(0, carry as u8);
+        }
+    }
+    let leading_zeros = bytes.iter().take_while(|&&b| b == 0).count().min(bytes.len() - 1);
+    bytes.drain(..leading_zeros);
+    bytes
+}
 
-            Ok(bytes)
+/// Two's complement of a big-endian magnitude, keeping its width and adding a
+/// sign octet only when the top bit would say otherwise
+fn twos_complement(mut bytes: Vec<u8>, negative: bool) -> Vec<u8> {
+    if !negative || bytes.iter().all(|&b| b == 0) {
+        if bytes[0] & 0x80 != 0 {
+            bytes.insert(0, 0x00);
         }
-    } else {
-        Err(EncodeError::InvalidValue(format!("Invalid integer value: {}", value)))
+        return bytes;
     }
+
+    let mut carry = true;
+    for byte in bytes.iter_mut().rev() {
+        let (sum, overflow) = (!*byte).overflowing_add(carry as u8);
+        *byte = sum;
+        carry = overflow;
+    }
+    if bytes[0] & 0x80 == 0 {
+        bytes.insert(0, 0xFF);
+    }
+    bytes
 }
 
 fn encode_bit_string(value: &str) -> Result<Vec<u8>, EncodeError> {
@@ -287,15 +1002,24 @@ fn encode_object_identifier(value: &str) -> Result<Vec<u8>, EncodeError> {
         return Err(EncodeError::InvalidValue("OID must have at least 2 components".to_string()));
     }
 
-    let first: u32 = parts[0].parse()
+    let first: u64 = parts[0].parse()
         .map_err(|_| EncodeError::InvalidValue("Invalid OID component".to_string()))?;
-    let second: u32 = parts[1].parse()
+    let second: u64 = parts[1].parse()
         .map_err(|_| EncodeError::InvalidValue("Invalid OID component".to_string()))?;
+    if first > 2 {
+        return Err(EncodeError::InvalidValue(format!("OID first arc must be 0, 1 or 2, got {}", first)));
+    }
+    if first < 2 && second >= 40 {
+        return Err(EncodeError::InvalidValue(format!("OID second arc must be below 40 under {}, got {}", first, second)));
+    }
+    let combined = second
+        .checked_add(first * 40)
+        .ok_or_else(|| EncodeError::InvalidValue("OID second arc is too large".to_string()))?;
 
-    let mut bytes = vec![first as u8 * 40 + second as u8];
+    let mut bytes = encode_oid_component(combined);
 
     for part in &parts[2..] {
-        let num: u32 = part.parse()
+        let num: u64 = part.parse()
             .map_err(|_| EncodeError::InvalidValue("Invalid OID component".to_string()))?;
         bytes.extend_from_slice(&encode_oid_component(num));
     }
@@ -303,7 +1027,7 @@ fn encode_object_identifier(value: &str) -> Result<Vec<u8>, EncodeError> {
     Ok(bytes)
 }
 
-fn encode_oid_component(mut num: u32) -> Vec<u8> {
+fn encode_oid_component(mut num: u64) -> Vec<u8> {
     if num == 0 {
         return vec![0];
     }
@@ -324,17 +1048,18 @@ fn encode_string(value: &str) -> Result<Vec<u8>, EncodeError> {
     Ok(value.trim().as_bytes().to_vec())
 }
 
-fn hex_to_bytes(hex: &str) -> Result<Vec<u8>, EncodeError> {
+pub(crate) fn hex_to_bytes(hex: &str) -> Result<Vec<u8>, EncodeError> {
     let hex = hex.replace(" ", "").replace(":", "");
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(EncodeError::InvalidValue(format!("Invalid hex string: {}", hex)));
+    }
     let mut bytes = Vec::new();
     
     for i in (0..hex.len()).step_by(2) {
-        if i + 1 < hex.len() {
-            let byte_str = &hex[i..i+2];
-            let byte = u8::from_str_radix(byte_str, 16)
-                .map_err(|_| EncodeError::InvalidValue(format!("Invalid hex string: {}", hex)))?;
-            bytes.push(byte);
-        }
+        let byte_str = &hex[i..i+2];
+        let byte = u8::from_str_radix(byte_str, 16)
+            .map_err(|_| EncodeError::InvalidValue(format!("Invalid hex string: {}", hex)))?;
+        bytes.push(byte);
     }
 
     Ok(bytes)
@@ -346,37 +1071,37 @@ mod tests {
 
     #[test]
     fn test_encode_tag_universal() {
-        assert_eq!(encode_tag("Universal", 2, false).unwrap(), 0x02); // INTEGER
-        assert_eq!(encode_tag("Universal", 16, true).unwrap(), 0x30); // SEQUENCE
-        assert_eq!(encode_tag("Universal", 4, false).unwrap(), 0x04); // OCTET STRING
-        assert_eq!(encode_tag("Universal", 3, false).unwrap(), 0x03); // BIT STRING
-        assert_eq!(encode_tag("Universal", 6, false).unwrap(), 0x06); // OID
-        assert_eq!(encode_tag("UNIVERSAL", 2, false).unwrap(), 0x02); // Case insensitive
+        assert_eq!(encode_tag("Universal", 2, false).unwrap(), vec![0x02]); // INTEGER
+        assert_eq!(encode_tag("Universal", 16, true).unwrap(), vec![0x30]); // SEQUENCE
+        assert_eq!(encode_tag("Universal", 4, false).unwrap(), vec![0x04]); // OCTET STRING
+        assert_eq!(encode_tag("Universal", 3, false).unwrap(), vec![0x03]); // BIT STRING
+        assert_eq!(encode_tag("Universal", 6, false).unwrap(), vec![0x06]); // OID
+        assert_eq!(encode_tag("UNIVERSAL", 2, false).unwrap(), vec![0x02]); // Case insensitive
     }
 
     #[test]
     fn test_encode_tag_context() {
-        assert_eq!(encode_tag("Context", 0, true).unwrap(), 0xA0); // [0]
-        assert_eq!(encode_tag("Context", 1, true).unwrap(), 0xA1); // [1]
-        assert_eq!(encode_tag("Context", 3, false).unwrap(), 0x83); // [3] primitive
-        assert_eq!(encode_tag("CONTEXT", 0, true).unwrap(), 0xA0); // Case insensitive
+        assert_eq!(encode_tag("Context", 0, true).unwrap(), vec![0xA0]); // [0]
+        assert_eq!(encode_tag("Context", 1, true).unwrap(), vec![0xA1]); // [1]
+        assert_eq!(encode_tag("Context", 3, false).unwrap(), vec![0x83]); // [3] primitive
+        assert_eq!(encode_tag("CONTEXT", 0, true).unwrap(), vec![0xA0]); // Case insensitive
     }
 
     #[test]
     fn test_encode_tag_application() {
-        assert_eq!(encode_tag("Application", 0, false).unwrap(), 0x40);
-        assert_eq!(encode_tag("Application", 5, true).unwrap(), 0x65);
+        assert_eq!(encode_tag("Application", 0, false).unwrap(), vec![0x40]);
+        assert_eq!(encode_tag("Application", 5, true).unwrap(), vec![0x65]);
     }
 
     #[test]
     fn test_encode_tag_private() {
-        assert_eq!(encode_tag("Private", 0, false).unwrap(), 0xC0);
-        assert_eq!(encode_tag("Private", 10, true).unwrap(), 0xEA);
+        assert_eq!(encode_tag("Private", 0, false).unwrap(), vec![0xC0]);
+        assert_eq!(encode_tag("Private", 10, true).unwrap(), vec![0xEA]);
     }
 
     #[test]
     fn test_encode_tag_pem_as_universal() {
-        assert_eq!(encode_tag("PEM", 16, true).unwrap(), 0x30); // PEM treated as Universal
+        assert_eq!(encode_tag("PEM", 16, true).unwrap(), vec![0x30]); // PEM treated as Universal
     }
 
     #[test]
@@ -384,6 +1109,22 @@ mod tests {
         assert!(encode_tag("Unknown", 2, false).is_err());
     }
 
+    #[test]
+    fn test_encode_tag_high_number() {
+        assert_eq!(
+            encode_tag("Universal", 31, false).unwrap(),
+            vec![0x1F, 0x1F]
+        );
+        assert_eq!(
+            encode_tag("Context", 200, true).unwrap(),
+            vec![0xBF, 0x81, 0x48]
+        );
+        assert_eq!(
+            encode_tag("Private", 16384, false).unwrap(),
+            vec![0xDF, 0x81, 0x80, 0x00]
+        );
+    }
+
     #[test]
     fn test_encode_length_short_form() {
         assert_eq!(encode_length(0).unwrap(), vec![0x00]);
@@ -435,14 +1176,67 @@ mod tests {
     #[test]
     fn test_encode_integer_hex() {
         assert_eq!(encode_integer("0x00").unwrap(), vec![0x00]);
-        assert_eq!(encode_integer("0xFF").unwrap(), vec![0xFF]);
+        assert_eq!(encode_integer("0xFF").unwrap(), vec![0x00, 0xFF]);
         assert_eq!(encode_integer("0x0102").unwrap(), vec![0x01, 0x02]);
+        assert_eq!(encode_integer("0x00FF").unwrap(), vec![0x00, 0xFF]);
+        assert_eq!(encode_integer("0xabc").unwrap(), vec![0x0A, 0xBC]);
+        assert_eq!(encode_integer("-0x01").unwrap(), vec![0xFF]);
+        assert_eq!(encode_integer("-0x80").unwrap(), vec![0x80]);
+        assert_eq!(encode_integer("-0x81").unwrap(), vec![0xFF, 0x7F]);
+        assert_eq!(encode_integer("-0x00").unwrap(), vec![0x00]);
     }
 
     #[test]
     fn test_encode_integer_invalid() {
         assert!(encode_integer("not_a_number").is_err());
         assert!(encode_integer("12.34").is_err());
+        assert!(encode_integer("").is_err());
+        assert!(encode_integer("-").is_err());
+        assert!(encode_integer("0x").is_err());
+        assert!(encode_integer("0x12G4").is_err());
+        assert!(encode_integer("--5").is_err());
+    }
+
+    #[test]
+    fn test_encode_integer_arbitrary_size() {
+        // 2^64 and -(2^64) need more than an i64
+        assert_eq!(
+            encode_integer("18446744073709551616").unwrap(),
+            [0x01, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            encode_integer("-18446744073709551616").unwrap(),
+            [0xFF, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            encode_integer("-9223372036854775808").unwrap(),
+            [0x80, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(encode_integer("007").unwrap(), [0x07]);
+
+        // A 20-byte serial number typed in decimal (2^159 + 1) gets its sign octet
+        let serial = encode_integer("730750818665451459101842416358141509827966271489").unwrap();
+        let mut expected = vec![0x00, 0x80];
+        expected.extend_from_slice(&[0x00; 18]);
+        expected.push(0x01);
+        assert_eq!(serial, expected);
+    }
+
+    #[test]
+    fn test_large_integers_round_trip_through_value() {
+        for content in [
+            vec![0x00, 0x80, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08],
+            vec![0xFF, 0x7F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01],
+            vec![0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        ] {
+            let mut der = vec![0x02, content.len() as u8];
+            der.extend_from_slice(&content);
+            let mut root = decode_root(&der);
+            root.children[0].content_hex = None;
+            let value = root.children[0].value.clone().unwrap();
+            assert_eq!(encode_integer(&value).unwrap(), content, "{}", value);
+            assert_eq!(encode_asn1_tree(&root).unwrap(), der);
+        }
     }
 
     #[test]
@@ -527,6 +1321,17 @@ mod tests {
         assert!(encode_object_identifier("1").is_err()); // Too short
         assert!(encode_object_identifier("invalid.oid").is_err());
         assert!(encode_object_identifier("1.2.abc").is_err());
+        assert!(encode_object_identifier("3.1").is_err());
+        assert!(encode_object_identifier("1.40").is_err());
+    }
+
+    #[test]
+    fn test_encode_oid_large_second_arc() {
+        // 2.999 combines to 1079 = 0x88 0x37
+        assert_eq!(encode_object_identifier("2.999.1").unwrap(), vec![0x88, 0x37, 0x01]);
+        assert_eq!(encode_object_identifier("2.999.3").unwrap(), vec![0x88, 0x37, 0x03]);
+        assert_eq!(encode_object_identifier("2.47").unwrap(), vec![0x7F]);
+        assert_eq!(encode_object_identifier("2.48").unwrap(), vec![0x81, 0x00]);
     }
 
     #[test]
@@ -550,6 +1355,9 @@ mod tests {
     fn test_hex_to_bytes_invalid() {
         assert!(hex_to_bytes("0G").is_err());
         assert!(hex_to_bytes("XYZ").is_err());
+        assert!(hex_to_bytes("010").is_err());
+        assert!(hex_to_bytes("é1").is_err());
+        assert!(hex_to_bytes("+1").is_err());
     }
 
     #[test]
@@ -563,7 +1371,9 @@ mod tests {
             byte_length: 0,
             length: 1,
             value: Some("42".to_string()),
-            children: None,
+            content_hex: None,
+            children: Vec::new(),
+            ..Default::default()
         };
         
         let result = encode_asn1_tree(&node).unwrap();
@@ -582,7 +1392,9 @@ mod tests {
             byte_length: 0,
             length: 1,
             value: Some("1".to_string()),
-            children: None,
+            content_hex: None,
+            children: Vec::new(),
+            ..Default::default()
         };
         
         let child2 = Asn1Node {
@@ -594,7 +1406,9 @@ mod tests {
             byte_length: 0,
             length: 1,
             value: Some("2".to_string()),
-            children: None,
+            content_hex: None,
+            children: Vec::new(),
+            ..Default::default()
         };
         
         let sequence = Asn1Node {
@@ -606,7 +1420,9 @@ mod tests {
             byte_length: 0,
             length: 6,
             value: None,
-            children: Some(vec![child1, child2]),
+            content_hex: None,
+            children: vec![child1, child2],
+            ..Default::default()
         };
         
         let result = encode_asn1_tree(&sequence).unwrap();
@@ -625,7 +1441,9 @@ mod tests {
             byte_length: 0,
             length: 1,
             value: Some("true".to_string()),
-            children: None,
+            content_hex: None,
+            children: Vec::new(),
+            ..Default::default()
         };
         
         let result = encode_asn1_tree(&node).unwrap();
@@ -644,7 +1462,9 @@ mod tests {
             byte_length: 0,
             length: 0,
             value: Some("".to_string()),
-            children: None,
+            content_hex: None,
+            children: Vec::new(),
+            ..Default::default()
         };
         
         let result = encode_asn1_tree(&node).unwrap();
@@ -663,7 +1483,8 @@ mod tests {
             byte_length: 0,
             length: 3,
             value: None,
-            children: Some(vec![
+            content_hex: None,
+            children: vec![
                 Asn1Node {
                     label: "INTEGER".to_string(),
                     tag_class: "Universal".to_string(),
@@ -673,9 +1494,12 @@ mod tests {
                     byte_length: 0,
                     length: 1,
                     value: Some("5".to_string()),
-                    children: None,
+                    content_hex: None,
+                    children: Vec::new(),
+                    ..Default::default()
                 }
-            ]),
+            ],
+            ..Default::default()
         };
         
         let result = encode_asn1_tree(&node).unwrap();
@@ -719,7 +1543,8 @@ mod tests {
             byte_length: 0,
             length: 0,
             value: None,
-            children: Some(vec![
+            content_hex: None,
+            children: vec![
                 Asn1Node {
                     label: "INTEGER".to_string(),
                     tag_class: "Universal".to_string(),
@@ -729,7 +1554,9 @@ mod tests {
                     byte_length: 0,
                     length: 1,
                     value: Some("100".to_string()),
-                    children: None,
+                    content_hex: None,
+                    children: Vec::new(),
+                    ..Default::default()
                 },
                 Asn1Node {
                     label: "OCTET STRING".to_string(),
@@ -740,16 +1567,703 @@ mod tests {
                     byte_length: 0,
                     length: 3,
                     value: Some("010203".to_string()),
-                    children: None,
+                    content_hex: None,
+                    children: Vec::new(),
+                    ..Default::default()
                 },
-            ]),
+            ],
+            ..Default::default()
         };
         
         let result = encode_asn1_tree(&node).unwrap();
         // Expected: SEQUENCE (0x30), length, INTEGER (0x02), length, value, OCTET STRING (0x04), length, value
-        assert!(result.len() > 0);
+        assert!(!result.is_empty());
         assert_eq!(result[0], 0x30); // SEQUENCE tag
         assert!(result.contains(&0x02)); // INTEGER tag
         assert!(result.contains(&0x04)); // OCTET STRING tag
     }
+    #[test]
+    fn test_encode_long_octet_string_uses_content_hex() {
+        let content: Vec<u8> = (0u8..40).collect();
+        let node = Asn1Node {
+            label: "OCTET STRING".to_string(),
+            tag_class: "Universal".to_string(),
+            tag_number: 4,
+            is_constructed: false,
+            byte_offset: 0,
+            byte_length: 0,
+            length: content.len(),
+            value: Some(decode_value(4, &content)),
+            content_hex: Some(crate::decoder::to_hex(&content)),
+            children: Vec::new(),
+            ..Default::default()
+        };
+
+        let result = encode_asn1_tree(&node).unwrap();
+        assert_eq!(result[..2], [0x04, 40]);
+        assert_eq!(result[2..], content[..]);
+    }
+
+    #[test]
+    fn test_encode_edited_value_overrides_content_hex() {
+        let node = Asn1Node {
+            label: "OCTET STRING".to_string(),
+            tag_class: "Universal".to_string(),
+            tag_number: 4,
+            is_constructed: false,
+            byte_offset: 0,
+            byte_length: 0,
+            length: 2,
+            value: Some("0A0B0C".to_string()),
+            content_hex: Some("0102".to_string()),
+            children: Vec::new(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            encode_asn1_tree(&node).unwrap(),
+            vec![0x04, 0x03, 0x0A, 0x0B, 0x0C]
+        );
+    }
+
+    #[test]
+    fn test_encode_truncated_display_rejected() {
+        let node = Asn1Node {
+            label: "OCTET STRING".to_string(),
+            tag_class: "Universal".to_string(),
+            tag_number: 4,
+            is_constructed: false,
+            byte_offset: 0,
+            byte_length: 0,
+            length: 20,
+            value: Some("[20 bytes] 00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F".to_string()),
+            content_hex: None,
+            children: Vec::new(),
+            ..Default::default()
+        };
+
+        assert!(encode_asn1_tree(&node).is_err());
+    }
+
+    #[test]
+    fn test_is_truncated_display() {
+        assert!(is_truncated_display("[20 bytes] 00 01 02"));
+        assert!(is_truncated_display("[5 bytes]"));
+        assert!(is_truncated_display("[Invalid UTF-8: 3 bytes]"));
+        assert!(!is_truncated_display("[3 bytes] 00 01 02"));
+        assert!(!is_truncated_display("0102"));
+        assert!(!is_truncated_display("[label]"));
+    }
+
+    fn decode_root(der: &[u8]) -> Asn1Node {
+        let pem = pem::encode(&pem::Pem::new("TEST", der.to_vec()));
+        Asn1Node::from_json(&crate::decoder::decode_pem_internal(&pem).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_untouched_nodes_keep_original_encoding() {
+        // SEQUENCE { INTEGER 1 } with a non-minimal long-form length (81 03)
+        let der = vec![0x30, 0x81, 0x03, 0x02, 0x01, 0x01];
+        let root = decode_root(&der);
+        assert_eq!(encode_asn1_tree(&root).unwrap(), der);
+    }
+
+    #[test]
+    fn test_edited_node_reencodes_only_ancestor_headers() {
+        // SEQUENCE { SEQUENCE(long form) { INTEGER 1 }, INTEGER 2 }
+        let der = vec![
+            0x30, 0x09, 0x30, 0x81, 0x03, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02,
+        ];
+        let mut root = decode_root(&der);
+        root.children[0].children[1].value = Some("3".to_string());
+
+        // The untouched inner SEQUENCE keeps its long-form length
+        assert_eq!(
+            encode_asn1_tree(&root).unwrap(),
+            vec![0x30, 0x09, 0x30, 0x81, 0x03, 0x02, 0x01, 0x01, 0x02, 0x01, 0x03]
+        );
+    }
+
+    #[test]
+    fn test_high_tag_numbers_round_trip() {
+        // SEQUENCE { [200] 05, [UNIVERSAL 31] { INTEGER 7 }, [PRIVATE 16384] 01 }
+        let der = vec![
+            0x30, 0x11, 0x9F, 0x81, 0x48, 0x01, 0x05, 0x3F, 0x1F, 0x03, 0x02, 0x01, 0x07, 0xDF,
+            0x81, 0x80, 0x00, 0x01, 0x01,
+        ];
+        let mut root = decode_root(&der);
+        assert_eq!(root.children[0].children[0].tag, 200);
+        assert_eq!(root.children[0].children[2].tag, 16384);
+        assert_eq!(encode_asn1_tree(&root).unwrap(), der);
+
+        // Without the source every identifier is encoded from its class and number
+        root.source_hex = None;
+        let encoded = encode_asn1_tree(&root).unwrap();
+        assert_eq!(encoded, der);
+        assert_eq!(decode_root(&encoded).children, decode_root(&der).children);
+    }
+
+    #[test]
+    fn test_untouched_ber_keeps_indefinite_lengths_and_high_tags() {
+        // SEQUENCE (indefinite) { [200] { INTEGER 7 }, OCTET STRING (constructed, indefinite) { 04 01 AA } } EOC
+        let der = vec![
+            0x30, 0x80, 0xBF, 0x81, 0x48, 0x03, 0x02, 0x01, 0x07, 0x24, 0x80, 0x04, 0x01, 0xAA,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        let root = decode_root(&der);
+        let encoded = encode_asn1_tree(&root).unwrap();
+        assert_eq!(encoded, der);
+        assert_eq!(decode_root(&encoded).children, root.children);
+
+        // An edit re-encodes only the edited node and its ancestors
+        let mut edited = root.clone();
+        edited.children[0].children[1].children[0].value = Some("BB".to_string());
+        edited.children[0].children[1].children[0].content_hex = None;
+        let encoded = encode_asn1_tree(&edited).unwrap();
+        assert_eq!(&encoded[2..9], &der[2..9]);
+        assert_eq!(encoded[9..], [0x24, 0x03, 0x04, 0x01, 0xBB]);
+    }
+
+    #[test]
+    fn test_certificate_signature_edit_preserves_tbs_bytes() {
+        let pem = pem::parse(crate::test_data::CERT_PEM).unwrap();
+        let mut root = decode_root(pem.contents());
+
+        let certificate = &mut root.children[0];
+        let tbs = certificate.children[0].clone();
+        certificate.children[2].value = Some("0000 (unused bits: 0)".to_string());
+
+        let der = encode_asn1_tree(&root).unwrap();
+        let tbs_bytes = &pem.contents()[tbs.byte_offset..tbs.byte_offset + tbs.byte_length];
+        assert_eq!(
+            &der[tbs.byte_offset..tbs.byte_offset + tbs.byte_length],
+            tbs_bytes
+        );
+        assert_ne!(der, pem.contents());
+    }
+
+    #[test]
+    fn test_collapsed_node_uses_source() {
+        let der = vec![0x30, 0x05, 0x30, 0x03, 0x02, 0x01, 0x01];
+        let mut root = crate::decoder::decode_der_tree("TEST", &der, Some(0)).unwrap();
+        assert!(root.children[0].collapsed);
+        assert_eq!(encode_asn1_tree(&root).unwrap(), der);
+
+        root.source_hex = None;
+        assert!(encode_asn1_tree(&root).is_err());
+    }
+
+    fn assert_matches_fresh_decode(root: &Asn1Node, der: &[u8]) {
+        let fresh = crate::decoder::decode_der_tree("TEST", der, None).unwrap();
+        assert_eq!(*root, fresh);
+    }
+
+    #[test]
+    fn test_encode_changes_updates_tree_in_place() {
+        let pem = pem::parse(crate::test_data::CERT_PEM).unwrap();
+        let mut root = decode_root(pem.contents());
+
+        // Lengthen the subject common name; the subject, tbs and certificate grow
+        let common_name =
+            &mut root.children[0].children[0].children[5].children[1].children[0].children[1];
+        common_name.value = Some("a-much-longer-name.example.com".to_string());
+        common_name.dirty = true;
+
+        let expected = encode_asn1_tree(&root).unwrap();
+        let der = encode_changes(&mut root).unwrap();
+        assert_eq!(der, expected);
+        assert_matches_fresh_decode(&root, &der);
+
+        // The signature after the edit is copied and shifted
+        let signature = &root.children[0].children[2];
+        let original = &pem.contents()[pem.contents().len() - signature.byte_length..];
+        assert_eq!(
+            &der[signature.byte_offset..signature.byte_offset + signature.byte_length],
+            original
+        );
+    }
+
+    #[test]
+    fn test_encode_changes_with_removed_and_added_children() {
+        // SEQUENCE { INTEGER 1, INTEGER 2 }, INTEGER 3
+        let der = vec![
+            0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02, 0x02, 0x01, 0x03,
+        ];
+        let mut root = decode_root(&der);
+
+        let sequence = &mut root.children[0];
+        sequence.children.remove(0);
+        sequence.children.push(Asn1Node {
+            tag_class: "UNIVERSAL".to_string(),
+            tag_number: 5,
+            dirty: true,
+            ..Default::default()
+        });
+        sequence.dirty = true;
+
+        let der = encode_changes(&mut root).unwrap();
+        assert_eq!(
+            der,
+            vec![0x30, 0x05, 0x02, 0x01, 0x02, 0x05, 0x00, 0x02, 0x01, 0x03]
+        );
+        assert_matches_fresh_decode(&root, &der);
+
+        // Removing a top-level element marks the root
+        root.children.remove(0);
+        root.dirty = true;
+        let der = encode_changes(&mut root).unwrap();
+        assert_eq!(der, vec![0x02, 0x01, 0x03]);
+        assert_matches_fresh_decode(&root, &der);
+    }
+
+    #[test]
+    fn test_encode_changes_reencodes_children_without_a_source_span() {
+        // SEQUENCE { INTEGER 1, INTEGER 2 }
+        let der = vec![0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02];
+        let mut root = decode_root(&der);
+
+        // A new child that was never marked dirty has no span at all
+        let sequence = &mut root.children[0];
+        sequence.children.push(Asn1Node {
+            tag_class: "UNIVERSAL".to_string(),
+            tag_number: 2,
+            value: Some("3".to_string()),
+            ..Default::default()
+        });
+        sequence.dirty = true;
+        let der = encode_changes(&mut root).unwrap();
+        assert_eq!(
+            der,
+            vec![0x30, 0x09, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02, 0x02, 0x01, 0x03]
+        );
+        assert_matches_fresh_decode(&root, &der);
+
+        // A child copied from another document keeps that document's offsets
+        let other = decode_root(&[0x04, 0x01, 0xFF, 0x01, 0x01, 0xFF]);
+        let sequence = &mut root.children[0];
+        sequence.children.insert(0, other.children[1].clone());
+        sequence.dirty = true;
+        let der = encode_changes(&mut root).unwrap();
+        assert_eq!(
+            der,
+            vec![0x30, 0x0C, 0x01, 0x01, 0xFF, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02, 0x02, 0x01, 0x03]
+        );
+        assert_matches_fresh_decode(&root, &der);
+    }
+
+    #[test]
+    fn test_encode_changes_retags_collapsed_node() {
+        // SEQUENCE { SEQUENCE { INTEGER 1 } }
+        let der = vec![0x30, 0x05, 0x30, 0x03, 0x02, 0x01, 0x01];
+        let mut root = crate::decoder::decode_der_tree("TEST", &der, Some(1)).unwrap();
+        root.source_hex = Some(to_hex(&der));
+        let collapsed = &mut root.children[0].children[0];
+        assert!(collapsed.collapsed);
+        collapsed.tag_class = "CONTEXT".to_string();
+        collapsed.tag_number = 40;
+        collapsed.dirty = true;
+
+        let expected = vec![0x30, 0x06, 0xBF, 0x28, 0x03, 0x02, 0x01, 0x01];
+        assert_eq!(encode_asn1_tree(&root).unwrap(), expected);
+        assert_eq!(encode_changes(&mut root).unwrap(), expected);
+        assert_matches_fresh_decode(&root, &expected);
+    }
+
+    #[test]
+    fn test_encode_changes_keeps_indefinite_ancestor() {
+        // [0] (indefinite) { INTEGER 1 } EOC
+        let der = vec![0xA0, 0x80, 0x02, 0x01, 0x01, 0x00, 0x00];
+        let mut root = decode_root(&der);
+        root.children[0].children[0].value = Some("300".to_string());
+        root.children[0].children[0].dirty = true;
+
+        let der = encode_changes(&mut root).unwrap();
+        assert_eq!(der, vec![0xA0, 0x80, 0x02, 0x02, 0x01, 0x2C, 0x00, 0x00]);
+        assert_matches_fresh_decode(&root, &der);
+    }
+
+    #[test]
+    fn test_encode_changes_needs_source() {
+        let mut root = decode_root(&[0x05, 0x00]);
+        root.source_hex = None;
+        assert!(encode_changes(&mut root).is_err());
+        assert!(encode_changes(&mut root.children[0].clone()).is_err());
+    }
+
+    fn encode_canonical(root: &Asn1Node) -> (Vec<u8>, Vec<&'static str>) {
+        let encoded = encode_asn1_tree_with_options(
+            root,
+            &EncodeOptions {
+                canonical: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        (
+            encoded.der,
+            encoded.normalizations.iter().map(|n| n.rule).collect(),
+        )
+    }
+
+    #[test]
+    fn test_canonical_der_input_is_unchanged() {
+        let pem = pem::parse(crate::test_data::CERT_PEM).unwrap();
+        let root = decode_root(pem.contents());
+        let (der, rules) = encode_canonical(&root);
+        assert_eq!(der, pem.contents());
+        assert!(rules.is_empty(), "{:?}", rules);
+    }
+
+    #[test]
+    fn test_canonical_sorts_set_and_minimizes_lengths() {
+        // SET (long-form length) { INTEGER 2, INTEGER 1 }
+        let root = decode_root(&[0x31, 0x81, 0x06, 0x02, 0x01, 0x02, 0x02, 0x01, 0x01]);
+        assert_eq!(
+            encode_asn1_tree(&root).unwrap(),
+            [0x31, 0x81, 0x06, 0x02, 0x01, 0x02, 0x02, 0x01, 0x01]
+        );
+
+        let (der, rules) = encode_canonical(&root);
+        assert_eq!(der, [0x31, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02]);
+        assert_eq!(rules, ["set_order", "length"]);
+
+        // Indefinite lengths become definite
+        let root = decode_root(&[0x30, 0x80, 0x05, 0x00, 0x00, 0x00]);
+        let (der, rules) = encode_canonical(&root);
+        assert_eq!(der, [0x30, 0x02, 0x05, 0x00]);
+        assert_eq!(rules, ["length"]);
+    }
+
+    #[test]
+    fn test_canonical_rules_ignore_class_case() {
+        // SET { INTEGER 2, INTEGER with a redundant leading octet }
+        let mut root = decode_root(&[0x31, 0x07, 0x02, 0x01, 0x02, 0x02, 0x02, 0x00, 0x01]);
+        let set = &mut root.children[0];
+        set.tag_class = "Universal".to_string();
+        set.children[1].tag_class = "universal".to_string();
+
+        let (der, rules) = encode_canonical(&root);
+        assert_eq!(der, [0x31, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02]);
+        assert_eq!(rules, ["integer", "set_order"]);
+    }
+
+    #[test]
+    fn test_canonical_integers() {
+        // INTEGER with a redundant leading octet, BOOLEAN TRUE as 0x01
+        let mut root = decode_root(&[0x30, 0x07, 0x02, 0x02, 0x00, 0x01, 0x01, 0x01, 0x01]);
+        let (der, rules) = encode_canonical(&root);
+        assert_eq!(der, [0x30, 0x06, 0x02, 0x01, 0x01, 0x01, 0x01, 0xFF]);
+        assert_eq!(rules, ["integer", "boolean"]);
+
+        root.children[0].children[0].value = Some("0x000001".to_string());
+        assert_eq!(encode_canonical(&root).0[2..5], [0x02, 0x01, 0x01]);
+    }
+
+    #[test]
+    fn test_canonical_bit_strings() {
+        // BIT STRING with 5 unused bits that are set
+        let root = decode_root(&[0x03, 0x02, 0x05, 0xFF]);
+        let (der, rules) = encode_canonical(&root);
+        assert_eq!(der, [0x03, 0x02, 0x05, 0xE0]);
+        assert_eq!(rules, ["bit_string"]);
+
+        // Named bit list with trailing zero bits (e.g. KeyUsage digitalSignature)
+        let mut root = decode_root(&[0x03, 0x03, 0x00, 0x80, 0x00]);
+        assert!(encode_canonical(&root).1.is_empty());
+        root.children[0].named_bits = true;
+        let (der, rules) = encode_canonical(&root);
+        assert_eq!(der, [0x03, 0x02, 0x07, 0x80]);
+        assert_eq!(rules, ["named_bits"]);
+
+        let mut root = decode_root(&[0x03, 0x02, 0x00, 0x00]);
+        root.children[0].named_bits = true;
+        assert_eq!(encode_canonical(&root).0, [0x03, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn test_canonical_expands_collapsed_nodes() {
+        let der = [0x31, 0x06, 0x02, 0x01, 0x02, 0x02, 0x01, 0x01];
+        let root = crate::decoder::decode_der_tree("TEST", &der, Some(0)).unwrap();
+        assert!(root.children[0].collapsed);
+        let (der, rules) = encode_canonical(&root);
+        assert_eq!(der, [0x31, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02]);
+        assert_eq!(rules, ["set_order"]);
+    }
+
+    fn encode_with_profile(root: &Asn1Node, profile: Profile) -> Vec<u8> {
+        encode_asn1_tree_with_options(
+            root,
+            &EncodeOptions {
+                profile,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .der
+    }
+
+    #[test]
+    fn test_ber_indefinite_profile() {
+        // SEQUENCE { SEQUENCE { INTEGER 1 } }
+        let mut root = decode_root(&[0x30, 0x05, 0x30, 0x03, 0x02, 0x01, 0x01]);
+        assert_eq!(
+            encode_with_profile(&root, Profile::BerIndefinite),
+            [0x30, 0x80, 0x30, 0x80, 0x02, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00]
+        );
+
+        // A per-node profile applies to that subtree only
+        root.children[0].children[0].profile = Some(Profile::BerIndefinite);
+        assert_eq!(
+            encode_with_profile(&root, Profile::Der),
+            [0x30, 0x07, 0x30, 0x80, 0x02, 0x01, 0x01, 0x00, 0x00]
+        );
+        root.children[0].profile = Some(Profile::Der);
+        root.children[0].children[0].profile = None;
+        assert_eq!(
+            encode_with_profile(&root, Profile::BerIndefinite),
+            [0x30, 0x05, 0x30, 0x03, 0x02, 0x01, 0x01]
+        );
+    }
+
+    #[test]
+    fn test_cer_segments_long_strings() {
+        let octets = vec![0xAB; 2500];
+        let mut der = vec![0x04, 0x82, 0x09, 0xC4];
+        der.extend_from_slice(&octets);
+        let root = decode_root(&der);
+
+        let cer = encode_with_profile(&root, Profile::Cer);
+        assert_eq!(cer[..6], [0x24, 0x80, 0x04, 0x82, 0x03, 0xE8]);
+        assert_eq!(cer[cer.len() - 2..], [0x00, 0x00]);
+
+        let decoded = crate::decoder::decode_der_tree("TEST", &cer, None).unwrap();
+        let segments: Vec<usize> = decoded.children[0]
+            .children
+            .iter()
+            .map(|segment| segment.length)
+            .collect();
+        assert_eq!(segments, [1000, 1000, 500]);
+
+        // The class name is not case-sensitive
+        let mut root = root;
+        root.children[0].tag_class = "Universal".to_string();
+        assert_eq!(encode_with_profile(&root, Profile::Cer), cer);
+
+        // Short strings stay primitive
+        let root = decode_root(&[0x30, 0x03, 0x04, 0x01, 0xAB]);
+        assert_eq!(
+            encode_with_profile(&root, Profile::Cer),
+            [0x30, 0x80, 0x04, 0x01, 0xAB, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_cer_segments_bit_strings() {
+        // 2000 data octets with 4 unused bits
+        let mut der = vec![0x03, 0x82, 0x07, 0xD1, 0x04];
+        der.extend_from_slice(&[0xF0; 2000]);
+        let root = decode_root(&der);
+
+        let cer = encode_with_profile(&root, Profile::Cer);
+        let decoded = crate::decoder::decode_der_tree("TEST", &cer, None).unwrap();
+        let segments = &decoded.children[0].children;
+        let lengths: Vec<usize> = segments.iter().map(|segment| segment.length).collect();
+        assert_eq!(lengths, [1000, 1000, 3]);
+        assert!(segments.iter().all(|segment| segment.tag_number == 3));
+        let unused: Vec<&str> = segments
+            .iter()
+            .map(|segment| &segment.content_hex.as_deref().unwrap()[..2])
+            .collect();
+        assert_eq!(unused, ["00", "00", "04"]);
+    }
+
+    fn raw_node(json: &str) -> Asn1Node {
+        Asn1Node::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn test_raw_overrides() {
+        // Wrong length, high tag number identifier, raw content
+        let node = raw_node(
+            r#"{"tag_class": "UNIVERSAL", "tag_number": 2, "is_constructed": false, "value": "1",
+                "raw": {"identifier_hex": "1F 81 00", "length": 5}}"#,
+        );
+        assert_eq!(
+            encode_asn1_tree(&node).unwrap(),
+            [0x1F, 0x81, 0x00, 0x05, 0x01]
+        );
+
+        // Forced long form, padded length octets and trailing garbage
+        let node = raw_node(
+            r#"{"tag_class": "UNIVERSAL", "tag_number": 4, "is_constructed": false,
+                "raw": {"content_hex": "AABB", "long_form_octets": 3, "trailing_hex": "DEAD"}}"#,
+        );
+        assert_eq!(
+            encode_asn1_tree(&node).unwrap(),
+            [0x04, 0x83, 0x00, 0x00, 0x02, 0xAA, 0xBB, 0xDE, 0xAD]
+        );
+
+        let node = raw_node(
+            r#"{"tag_class": "UNIVERSAL", "tag_number": 5, "is_constructed": false,
+                "raw": {"length_form": "long"}}"#,
+        );
+        assert_eq!(encode_asn1_tree(&node).unwrap(), [0x05, 0x81, 0x00]);
+
+        // Indefinite length on a primitive
+        let node = raw_node(
+            r#"{"tag_class": "UNIVERSAL", "tag_number": 4, "is_constructed": false,
+                "raw": {"content_hex": "01", "length_form": "indefinite"}}"#,
+        );
+        assert_eq!(
+            encode_asn1_tree(&node).unwrap(),
+            [0x04, 0x80, 0x01, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_raw_override_inside_a_decoded_tree() {
+        // SEQUENCE { INTEGER 1, INTEGER 2 }: the parent's length covers the bytes emitted
+        let mut root = decode_root(&[0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02]);
+        root.children[0].children[0].raw = Some(RawOverrides {
+            length: Some(0x7F),
+            trailing_hex: Some("00".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            encode_asn1_tree(&root).unwrap(),
+            [0x30, 0x07, 0x02, 0x7F, 0x01, 0x00, 0x02, 0x01, 0x02]
+        );
+
+        // Overrides apply to collapsed nodes too
+        let mut root = decode_root(&[0x30, 0x03, 0x02, 0x01, 0x01]);
+        root.children[0].collapsed = true;
+        root.children[0].children.clear();
+        root.children[0].raw = Some(RawOverrides {
+            identifier_hex: Some("31".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            encode_asn1_tree(&root).unwrap(),
+            [0x31, 0x03, 0x02, 0x01, 0x01]
+        );
+    }
+
+    #[test]
+    fn test_raw_override_errors() {
+        let node = raw_node(
+            r#"{"tag_class": "UNIVERSAL", "tag_number": 4, "is_constructed": false,
+                "raw": {"content_hex": "ABC"}}"#,
+        );
+        assert!(encode_asn1_tree(&node).is_err());
+
+        let node = raw_node(
+            r#"{"tag_class": "UNIVERSAL", "tag_number": 4, "is_constructed": false,
+                "raw": {"length": 300, "long_form_octets": 1}}"#,
+        );
+        assert!(encode_asn1_tree(&node).is_err());
+
+        let node = raw_node(
+            r#"{"tag_class": "UNIVERSAL", "tag_number": 4, "is_constructed": false,
+                "raw": {"length": 300, "length_form": "short"}}"#,
+        );
+        assert!(encode_asn1_tree(&node).is_err());
+    }
+
+    #[test]
+    fn test_errors_name_every_failing_node() {
+        // SEQUENCE { INTEGER bad, SEQUENCE { OCTET STRING bad content_hex, bad class } }
+        let root = Asn1Node::from_json(
+            r#"{"tag_class": "PEM", "tag_number": 0, "is_constructed": true, "children": [
+                {"path": "0", "label": "SEQUENCE", "tag_class": "UNIVERSAL", "tag_number": 16, "is_constructed": true, "children": [
+                    {"path": "0.0", "label": "INTEGER", "tag_class": "UNIVERSAL", "tag_number": 2, "is_constructed": false, "value": "12x"},
+                    {"path": "0.1", "label": "SEQUENCE", "tag_class": "UNIVERSAL", "tag_number": 16, "is_constructed": true, "children": [
+                        {"path": "0.1.0", "label": "OCTET STRING", "tag_class": "UNIVERSAL", "tag_number": 4, "is_constructed": false, "content_hex": "ZZ"},
+                        {"path": "0.1.1", "label": "[31]", "tag_class": "NOWHERE", "tag_number": 31, "is_constructed": false}
+                    ]},
+                    {"path": "0.2", "label": "NULL", "tag_class": "UNIVERSAL", "tag_number": 5, "is_constructed": false}
+                ]}
+            ]}"#,
+        )
+        .unwrap();
+
+        let error = encode_asn1_tree(&root).unwrap_err();
+        let located: Vec<(&str, &str, &str)> = error
+            .node_errors()
+            .iter()
+            .map(|e| (e.path.as_str(), e.field, e.kind))
+            .collect();
+        assert_eq!(
+            located,
+            [
+                ("0.0", "value", "invalid_value"),
+                ("0.1.0", "content_hex", "invalid_value"),
+                ("0.1.1", "tag_class", "invalid_tag"),
+            ]
+        );
+        let integer = &error.node_errors()[0];
+        assert_eq!(
+            (
+                integer.label.as_str(),
+                integer.tag_class.as_str(),
+                integer.tag_number
+            ),
+            ("INTEGER", "UNIVERSAL", 2)
+        );
+        assert!(error
+            .to_string()
+            .contains("Invalid integer value: 12x at 0.0 (INTEGER), field value"));
+    }
+
+    #[test]
+    fn test_errors_from_raw_overrides_and_encode_changes() {
+        let mut root = decode_root(&[0x30, 0x03, 0x02, 0x01, 0x01]);
+        root.children[0].children[0].raw = Some(RawOverrides {
+            trailing_hex: Some("0".to_string()),
+            ..Default::default()
+        });
+        let error = encode_asn1_tree(&root).unwrap_err();
+        assert_eq!(error.node_errors()[0].field, "raw.trailing_hex");
+        assert_eq!(error.node_errors()[0].path, "0.0");
+
+        let mut root = decode_root(&[0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02]);
+        for child in &mut root.children[0].children {
+            child.value = Some("nope".to_string());
+            child.dirty = true;
+        }
+        let error = encode_changes(&mut root).unwrap_err();
+        let paths: Vec<&str> = error
+            .node_errors()
+            .iter()
+            .map(|e| e.path.as_str())
+            .collect();
+        assert_eq!(paths, ["0.0", "0.1"]);
+    }
+
+    #[test]
+    fn test_encode_changes_is_all_or_nothing() {
+        // SEQUENCE { [APPLICATION 100] { INTEGER 1 }, INTEGER 2 }
+        let der = vec![
+            0x30, 0x09, 0x7F, 0x64, 0x03, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02,
+        ];
+        let mut root = decode_root(&der);
+        let application = &mut root.children[0].children[0];
+        application.dirty = true;
+        application.children[0].value = Some("300".to_string());
+        application.children[0].dirty = true;
+        root.children[0].children[1].value = Some("nope".to_string());
+        root.children[0].children[1].dirty = true;
+
+        let before = root.clone();
+        assert!(encode_changes(&mut root).is_err());
+        assert_eq!(root, before);
+
+        // Once the bad edit is undone, the high-tag node is re-encoded with its identifier
+        root.children[0].children[1].value = Some("2".to_string());
+        let der = encode_changes(&mut root).unwrap();
+        assert_eq!(
+            der,
+            vec![0x30, 0x0A, 0x7F, 0x64, 0x04, 0x02, 0x02, 0x01, 0x2C, 0x02, 0x01, 0x02]
+        );
+        assert_matches_fresh_decode(&root, &der);
+    }
 }
@@ -0,0 +1,342 @@
+use crate::decoder::{decode_pem_tree, get_universal_tag_name};
+use crate::encoder::hex_to_bytes;
+use crate::path::{find_node, resolve_offset};
+use crate::tlv::{read_identifier, TagClass};
+use crate::{Asn1Node, LengthForm};
+use serde::Serialize;
+
+/// One group of bits inside an identifier or length octet
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BitField {
+    pub name: String,
+    pub bits: String,
+    pub meaning: String,
+}
+
+/// Bit-level explanation of a node's identifier or length octets
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ExplanationRecord {
+    /// "identifier" or "length"
+    pub part: &'static str,
+    pub byte_offset: usize,
+    pub octets: String,
+    pub fields: Vec<BitField>,
+    /// e.g. "0x30 = class UNIVERSAL (00), constructed (1), tag 16 SEQUENCE"
+    pub summary: String,
+}
+
+/// Explain the node at `path` (e.g. "0.0.3") as JSON records
+pub fn explain_path_internal(
+    pem_input: &str,
+    path: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let root = decode_pem_tree(pem_input)?;
+    Ok(serde_json::to_string_pretty(&explain_node(find_node(
+        &root, path,
+    )?)?)?)
+}
+
+/// Explain the innermost node containing the byte at `byte_offset` as JSON records
+pub fn explain_offset_internal(
+    pem_input: &str,
+    byte_offset: usize,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let root = decode_pem_tree(pem_input)?;
+    let node = node_at_offset(&root, byte_offset)
+        .ok_or_else(|| format!("No node contains byte offset {}", byte_offset))?;
+    Ok(serde_json::to_string_pretty(&explain_node(node)?)?)
+}
+
+/// Explain the identifier and length octets of a decoded node
+pub fn explain_node(node: &Asn1Node) -> Result<Vec<ExplanationRecord>, Box<dyn std::error::Error>> {
+    let Some(length_form) = node.length_form else {
+        return Err("The PEM root has no identifier or length octets".into());
+    };
+
+    let identifier = hex_to_bytes(&node.identifier_octets)?;
+    let length = hex_to_bytes(&node.length_octets)?;
+
+    Ok(vec![
+        explain_identifier(&identifier, node.byte_offset)?,
+        explain_length(&length, length_form, node.byte_offset + identifier.len()),
+    ])
+}
+
+fn explain_identifier(
+    octets: &[u8],
+    byte_offset: usize,
+) -> Result<ExplanationRecord, Box<dyn std::error::Error>> {
+    let (tag, _) = read_identifier(octets)?;
+    let tag_number = tag.number;
+    let first = octets[0];
+
+    let class_name = tag.class.name();
+    let class_bits = format!("{:02b}", tag.class.bits());
+    let constructed_bit = if tag.constructed { "1" } else { "0" };
+    let constructed_name = if tag.constructed {
+        "constructed"
+    } else {
+        "primitive"
+    };
+    let tag_name = if tag.class == TagClass::Universal {
+        format!("tag {} {}", tag_number, get_universal_tag_name(tag_number))
+    } else {
+        format!("tag {}", tag_number)
+    };
+
+    let mut fields = vec![
+        BitField {
+            name: "class".to_string(),
+            bits: class_bits.clone(),
+            meaning: class_name.to_string(),
+        },
+        BitField {
+            name: "constructed".to_string(),
+            bits: constructed_bit.to_string(),
+            meaning: constructed_name.to_string(),
+        },
+    ];
+
+    let summary = if octets.len() == 1 {
+        fields.push(BitField {
+            name: "tag number".to_string(),
+            bits: format!("{:05b}", first & 0x1F),
+            meaning: tag_number.to_string(),
+        });
+        format!(
+            "{} = class {} ({}), {} ({}), {}",
+            format_octets(octets),
+            class_name,
+            class_bits,
+            constructed_name,
+            constructed_bit,
+            tag_name
+        )
+    } else {
+        fields.push(BitField {
+            name: "tag number".to_string(),
+            bits: "11111".to_string(),
+            meaning: "high-tag-number form".to_string(),
+        });
+        for (i, &octet) in octets[1..].iter().enumerate() {
+            let more = octet & 0x80 != 0;
+            fields.push(BitField {
+                name: format!("tag number octet {}", i + 1),
+                bits: format!("{} {:07b}", if more { 1 } else { 0 }, octet & 0x7F),
+                meaning: if more {
+                    "more octets follow"
+                } else {
+                    "last octet"
+                }
+                .to_string(),
+            });
+        }
+        format!(
+            "{} = class {} ({}), {} ({}), high-tag-number form, {}",
+            format_octets(octets),
+            class_name,
+            class_bits,
+            constructed_name,
+            constructed_bit,
+            tag_name
+        )
+    };
+
+    Ok(ExplanationRecord {
+        part: "identifier",
+        byte_offset,
+        octets: format_octets(octets),
+        fields,
+        summary,
+    })
+}
+
+fn explain_length(octets: &[u8], length_form: LengthForm, byte_offset: usize) -> ExplanationRecord {
+    let first = octets.first().copied().unwrap_or(0);
+    let mut fields = Vec::new();
+
+    let summary = match length_form {
+        LengthForm::Short => {
+            fields.push(BitField {
+                name: "form".to_string(),
+                bits: "0".to_string(),
+                meaning: "short".to_string(),
+            });
+            fields.push(BitField {
+                name: "length".to_string(),
+                bits: format!("{:07b}", first & 0x7F),
+                meaning: first.to_string(),
+            });
+            format!("{} = short form, {}", format_octets(octets), first)
+        }
+        LengthForm::Long => {
+            let count = octets.len() - 1;
+            let length = octets[1..]
+                .iter()
+                .fold(0u64, |acc, &b| (acc << 8) | b as u64);
+            fields.push(BitField {
+                name: "form".to_string(),
+                bits: "1".to_string(),
+                meaning: "long".to_string(),
+            });
+            fields.push(BitField {
+                name: "length octet count".to_string(),
+                bits: format!("{:07b}", first & 0x7F),
+                meaning: count.to_string(),
+            });
+            for (i, &octet) in octets[1..].iter().enumerate() {
+                fields.push(BitField {
+                    name: format!("length octet {}", i + 1),
+                    bits: format!("{:08b}", octet),
+                    meaning: octet.to_string(),
+                });
+            }
+            format!(
+                "{} = long form, {} length octet{}, {}",
+                format_octets(octets),
+                count,
+                if count == 1 { "" } else { "s" },
+                length
+            )
+        }
+        LengthForm::Indefinite => {
+            fields.push(BitField {
+                name: "form".to_string(),
+                bits: "1".to_string(),
+                meaning: "long".to_string(),
+            });
+            fields.push(BitField {
+                name: "length octet count".to_string(),
+                bits: "0000000".to_string(),
+                meaning: "indefinite".to_string(),
+            });
+            format!(
+                "{} = indefinite form, content ends with end-of-contents octets 0x00 0x00",
+                format_octets(octets)
+            )
+        }
+    };
+
+    ExplanationRecord {
+        part: "length",
+        byte_offset,
+        octets: format_octets(octets),
+        fields,
+        summary,
+    }
+}
+
+/// Find the innermost node containing the byte at the given offset
+pub fn node_at_offset(root: &Asn1Node, byte_offset: usize) -> Option<&Asn1Node> {
+    let location = resolve_offset(root, byte_offset)?;
+    find_node(root, &location.path).ok()
+}
+
+fn format_octets(octets: &[u8]) -> String {
+    octets
+        .iter()
+        .map(|b| format!("0x{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode_der_recursive;
+
+    fn decode(der: &[u8]) -> Asn1Node {
+        let mut root = Asn1Node {
+            tag_class: "PEM".to_string(),
+            ..Default::default()
+        };
+        decode_der_recursive(der, &mut root.children, 0).unwrap();
+        crate::path::assign_paths(&mut root, "");
+        root
+    }
+
+    #[test]
+    fn test_explain_sequence_long_length() {
+        // SEQUENCE of 145 NULLs (290 content bytes)
+        let mut der = vec![0x30, 0x82, 0x01, 0x22];
+        der.extend([0x05, 0x00].repeat(145));
+        let root = decode(&der);
+
+        let records = explain_node(&root.children[0]).unwrap();
+        assert_eq!(
+            records[0].summary,
+            "0x30 = class UNIVERSAL (00), constructed (1), tag 16 SEQUENCE"
+        );
+        assert_eq!(
+            records[1].summary,
+            "0x82 0x01 0x22 = long form, 2 length octets, 290"
+        );
+        assert_eq!(records[1].byte_offset, 1);
+        assert_eq!(records[1].fields.len(), 4);
+    }
+
+    #[test]
+    fn test_explain_short_and_context() {
+        // [0] { INTEGER 5 }
+        let root = decode(&[0xA0, 0x03, 0x02, 0x01, 0x05]);
+
+        let records = explain_node(&root.children[0]).unwrap();
+        assert_eq!(
+            records[0].summary,
+            "0xA0 = class CONTEXT (10), constructed (1), tag 0"
+        );
+        assert_eq!(records[1].summary, "0x03 = short form, 3");
+
+        let integer = node_at_offset(&root, 2).unwrap();
+        let records = explain_node(integer).unwrap();
+        assert_eq!(
+            records[0].summary,
+            "0x02 = class UNIVERSAL (00), primitive (0), tag 2 INTEGER"
+        );
+
+        // Any byte of an element resolves to it, not just the first
+        assert_eq!(node_at_offset(&root, 4).unwrap().path, integer.path);
+        assert_eq!(
+            node_at_offset(&root, 1).unwrap().path,
+            root.children[0].path
+        );
+    }
+
+    #[test]
+    fn test_explain_high_tag_and_indefinite() {
+        // [CONTEXT 200] constructed, indefinite { NULL } EOC
+        let root = decode(&[0xBF, 0x81, 0x48, 0x80, 0x05, 0x00, 0x00, 0x00]);
+
+        let records = explain_node(&root.children[0]).unwrap();
+        assert_eq!(
+            records[0].summary,
+            "0xBF 0x81 0x48 = class CONTEXT (10), constructed (1), high-tag-number form, tag 200"
+        );
+        assert!(records[1].summary.starts_with("0x80 = indefinite form"));
+    }
+
+    #[test]
+    fn test_explain_pem_root_rejected() {
+        let root = decode(&[0x05, 0x00]);
+        assert!(explain_node(&root).is_err());
+        assert!(node_at_offset(&root, 2).is_none());
+    }
+
+    #[test]
+    fn test_explain_path_internal() {
+        // SEQUENCE { INTEGER 1 }
+        let pem_str = "-----BEGIN TEST-----\nMAMCAQE=\n-----END TEST-----";
+
+        let json = explain_path_internal(pem_str, "0.0").unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[0]["part"], "identifier");
+        assert_eq!(parsed[1]["summary"], "0x01 = short form, 1");
+
+        assert!(explain_path_internal(pem_str, "0.5").is_err());
+        assert_eq!(
+            explain_offset_internal(pem_str, 3).unwrap(),
+            explain_path_internal(pem_str, "0.0").unwrap()
+        );
+        assert!(explain_offset_internal(pem_str, 5).is_err());
+    }
+}
@@ -1,25 +1,37 @@
 use wasm_bindgen::prelude::*;
-use serde::{Deserialize, Serialize};
 
+pub mod ascii;
+pub mod asn1parse;
+pub mod author;
 mod encoder;
 mod decoder;
+pub mod diff;
+mod document;
+pub mod edit;
+mod explain;
+pub mod mutate;
+mod node;
+mod openssl_objects;
+pub mod path;
+pub mod query;
+pub mod script;
+pub mod stream;
+pub mod tlv;
+#[cfg(test)]
+mod test_data;
 
-use encoder::{encode_asn1_tree, Asn1Node as EncoderNode};
+pub use node::{Asn1Node, LengthForm, Profile, RawOverrides, SCHEMA_VERSION};
+pub use decoder::{decode_der_recursive, decode_der_tree, decode_pem_tree, decode_tlv, decode_tlv_to_depth};
+pub use document::Document;
+pub use encoder::{encode_asn1_tree, encode_asn1_tree_with_options, EncodeError, EncodeOptions, Encoded, NodeError, Normalization};
+use encoder::encode_changes;
 use decoder::decode_pem_internal;
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Asn1Node {
-    pub label: String,
-    pub tag: u8,
-    pub tag_class: String,
-    pub tag_number: u32,
-    pub is_constructed: bool,
-    pub length: usize,
-    pub value: Option<String>,
-    pub children: Vec<Asn1Node>,
-    pub byte_offset: usize,
-    pub byte_length: usize,
-}
+use explain::{explain_offset_internal, explain_path_internal};
+use query::query_internal;
+use diff::diff_internal;
+use script::apply_script_internal;
+use mutate::mutate_internal;
+use asn1parse::asn1parse_internal;
 
 #[wasm_bindgen]
 pub fn decode_pem_to_json(pem_input: &str) -> Result<String, JsValue> {
@@ -43,14 +55,339 @@ pub fn pem_to_hex(pem_input: &str) -> Result<String, JsValue> {
     }
 }
 
+/// Encoding errors are thrown as JSON `{error, errors}`, where `errors` lists
+/// every node that failed with its path, label, tag, field and message
+fn encode_error_to_js(e: EncodeError) -> JsValue {
+    let json = serde_json::json!({
+        "error": format!("Failed to encode ASN.1: {}", e),
+        "errors": e.node_errors(),
+    });
+    JsValue::from_str(&json.to_string())
+}
+
 #[wasm_bindgen]
 pub fn encode_asn1_to_pem(json_str: &str, label: &str) -> Result<String, JsValue> {
-    let root: EncoderNode = serde_json::from_str(json_str)
+    let root = Asn1Node::from_json(json_str)
         .map_err(|e| JsValue::from_str(&format!("Failed to parse JSON: {}", e)))?;
     
     let der_bytes = encode_asn1_tree(&root)
-        .map_err(|e| JsValue::from_str(&format!("Failed to encode ASN.1: {}", e)))?;
+        .map_err(encode_error_to_js)?;
     
     let pem = pem::Pem::new(label, der_bytes);
     Ok(pem::encode(&pem))
 }
+
+/// Compile the compact authoring JSON (see `src/author.rs`), e.g.
+/// `{"SEQUENCE": [{"INTEGER": 5}, {"OID": "commonName"}]}`, to PEM
+#[wasm_bindgen]
+pub fn encode_authored_to_pem(json_str: &str, label: &str) -> Result<String, JsValue> {
+    let der = author::compile_authored(json_str)
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))?;
+    Ok(pem::encode(&pem::Pem::new(label, der)))
+}
+
+/// Compile the der-ascii style text format (see `src/ascii.rs`), e.g.
+/// `SEQUENCE { INTEGER { 5 } }`, to PEM
+#[wasm_bindgen]
+pub fn encode_ascii_to_pem(text: &str, label: &str) -> Result<String, JsValue> {
+    let der = ascii::compile_ascii(text)
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))?;
+    Ok(pem::encode(&pem::Pem::new(label, der)))
+}
+
+/// Render a PEM document in the der-ascii style text format; compiling the text
+/// with `encode_ascii_to_pem` gives back the same DER, non-canonical lengths included
+#[wasm_bindgen]
+pub fn pem_to_ascii(pem_input: &str) -> Result<String, JsValue> {
+    let pem = pem::parse(pem_input).map_err(|e| JsValue::from_str(&format!("Error: {}", e)))?;
+    Ok(ascii::render_ascii(pem.contents()))
+}
+
+/// Encode with options given as JSON (e.g. `{"canonical": true}` or
+/// `{"profile": "cer"}`; profiles are der, cer and ber_indefinite), returning
+/// JSON `{pem, normalizations}` listing every change made to reach canonical DER
+#[wasm_bindgen]
+pub fn encode_asn1_to_pem_with_options(json_str: &str, label: &str, options_json: &str) -> Result<String, JsValue> {
+    let root = Asn1Node::from_json(json_str)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse JSON: {}", e)))?;
+    let options: EncodeOptions = serde_json::from_str(options_json)
+        .map_err(|e| JsValue::from_str(&format!("Invalid options: {}", e)))?;
+
+    let encoded = encode_asn1_tree_with_options(&root, &options)
+        .map_err(encode_error_to_js)?;
+
+    let pem = pem::encode(&pem::Pem::new(label, encoded.der));
+    serde_json::to_string(&serde_json::json!({ "pem": pem, "normalizations": encoded.normalizations }))
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+}
+
+/// Re-encode a tree whose edited nodes are marked `dirty`, copying everything
+/// else from `source_hex`. Returns JSON `{pem, tree}` where `tree` already has
+/// the new offsets, so the document does not need to be decoded again.
+#[wasm_bindgen]
+pub fn encode_changes_to_pem(json_str: &str, label: &str) -> Result<String, JsValue> {
+    let mut root = Asn1Node::from_json(json_str)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse JSON: {}", e)))?;
+
+    let der_bytes = encode_changes(&mut root)
+        .map_err(encode_error_to_js)?;
+
+    let pem = pem::encode(&pem::Pem::new(label, der_bytes));
+    serde_json::to_string(&serde_json::json!({ "pem": pem, "tree": root }))
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+}
+
+/// Resolve a byte offset in the decoded buffer to the innermost node path and
+/// the part (identifier, length, content, end_of_contents) it falls in
+#[wasm_bindgen]
+pub fn resolve_byte_offset(pem_input: &str, byte_offset: usize) -> Result<String, JsValue> {
+    let document = Document::from_pem(pem_input)
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))?;
+    let location = document
+        .resolve_offset(byte_offset)
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))?;
+    serde_json::to_string(&location).map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn explain_node_at_path(pem_input: &str, path: &str) -> Result<String, JsValue> {
+    explain_path_internal(pem_input, path)
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn explain_node_at_offset(pem_input: &str, byte_offset: usize) -> Result<String, JsValue> {
+    explain_offset_internal(pem_input, byte_offset)
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+}
+
+/// Select nodes with a selector such as `$extensions OID` or
+/// `UTF8String[value*=example]`, returning the matching paths as a JSON array
+#[wasm_bindgen]
+pub fn query_nodes(pem_input: &str, selector: &str) -> Result<String, JsValue> {
+    query_internal(pem_input, selector)
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+}
+
+/// Compare two PEM documents, returning the added, removed, retagged and
+/// value-changed nodes with their paths and byte ranges as JSON
+#[wasm_bindgen]
+pub fn diff_pem(old_pem: &str, new_pem: &str) -> Result<String, JsValue> {
+    diff_internal(old_pem, new_pem)
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+}
+
+/// Apply a JSON edit script (see `src/script.rs`), returning JSON `{pem, results}`
+/// with the edited document and the outcome of each operation
+#[wasm_bindgen]
+pub fn apply_edit_script(pem_input: &str, script_json: &str) -> Result<String, JsValue> {
+    apply_script_internal(pem_input, script_json)
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+}
+
+/// Generate malformed variants of a PEM document for parser testing. Options are
+/// JSON `{seed, count, kinds, nesting_depth}`; returns JSON `{seed, mutants}`
+/// where each mutant has its kind, target path, a description and `der_hex`
+#[wasm_bindgen]
+pub fn mutate_pem(pem_input: &str, options_json: &str) -> Result<String, JsValue> {
+    mutate_internal(pem_input, options_json)
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+}
+
+/// `openssl asn1parse` output for a PEM document. Options are JSON
+/// `{indent, strparse, offset, length}`, matching `-i`, `-strparse`, `-offset`
+/// and `-length`
+#[wasm_bindgen]
+pub fn asn1parse_pem(pem_input: &str, options_json: &str) -> Result<String, JsValue> {
+    asn1parse_internal(pem_input, options_json)
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+}
+
+/// Streaming decoder for raw DER/BER input too large to hold in memory,
+/// fed chunk by chunk (e.g. from `File.slice`)
+#[wasm_bindgen]
+pub struct StreamingDecoder {
+    inner: stream::StreamDecoder,
+}
+
+#[wasm_bindgen]
+impl StreamingDecoder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> StreamingDecoder {
+        StreamingDecoder { inner: stream::StreamDecoder::new() }
+    }
+
+    /// Feed the next chunk, returning the completed events as a JSON array
+    pub fn push(&mut self, chunk: &[u8]) -> Result<String, JsValue> {
+        let events = self.inner.push(chunk)
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))?;
+        serde_json::to_string(&events)
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+    }
+
+    /// Signal the end of input; fails if an element is incomplete
+    pub fn finish(&mut self) -> Result<(), JsValue> {
+        self.inner.finish()
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+    }
+
+    /// Number of bytes consumed so far
+    pub fn offset(&self) -> usize {
+        self.inner.offset()
+    }
+}
+
+impl Default for StreamingDecoder {
+    fn default() -> Self {
+        StreamingDecoder::new()
+    }
+}
+
+/// A PEM document kept in memory so large trees can be decoded lazily
+#[wasm_bindgen]
+pub struct Asn1Document {
+    inner: Document,
+}
+
+#[wasm_bindgen]
+impl Asn1Document {
+    #[wasm_bindgen(constructor)]
+    pub fn new(pem_input: &str) -> Result<Asn1Document, JsValue> {
+        let inner = Document::from_pem(pem_input)
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))?;
+        Ok(Asn1Document { inner })
+    }
+
+    /// Decode the tree as JSON; constructed nodes at `max_depth` are marked collapsed
+    pub fn decode(&self, max_depth: Option<usize>) -> Result<String, JsValue> {
+        self.inner.decode(max_depth)
+            .and_then(|root| Ok(root.to_json()?))
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+    }
+
+    /// Decode the subtree at `path` as JSON, e.g. when a collapsed node is opened
+    pub fn expand(&self, path: &str, max_depth: Option<usize>) -> Result<String, JsValue> {
+        self.inner.expand(path, max_depth)
+            .and_then(|node| Ok(node.to_json()?))
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+    }
+
+    /// The complete node at `path` as JSON
+    pub fn node(&self, path: &str) -> Result<String, JsValue> {
+        self.inner.node(path)
+            .and_then(|node| Ok(node.to_json()?))
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+    }
+
+    /// The parent of the node at `path` as JSON
+    pub fn parent(&self, path: &str) -> Result<String, JsValue> {
+        self.inner.parent(path)
+            .and_then(|node| Ok(node.to_json()?))
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+    }
+
+    /// The innermost node owning `byte_offset` as JSON `{path, part, byte_offset,
+    /// byte_length}`, where part is identifier, length, content or end_of_contents;
+    /// null when no element contains the byte
+    pub fn resolve_offset(&self, byte_offset: usize) -> Result<String, JsValue> {
+        let location = self
+            .inner
+            .resolve_offset(byte_offset)
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))?;
+        serde_json::to_string(&location).map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+    }
+
+    /// Paths of the nodes matching `selector` as a JSON array
+    pub fn query(&self, selector: &str) -> Result<String, JsValue> {
+        query::Selector::parse(selector)
+            .and_then(|selector| Ok(selector.select(&self.inner.decode(None)?)))
+            .and_then(|paths| Ok(serde_json::to_string(&paths)?))
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+    }
+
+    /// Changes from this document to `other` as JSON
+    pub fn diff(&self, other: &Asn1Document) -> Result<String, JsValue> {
+        diff::diff_documents(&self.inner, &other.inner)
+            .and_then(|changes| Ok(serde_json::to_string(&changes)?))
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+    }
+
+    /// The current DER, including any edits
+    pub fn der(&self) -> Vec<u8> {
+        self.inner.der().to_vec()
+    }
+
+    /// The current document as PEM, including any edits
+    pub fn to_pem(&self) -> String {
+        self.inner.to_pem()
+    }
+
+    /// The current document in the der-ascii style text format
+    pub fn to_ascii(&self) -> String {
+        ascii::render_ascii(self.inner.der())
+    }
+
+    /// The current document as `openssl asn1parse` would print it, with options
+    /// as for `asn1parse_pem`
+    pub fn to_asn1parse(&self, options_json: &str) -> Result<String, JsValue> {
+        let options: asn1parse::Asn1ParseOptions = serde_json::from_str(options_json)
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))?;
+        let output = asn1parse::asn1parse(self.inner.der(), &options)
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Set the value of the primitive node at `path`; edits return JSON
+    /// `{path, nodes, size_delta}` with the new offsets of the node and its ancestors
+    pub fn set_value(&mut self, path: &str, value: &str) -> Result<String, JsValue> {
+        edit_to_json(self.inner.set_value(path, value))
+    }
+
+    /// Change the class and tag number of the node at `path`
+    pub fn set_tag(&mut self, path: &str, tag_class: &str, tag_number: u32) -> Result<String, JsValue> {
+        edit_to_json(self.inner.set_tag(path, tag_class, tag_number))
+    }
+
+    /// Wrap the node at `path` in a constructed element with the given tag
+    pub fn wrap(&mut self, path: &str, tag_class: &str, tag_number: u32) -> Result<String, JsValue> {
+        edit_to_json(self.inner.wrap(path, tag_class, tag_number))
+    }
+
+    /// Remove the node at `path`
+    pub fn delete_node(&mut self, path: &str) -> Result<String, JsValue> {
+        edit_to_json(self.inner.delete(path))
+    }
+
+    /// Insert the node given as JSON as child `index` of `parent_path`
+    pub fn insert_child(&mut self, parent_path: &str, index: usize, node_json: &str) -> Result<String, JsValue> {
+        let node = Asn1Node::from_json(node_json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse JSON: {}", e)))?;
+        edit_to_json(self.inner.insert_child(parent_path, index, &node))
+    }
+
+    /// Move the node at `path` to child `index` of `new_parent_path`, both counted
+    /// in the tree before the move
+    pub fn move_node(&mut self, path: &str, new_parent_path: &str, index: usize) -> Result<String, JsValue> {
+        edit_to_json(self.inner.move_node(path, new_parent_path, index))
+    }
+
+    /// Apply a JSON edit script, returning the outcome of each operation as JSON
+    pub fn apply_script(&mut self, script_json: &str) -> Result<String, JsValue> {
+        script::parse_script(script_json)
+            .map(|script| script::apply_script(&mut self.inner, &script))
+            .and_then(|results| Ok(serde_json::to_string(&results)?))
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+    }
+
+    /// The encoded bytes of the node at `path`
+    pub fn raw_bytes(&self, path: &str) -> Result<Vec<u8>, JsValue> {
+        self.inner.raw_bytes(path)
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+    }
+}
+
+fn edit_to_json(edit: Result<edit::Edit, Box<dyn std::error::Error>>) -> Result<String, JsValue> {
+    edit.and_then(|edit| Ok(serde_json::to_string(&edit)?))
+        .map_err(|e| JsValue::from_str(&format!("Error: {}", e)))
+}
@@ -0,0 +1,486 @@
+//! Systematically broken variants of a valid document, for testing parsers.
+//!
+//! Each mutant changes one node of the decoded tree (through its `raw`
+//! overrides, see [`crate::RawOverrides`]) and is re-encoded with
+//! [`encode_asn1_tree`], so everything outside the mutated node keeps its
+//! original encoding and only the enclosing lengths are adjusted. Mutant `i` of
+//! a given seed is always the same, whatever `count` is.
+
+use crate::decoder::{decode_pem_tree, to_hex};
+use crate::encoder::{encode_asn1_tree, hex_to_bytes};
+use crate::node::is_universal;
+use crate::path::find_node_mut;
+use crate::{Asn1Node, LengthForm, RawOverrides};
+use serde::{Deserialize, Serialize, Serializer};
+use std::error::Error;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MutationKind {
+    /// Cut the document off inside the node
+    Truncate,
+    /// Claim one content octet more or less than there is
+    LengthOffByOne,
+    /// Change the class bits of the identifier
+    TagClassFlip,
+    /// Toggle the constructed bit of the identifier
+    ConstructedBitFlip,
+    /// Wrap the node in deeply nested indefinite-length SEQUENCEs
+    NestingBomb,
+    /// Claim a length far beyond the end of the document
+    HugeLength,
+    /// Append an OBJECT IDENTIFIER arc that does not fit in 32 or 64 bits
+    OidOverflowArc,
+}
+
+const ALL_KINDS: [MutationKind; 7] = [
+    MutationKind::Truncate,
+    MutationKind::LengthOffByOne,
+    MutationKind::TagClassFlip,
+    MutationKind::ConstructedBitFlip,
+    MutationKind::NestingBomb,
+    MutationKind::HugeLength,
+    MutationKind::OidOverflowArc,
+];
+
+/// Options for `mutate`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MutationOptions {
+    pub seed: u64,
+    /// Number of mutants to generate
+    pub count: usize,
+    /// Kinds to choose from; all of them when empty
+    pub kinds: Vec<MutationKind>,
+    /// Levels of nesting added by a nesting bomb. Beyond `tlv::MAX_DEPTH` the
+    /// decoder itself rejects the mutant.
+    pub nesting_depth: usize,
+}
+
+impl Default for MutationOptions {
+    fn default() -> Self {
+        MutationOptions {
+            seed: 0,
+            count: 100,
+            kinds: Vec::new(),
+            nesting_depth: 200,
+        }
+    }
+}
+
+/// One broken variant and the manifest entry describing it
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Mutant {
+    pub index: usize,
+    pub kind: MutationKind,
+    /// Path and label of the mutated node in the original document
+    pub path: String,
+    pub label: String,
+    pub detail: String,
+    #[serde(rename = "der_hex", serialize_with = "serialize_hex")]
+    pub der: Vec<u8>,
+}
+
+fn serialize_hex<S: Serializer>(der: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&to_hex(der))
+}
+
+/// SplitMix64, so a seed gives the same corpus on every platform
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+fn applies_to(kind: MutationKind, node: &Asn1Node) -> bool {
+    let definite = node.length_form != Some(LengthForm::Indefinite);
+    match kind {
+        MutationKind::Truncate => node.byte_length > 0,
+        MutationKind::LengthOffByOne | MutationKind::HugeLength => definite,
+        MutationKind::TagClassFlip
+        | MutationKind::ConstructedBitFlip
+        | MutationKind::NestingBomb => true,
+        MutationKind::OidOverflowArc => is_universal(node, 6) && node.content_hex.is_some(),
+    }
+}
+
+fn collect_nodes<'a>(node: &'a Asn1Node, nodes: &mut Vec<&'a Asn1Node>) {
+    for child in &node.children {
+        nodes.push(child);
+        collect_nodes(child, nodes);
+    }
+}
+
+/// Generate `options.count` mutants of a decoded document
+pub fn mutate(root: &Asn1Node, options: &MutationOptions) -> Result<Vec<Mutant>, Box<dyn Error>> {
+    let original = encode_asn1_tree(root)?;
+    let mut nodes = Vec::new();
+    collect_nodes(root, &mut nodes);
+
+    let kinds: &[MutationKind] = if options.kinds.is_empty() {
+        &ALL_KINDS
+    } else {
+        &options.kinds
+    };
+    let candidates: Vec<(MutationKind, Vec<&Asn1Node>)> = kinds
+        .iter()
+        .map(|&kind| {
+            (
+                kind,
+                nodes
+                    .iter()
+                    .copied()
+                    .filter(|node| applies_to(kind, node))
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .filter(|(_, targets)| !targets.is_empty())
+        .collect();
+    if candidates.is_empty() {
+        return Err("No node in the document can take the requested mutations".into());
+    }
+
+    (0..options.count)
+        .map(|index| {
+            // Each mutant gets its own stream, so neighbouring seeds do not share mutants
+            let mut rng = Rng(options.seed ^ (index as u64).wrapping_mul(0xD1B5_4A32_D192_ED03));
+            let (kind, targets) = rng.pick(&candidates);
+            let target = *rng.pick(targets);
+            let (der, detail) = apply(*kind, root, target, &original, options, &mut rng)?;
+            Ok(Mutant {
+                index,
+                kind: *kind,
+                path: target.path.clone(),
+                label: target.label.clone(),
+                detail,
+                der,
+            })
+        })
+        .collect()
+}
+
+fn apply(
+    kind: MutationKind,
+    root: &Asn1Node,
+    target: &Asn1Node,
+    original: &[u8],
+    options: &MutationOptions,
+    rng: &mut Rng,
+) -> Result<(Vec<u8>, String), Box<dyn Error>> {
+    let identifier = hex_to_bytes(&target.identifier_octets)?;
+    let mut raw = RawOverrides::default();
+    let detail = match kind {
+        MutationKind::Truncate => {
+            let cut = target.byte_offset + rng.below(target.byte_length);
+            let detail = format!("truncated to {} of {} octets", cut, original.len());
+            return Ok((original[..cut].to_vec(), detail));
+        }
+        MutationKind::LengthOffByOne => {
+            let length = if target.length == 0 || rng.below(2) == 0 {
+                target.length + 1
+            } else {
+                target.length - 1
+            };
+            raw.length = Some(length);
+            format!("length {} claimed as {}", target.length, length)
+        }
+        MutationKind::TagClassFlip => {
+            let class = identifier[0] & 0xC0;
+            let others: Vec<u8> = [0x00, 0x40, 0x80, 0xC0]
+                .into_iter()
+                .filter(|&c| c != class)
+                .collect();
+            let mut flipped = identifier.clone();
+            flipped[0] = (flipped[0] & 0x3F) | rng.pick(&others);
+            raw.identifier_hex = Some(to_hex(&flipped));
+            format!(
+                "identifier {} changed to {}",
+                to_hex(&identifier),
+                to_hex(&flipped)
+            )
+        }
+        MutationKind::ConstructedBitFlip => {
+            let mut flipped = identifier.clone();
+            flipped[0] ^= 0x20;
+            raw.identifier_hex = Some(to_hex(&flipped));
+            format!(
+                "identifier {} changed to {}",
+                to_hex(&identifier),
+                to_hex(&flipped)
+            )
+        }
+        MutationKind::NestingBomb => {
+            let depth = options.nesting_depth.max(1);
+            let tlv = &original[target.byte_offset..target.byte_offset + target.byte_length];
+            let inner = format!(
+                "{}{}{}",
+                "3080".repeat(depth - 1),
+                to_hex(tlv),
+                "0000".repeat(depth - 1)
+            );
+            let bomb = Asn1Node {
+                path: target.path.clone(),
+                tag_class: "UNIVERSAL".to_string(),
+                tag_number: 16,
+                is_constructed: true,
+                raw: Some(RawOverrides {
+                    length_form: Some(LengthForm::Indefinite),
+                    content_hex: Some(inner),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            return Ok((
+                encode_replaced(root, &target.path, bomb)?,
+                format!("wrapped in {} indefinite SEQUENCEs", depth),
+            ));
+        }
+        MutationKind::HugeLength => {
+            let (length, octets) = *rng.pick(&[
+                (0x7FFF_FFFF, None),
+                (0xFFFF_FFFF, None),
+                (0xFFFF_FFFF, Some(9)),
+            ]);
+            raw.length = Some(length);
+            raw.long_form_octets = octets;
+            match octets {
+                Some(octets) => format!(
+                    "length {} claimed as 0x{:X} in {} octets",
+                    target.length, length, octets
+                ),
+                None => format!("length {} claimed as 0x{:X}", target.length, length),
+            }
+        }
+        MutationKind::OidOverflowArc => {
+            // 2^32, 2^64 and a non-minimal arc with a leading 0x80 octet
+            let (arc, what) = *rng.pick(&[
+                ("9080808000", "2^32"),
+                ("82808080808080808000", "2^64"),
+                ("8001", "non-minimal 1"),
+            ]);
+            let content = target.content_hex.as_deref().unwrap_or_default();
+            raw.content_hex = Some(format!("{}{}", content, arc));
+            format!("arc {} appended", what)
+        }
+    };
+
+    let mut node = target.clone();
+    node.raw = Some(raw);
+    Ok((encode_replaced(root, &target.path, node)?, detail))
+}
+
+/// Encode `root` with the node at `path` replaced
+fn encode_replaced(root: &Asn1Node, path: &str, node: Asn1Node) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut tree = root.clone();
+    *find_node_mut(&mut tree, path)? = node;
+    Ok(encode_asn1_tree(&tree)?)
+}
+
+/// Mutate a PEM document, returning JSON `{seed, mutants}` where each mutant has
+/// its manifest entry and `der_hex`
+pub fn mutate_internal(pem_input: &str, options_json: &str) -> Result<String, Box<dyn Error>> {
+    let options: MutationOptions = serde_json::from_str(options_json)?;
+    let root = decode_pem_tree(pem_input)?;
+    let mutants = mutate(&root, &options)?;
+    Ok(serde_json::to_string(
+        &serde_json::json!({ "seed": options.seed, "mutants": mutants }),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode_der_tree;
+    use crate::path::find_node;
+    use crate::test_data::CERT_PEM;
+    use crate::tlv::DecodeError;
+
+    fn mutants(kinds: Vec<MutationKind>, count: usize) -> Vec<Mutant> {
+        let root = decode_pem_tree(CERT_PEM).unwrap();
+        mutate(
+            &root,
+            &MutationOptions {
+                seed: 7,
+                count,
+                kinds,
+                nesting_depth: 50,
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_same_seed_same_corpus() {
+        let root = decode_pem_tree(CERT_PEM).unwrap();
+        let options = MutationOptions {
+            seed: 42,
+            count: 30,
+            ..Default::default()
+        };
+        let first = mutate(&root, &options).unwrap();
+        assert_eq!(first, mutate(&root, &options).unwrap());
+
+        // A longer run starts with the same mutants
+        let longer = mutate(
+            &root,
+            &MutationOptions {
+                count: 40,
+                ..options.clone()
+            },
+        )
+        .unwrap();
+        assert_eq!(first[..], longer[..30]);
+
+        let other = mutate(
+            &root,
+            &MutationOptions {
+                seed: 43,
+                ..options
+            },
+        )
+        .unwrap();
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_every_mutant_differs_from_the_original() {
+        let root = decode_pem_tree(CERT_PEM).unwrap();
+        let original = encode_asn1_tree(&root).unwrap();
+        let corpus = mutate(
+            &root,
+            &MutationOptions {
+                count: 200,
+                nesting_depth: 20,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(corpus.iter().all(|mutant| mutant.der != original));
+        for kind in ALL_KINDS {
+            assert!(
+                corpus.iter().any(|mutant| mutant.kind == kind),
+                "{:?} never chosen",
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn test_length_off_by_one_only_touches_the_target() {
+        // SEQUENCE { INTEGER 1 }: the enclosing length stays correct
+        let root = decode_der_tree("TEST", &[0x30, 0x03, 0x02, 0x01, 0x01], None).unwrap();
+        let options = MutationOptions {
+            count: 20,
+            kinds: vec![MutationKind::LengthOffByOne],
+            ..Default::default()
+        };
+        for mutant in mutate(&root, &options).unwrap() {
+            let expected: &[&[u8]] = match mutant.path.as_str() {
+                "0" => &[
+                    &[0x30, 0x04, 0x02, 0x01, 0x01],
+                    &[0x30, 0x02, 0x02, 0x01, 0x01],
+                ],
+                _ => &[
+                    &[0x30, 0x03, 0x02, 0x02, 0x01],
+                    &[0x30, 0x03, 0x02, 0x00, 0x01],
+                ],
+            };
+            assert!(expected.contains(&mutant.der.as_slice()), "{:?}", mutant);
+        }
+    }
+
+    #[test]
+    fn test_oid_overflow_and_nesting_bomb() {
+        let oid = &mutants(vec![MutationKind::OidOverflowArc], 1)[0];
+        assert!(oid.label.contains("OBJECT IDENTIFIER"), "{}", oid.label);
+
+        let bomb = &mutants(vec![MutationKind::NestingBomb], 1)[0];
+        let nested = decode_der_tree("TEST", &bomb.der, None).unwrap();
+        let mut depth = 0;
+        let mut node = find_node(&nested, &bomb.path).unwrap();
+        while node.tag_number == 16 && node.length_form == Some(LengthForm::Indefinite) {
+            depth += 1;
+            node = &node.children[0];
+        }
+        assert_eq!(depth, 50);
+    }
+
+    #[test]
+    fn test_deep_nesting_bombs_are_rejected_by_the_decoder() {
+        let root = decode_pem_tree(CERT_PEM).unwrap();
+        let kinds = vec![MutationKind::NestingBomb];
+        let options = MutationOptions {
+            count: 1,
+            kinds,
+            ..Default::default()
+        };
+        let bomb = &mutate(&root, &options).unwrap()[0];
+        assert!(decode_der_tree("TEST", &bomb.der, None).is_ok());
+
+        let bomb = &mutate(
+            &root,
+            &MutationOptions {
+                nesting_depth: 100_000,
+                ..options
+            },
+        )
+        .unwrap()[0];
+        let error = decode_der_tree("TEST", &bomb.der, None).unwrap_err();
+        assert!(
+            matches!(
+                error.downcast_ref::<DecodeError>(),
+                Some(DecodeError::TooDeep(_))
+            ),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_truncate_and_constructed_bit_flip() {
+        let root = decode_pem_tree(CERT_PEM).unwrap();
+        for mutant in mutants(vec![MutationKind::Truncate], 5) {
+            let target = find_node(&root, &mutant.path).unwrap();
+            assert!(
+                mutant.der.len() >= target.byte_offset
+                    && mutant.der.len() < target.byte_offset + target.byte_length
+            );
+        }
+        for mutant in mutants(vec![MutationKind::ConstructedBitFlip], 5) {
+            let target = find_node(&root, &mutant.path).unwrap();
+            let first = u8::from_str_radix(&target.identifier_octets[..2], 16).unwrap();
+            assert!(mutant.detail.ends_with(&format!("{:02X}", first ^ 0x20)));
+        }
+    }
+
+    #[test]
+    fn test_mutate_internal() {
+        let json = mutate_internal(
+            CERT_PEM,
+            r#"{"seed": 3, "count": 4, "kinds": ["huge_length"]}"#,
+        )
+        .unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["seed"], 3);
+        let mutants = parsed["mutants"].as_array().unwrap();
+        assert_eq!(mutants.len(), 4);
+        assert!(mutants
+            .iter()
+            .all(|m| m["kind"] == "huge_length" && m["der_hex"].is_string()));
+        assert!(mutate_internal(CERT_PEM, r#"{"kinds": ["explode"]}"#).is_err());
+    }
+}
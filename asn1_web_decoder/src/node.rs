@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+
+/// Version of the JSON node schema produced by the decoder and accepted by the encoder.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How the length octets of an element are encoded
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LengthForm {
+    Short,
+    Long,
+    Indefinite,
+}
+
+/// Encoding rules used by the encoder for a node and its descendants
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    /// Definite lengths; unchanged elements keep their original encoding
+    #[default]
+    Der,
+    /// Indefinite lengths on constructed elements, strings over 1000 octets
+    /// split into 1000-octet segments (X.690 clause 9)
+    Cer,
+    /// Indefinite lengths on every constructed element
+    BerIndefinite,
+}
+
+/// Deliberately malformed encoding of one element, for negative testing.
+///
+/// Each field replaces one part of what the encoder would produce and skips the
+/// checks it would normally make; fields left out are encoded as usual. The
+/// ancestors' lengths are computed from the bytes actually emitted.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RawOverrides {
+    /// Identifier octets as hex, used instead of `tag_class`/`tag_number`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier_hex: Option<String>,
+    /// Length to write, whatever the content length really is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<usize>,
+    /// `long` forces the long form even for short lengths; `indefinite` writes
+    /// 0x80 and end-of-contents octets, also on primitive elements
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length_form: Option<LengthForm>,
+    /// Number of long-form length octets, padded with leading zeros
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub long_form_octets: Option<usize>,
+    /// Content octets as hex, used instead of the value or children
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hex: Option<String>,
+    /// Octets appended after the element
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailing_hex: Option<String>,
+}
+
+/// A decoded ASN.1 element, shared by the decoder, the encoder and the wasm API.
+///
+/// Only `tag_class`, `tag_number` and `is_constructed` are required when the JSON is
+/// written by hand; everything else is filled in by the decoder and may be omitted.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Asn1Node {
+    /// Set on the root node only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    /// Child indices from the root, e.g. "0.0.3"; empty for the root itself
+    #[serde(default)]
+    pub path: String,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub tag: u32,
+    pub tag_class: String,
+    pub tag_number: u32,
+    pub is_constructed: bool,
+    #[serde(default)]
+    pub length: usize,
+    #[serde(default)]
+    pub value: Option<String>,
+    /// Complete primitive content as hex; `value` may be abbreviated for display
+    #[serde(default)]
+    pub content_hex: Option<String>,
+    #[serde(default)]
+    pub children: Vec<Asn1Node>,
+    #[serde(default)]
+    pub byte_offset: usize,
+    #[serde(default)]
+    pub byte_length: usize,
+    /// Offset of the first content octet
+    #[serde(default)]
+    pub content_offset: usize,
+    /// Number of identifier and length octets
+    #[serde(default)]
+    pub header_length: usize,
+    #[serde(default)]
+    pub identifier_octets: String,
+    #[serde(default)]
+    pub length_octets: String,
+    /// None for the PEM pseudo-root, which has no header
+    #[serde(default)]
+    pub length_form: Option<LengthForm>,
+    /// Constructed node whose children have not been decoded yet
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub collapsed: bool,
+    /// Set by an editor on nodes it changed: a value edit marks the node, adding
+    /// or removing children marks the parent. Used by `encode_changes`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dirty: bool,
+    /// BIT STRING that is a named bit list (e.g. KeyUsage), whose canonical
+    /// encoding has no trailing zero bits
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub named_bits: bool,
+    /// Encoding profile for this node and its descendants, overriding the one
+    /// passed to the encoder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Profile>,
+    /// Overrides for crafting an invalid encoding of this node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<RawOverrides>,
+    /// Original DER as hex, set on the decoded root only. The raw TLV of every node
+    /// is `source[byte_offset..byte_offset + byte_length]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_hex: Option<String>,
+}
+
+/// Whether `node` has the UNIVERSAL tag `tag_number`. Class names are compared
+/// case-insensitively, as `encode_tag` accepts them.
+pub(crate) fn is_universal(node: &Asn1Node, tag_number: u32) -> bool {
+    node.tag_class.eq_ignore_ascii_case("UNIVERSAL") && node.tag_number == tag_number
+}
+
+impl Asn1Node {
+    /// The pseudo-root created for a PEM document; its children are the top-level DER elements
+    pub fn is_pem_root(&self) -> bool {
+        self.tag_class == "PEM"
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Parse a node tree, rejecting documents written with a newer schema version
+    pub fn from_json(json: &str) -> Result<Asn1Node, Box<dyn std::error::Error>> {
+        let node: Asn1Node = serde_json::from_str(json)?;
+        if let Some(version) = node.schema_version {
+            if version > SCHEMA_VERSION {
+                return Err(format!(
+                    "Unsupported schema version {} (supported: {})",
+                    version, SCHEMA_VERSION
+                )
+                .into());
+            }
+        }
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode_pem_internal;
+    use crate::encoder::encode_asn1_tree;
+    use crate::test_data::CERT_PEM;
+
+    #[test]
+    fn test_decode_encode_decode_identity() {
+        let json = decode_pem_internal(CERT_PEM).unwrap();
+        let root = Asn1Node::from_json(&json).unwrap();
+        assert_eq!(root.schema_version, Some(SCHEMA_VERSION));
+
+        let der = encode_asn1_tree(&root).unwrap();
+        let pem = pem::encode(&pem::Pem::new("CERTIFICATE", der));
+        let json_again = decode_pem_internal(&pem).unwrap();
+
+        assert_eq!(json, json_again);
+    }
+
+    #[test]
+    fn test_from_json_minimal_node() {
+        let node = Asn1Node::from_json(
+            r#"{"tag_class": "UNIVERSAL", "tag_number": 2, "is_constructed": false, "value": "5"}"#,
+        )
+        .unwrap();
+        assert!(node.children.is_empty());
+        assert_eq!(encode_asn1_tree(&node).unwrap(), vec![0x02, 0x01, 0x05]);
+    }
+
+    #[test]
+    fn test_from_json_rejects_newer_schema() {
+        let json = r#"{"schema_version": 99, "tag_class": "PEM", "tag_number": 0, "is_constructed": true}"#;
+        assert!(Asn1Node::from_json(json).is_err());
+    }
+}
@@ -0,0 +1,1604 @@
+//! Names OpenSSL prints for OBJECT IDENTIFIERs: the long name of every object
+//! in OpenSSL 3.5's `obj_mac.h` (generated from `objects.txt`), or the short name
+//! when an object has no long name.
+
+/// (dotted OID, OpenSSL name), in `obj_mac.h` order
+pub(crate) const OPENSSL_NAMES: &[(&str, &str)] = &[
+    ("1.2", "ISO Member Body"),
+    ("1.3", "org"),
+    ("1.0.9797.3.4", "gmac"),
+    ("1.3.6.1.5.5.8.1.1", "hmac-md5"),
+    ("1.3.6.1.5.5.8.1.2", "hmac-sha1"),
+    (
+        "1.3.36.8.3.3",
+        "Professional Information or basis for Admission",
+    ),
+    ("1.3.132", "certicom-arc"),
+    ("1.3.111", "ieee"),
+    ("1.3.111.2.1619", "IEEE Security in Storage Working Group"),
+    ("2.23", "International Organizations"),
+    ("2.23.43", "wap"),
+    ("2.23.43.1", "wap-wsg"),
+    ("2.5.1.5", "Selected Attribute Types"),
+    ("2.5.1.5.55", "clearance"),
+    ("1.2.840", "ISO US Member Body"),
+    ("1.2.840.10040", "X9.57"),
+    ("1.2.840.10040.4", "X9.57 CM ?"),
+    ("1.2.156", "ISO CN Member Body"),
+    ("1.2.156.10197", "oscca"),
+    ("1.2.156.10197.1", "sm-scheme"),
+    ("1.2.840.10040.4.1", "dsaEncryption"),
+    ("1.2.840.10040.4.3", "dsaWithSHA1"),
+    ("1.2.840.10045", "ANSI X9.62"),
+    ("1.2.840.10045.1.1", "prime-field"),
+    ("1.2.840.10045.1.2", "characteristic-two-field"),
+    ("1.2.840.10045.1.2.3", "id-characteristic-two-basis"),
+    ("1.2.840.10045.1.2.3.1", "onBasis"),
+    ("1.2.840.10045.1.2.3.2", "tpBasis"),
+    ("1.2.840.10045.1.2.3.3", "ppBasis"),
+    ("1.2.840.10045.2.1", "id-ecPublicKey"),
+    ("1.2.840.10045.3.0.1", "c2pnb163v1"),
+    ("1.2.840.10045.3.0.2", "c2pnb163v2"),
+    ("1.2.840.10045.3.0.3", "c2pnb163v3"),
+    ("1.2.840.10045.3.0.4", "c2pnb176v1"),
+    ("1.2.840.10045.3.0.5", "c2tnb191v1"),
+    ("1.2.840.10045.3.0.6", "c2tnb191v2"),
+    ("1.2.840.10045.3.0.7", "c2tnb191v3"),
+    ("1.2.840.10045.3.0.8", "c2onb191v4"),
+    ("1.2.840.10045.3.0.9", "c2onb191v5"),
+    ("1.2.840.10045.3.0.10", "c2pnb208w1"),
+    ("1.2.840.10045.3.0.11", "c2tnb239v1"),
+    ("1.2.840.10045.3.0.12", "c2tnb239v2"),
+    ("1.2.840.10045.3.0.13", "c2tnb239v3"),
+    ("1.2.840.10045.3.0.14", "c2onb239v4"),
+    ("1.2.840.10045.3.0.15", "c2onb239v5"),
+    ("1.2.840.10045.3.0.16", "c2pnb272w1"),
+    ("1.2.840.10045.3.0.17", "c2pnb304w1"),
+    ("1.2.840.10045.3.0.18", "c2tnb359v1"),
+    ("1.2.840.10045.3.0.19", "c2pnb368w1"),
+    ("1.2.840.10045.3.0.20", "c2tnb431r1"),
+    ("1.2.840.10045.3.1.1", "prime192v1"),
+    ("1.2.840.10045.3.1.2", "prime192v2"),
+    ("1.2.840.10045.3.1.3", "prime192v3"),
+    ("1.2.840.10045.3.1.4", "prime239v1"),
+    ("1.2.840.10045.3.1.5", "prime239v2"),
+    ("1.2.840.10045.3.1.6", "prime239v3"),
+    ("1.2.840.10045.3.1.7", "prime256v1"),
+    ("1.2.840.10045.4.1", "ecdsa-with-SHA1"),
+    ("1.2.840.10045.4.2", "ecdsa-with-Recommended"),
+    ("1.2.840.10045.4.3", "ecdsa-with-Specified"),
+    ("1.2.840.10045.4.3.1", "ecdsa-with-SHA224"),
+    ("1.2.840.10045.4.3.2", "ecdsa-with-SHA256"),
+    ("1.2.840.10045.4.3.3", "ecdsa-with-SHA384"),
+    ("1.2.840.10045.4.3.4", "ecdsa-with-SHA512"),
+    ("1.3.132.0.6", "secp112r1"),
+    ("1.3.132.0.7", "secp112r2"),
+    ("1.3.132.0.28", "secp128r1"),
+    ("1.3.132.0.29", "secp128r2"),
+    ("1.3.132.0.9", "secp160k1"),
+    ("1.3.132.0.8", "secp160r1"),
+    ("1.3.132.0.30", "secp160r2"),
+    ("1.3.132.0.31", "secp192k1"),
+    ("1.3.132.0.32", "secp224k1"),
+    ("1.3.132.0.33", "secp224r1"),
+    ("1.3.132.0.10", "secp256k1"),
+    ("1.3.132.0.34", "secp384r1"),
+    ("1.3.132.0.35", "secp521r1"),
+    ("1.3.132.0.4", "sect113r1"),
+    ("1.3.132.0.5", "sect113r2"),
+    ("1.3.132.0.22", "sect131r1"),
+    ("1.3.132.0.23", "sect131r2"),
+    ("1.3.132.0.1", "sect163k1"),
+    ("1.3.132.0.2", "sect163r1"),
+    ("1.3.132.0.15", "sect163r2"),
+    ("1.3.132.0.24", "sect193r1"),
+    ("1.3.132.0.25", "sect193r2"),
+    ("1.3.132.0.26", "sect233k1"),
+    ("1.3.132.0.27", "sect233r1"),
+    ("1.3.132.0.3", "sect239k1"),
+    ("1.3.132.0.16", "sect283k1"),
+    ("1.3.132.0.17", "sect283r1"),
+    ("1.3.132.0.36", "sect409k1"),
+    ("1.3.132.0.37", "sect409r1"),
+    ("1.3.132.0.38", "sect571k1"),
+    ("1.3.132.0.39", "sect571r1"),
+    ("2.23.43.1.4.1", "wap-wsg-idm-ecid-wtls1"),
+    ("2.23.43.1.4.3", "wap-wsg-idm-ecid-wtls3"),
+    ("2.23.43.1.4.4", "wap-wsg-idm-ecid-wtls4"),
+    ("2.23.43.1.4.5", "wap-wsg-idm-ecid-wtls5"),
+    ("2.23.43.1.4.6", "wap-wsg-idm-ecid-wtls6"),
+    ("2.23.43.1.4.7", "wap-wsg-idm-ecid-wtls7"),
+    ("2.23.43.1.4.8", "wap-wsg-idm-ecid-wtls8"),
+    ("2.23.43.1.4.9", "wap-wsg-idm-ecid-wtls9"),
+    ("2.23.43.1.4.10", "wap-wsg-idm-ecid-wtls10"),
+    ("2.23.43.1.4.11", "wap-wsg-idm-ecid-wtls11"),
+    ("2.23.43.1.4.12", "wap-wsg-idm-ecid-wtls12"),
+    ("1.2.840.113533.7.66.10", "cast5-cbc"),
+    ("1.2.840.113533.7.66.12", "pbeWithMD5AndCast5CBC"),
+    ("1.2.840.113533.7.66.13", "password based MAC"),
+    ("1.2.840.113533.7.66.30", "Diffie-Hellman based MAC"),
+    ("1.2.840.113549", "RSA Data Security, Inc."),
+    ("1.2.840.113549.1", "RSA Data Security, Inc. PKCS"),
+    ("1.2.840.113549.1.1", "pkcs1"),
+    ("1.2.840.113549.1.1.1", "rsaEncryption"),
+    ("1.2.840.113549.1.1.2", "md2WithRSAEncryption"),
+    ("1.2.840.113549.1.1.3", "md4WithRSAEncryption"),
+    ("1.2.840.113549.1.1.4", "md5WithRSAEncryption"),
+    ("1.2.840.113549.1.1.5", "sha1WithRSAEncryption"),
+    ("1.2.840.113549.1.1.7", "rsaesOaep"),
+    ("1.2.840.113549.1.1.8", "mgf1"),
+    ("1.2.840.113549.1.1.9", "pSpecified"),
+    ("1.2.840.113549.1.1.10", "rsassaPss"),
+    ("1.2.840.113549.1.1.11", "sha256WithRSAEncryption"),
+    ("1.2.840.113549.1.1.12", "sha384WithRSAEncryption"),
+    ("1.2.840.113549.1.1.13", "sha512WithRSAEncryption"),
+    ("1.2.840.113549.1.1.14", "sha224WithRSAEncryption"),
+    ("1.2.840.113549.1.1.15", "sha512-224WithRSAEncryption"),
+    ("1.2.840.113549.1.1.16", "sha512-256WithRSAEncryption"),
+    ("1.2.840.113549.1.3", "pkcs3"),
+    ("1.2.840.113549.1.3.1", "dhKeyAgreement"),
+    ("1.2.840.113549.1.5", "pkcs5"),
+    ("1.2.840.113549.1.5.1", "pbeWithMD2AndDES-CBC"),
+    ("1.2.840.113549.1.5.3", "pbeWithMD5AndDES-CBC"),
+    ("1.2.840.113549.1.5.4", "pbeWithMD2AndRC2-CBC"),
+    ("1.2.840.113549.1.5.6", "pbeWithMD5AndRC2-CBC"),
+    ("1.2.840.113549.1.5.10", "pbeWithSHA1AndDES-CBC"),
+    ("1.2.840.113549.1.5.11", "pbeWithSHA1AndRC2-CBC"),
+    ("1.2.840.113549.1.5.12", "PBKDF2"),
+    ("1.2.840.113549.1.5.13", "PBES2"),
+    ("1.2.840.113549.1.5.14", "PBMAC1"),
+    ("1.2.840.113549.1.7", "pkcs7"),
+    ("1.2.840.113549.1.7.1", "pkcs7-data"),
+    ("1.2.840.113549.1.7.2", "pkcs7-signedData"),
+    ("1.2.840.113549.1.7.3", "pkcs7-envelopedData"),
+    ("1.2.840.113549.1.7.4", "pkcs7-signedAndEnvelopedData"),
+    ("1.2.840.113549.1.7.5", "pkcs7-digestData"),
+    ("1.2.840.113549.1.7.6", "pkcs7-encryptedData"),
+    ("1.2.840.113549.1.9", "pkcs9"),
+    ("1.2.840.113549.1.9.1", "emailAddress"),
+    ("1.2.840.113549.1.9.2", "unstructuredName"),
+    ("1.2.840.113549.1.9.3", "contentType"),
+    ("1.2.840.113549.1.9.4", "messageDigest"),
+    ("1.2.840.113549.1.9.5", "signingTime"),
+    ("1.2.840.113549.1.9.6", "countersignature"),
+    ("1.2.840.113549.1.9.7", "challengePassword"),
+    ("1.2.840.113549.1.9.8", "unstructuredAddress"),
+    ("1.2.840.113549.1.9.9", "extendedCertificateAttributes"),
+    ("1.2.840.113549.1.9.14", "Extension Request"),
+    ("1.2.840.113549.1.9.15", "S/MIME Capabilities"),
+    ("1.2.840.113549.1.9.16", "S/MIME"),
+    ("1.2.840.113549.1.9.16.0", "id-smime-mod"),
+    ("1.2.840.113549.1.9.16.1", "id-smime-ct"),
+    ("1.2.840.113549.1.9.16.2", "id-smime-aa"),
+    ("1.2.840.113549.1.9.16.3", "id-smime-alg"),
+    ("1.2.840.113549.1.9.16.4", "id-smime-cd"),
+    ("1.2.840.113549.1.9.16.5", "id-smime-spq"),
+    ("1.2.840.113549.1.9.16.6", "id-smime-cti"),
+    ("1.2.840.113549.1.9.16.0.1", "id-smime-mod-cms"),
+    ("1.2.840.113549.1.9.16.0.2", "id-smime-mod-ess"),
+    ("1.2.840.113549.1.9.16.0.3", "id-smime-mod-oid"),
+    ("1.2.840.113549.1.9.16.0.4", "id-smime-mod-msg-v3"),
+    (
+        "1.2.840.113549.1.9.16.0.5",
+        "id-smime-mod-ets-eSignature-88",
+    ),
+    (
+        "1.2.840.113549.1.9.16.0.6",
+        "id-smime-mod-ets-eSignature-97",
+    ),
+    (
+        "1.2.840.113549.1.9.16.0.7",
+        "id-smime-mod-ets-eSigPolicy-88",
+    ),
+    (
+        "1.2.840.113549.1.9.16.0.8",
+        "id-smime-mod-ets-eSigPolicy-97",
+    ),
+    ("1.2.840.113549.1.9.16.1.1", "id-smime-ct-receipt"),
+    ("1.2.840.113549.1.9.16.1.2", "id-smime-ct-authData"),
+    ("1.2.840.113549.1.9.16.1.3", "id-smime-ct-publishCert"),
+    ("1.2.840.113549.1.9.16.1.4", "id-smime-ct-TSTInfo"),
+    ("1.2.840.113549.1.9.16.1.5", "id-smime-ct-TDTInfo"),
+    ("1.2.840.113549.1.9.16.1.6", "id-smime-ct-contentInfo"),
+    ("1.2.840.113549.1.9.16.1.7", "id-smime-ct-DVCSRequestData"),
+    ("1.2.840.113549.1.9.16.1.8", "id-smime-ct-DVCSResponseData"),
+    ("1.2.840.113549.1.9.16.1.9", "id-smime-ct-compressedData"),
+    (
+        "1.2.840.113549.1.9.16.1.19",
+        "id-smime-ct-contentCollection",
+    ),
+    (
+        "1.2.840.113549.1.9.16.1.23",
+        "id-smime-ct-authEnvelopedData",
+    ),
+    ("1.2.840.113549.1.9.16.1.24", "id-ct-routeOriginAuthz"),
+    ("1.2.840.113549.1.9.16.1.26", "id-ct-rpkiManifest"),
+    ("1.2.840.113549.1.9.16.1.27", "id-ct-asciiTextWithCRLF"),
+    ("1.2.840.113549.1.9.16.1.28", "id-ct-xml"),
+    ("1.2.840.113549.1.9.16.1.35", "id-ct-rpkiGhostbusters"),
+    ("1.2.840.113549.1.9.16.1.36", "id-ct-resourceTaggedAttest"),
+    ("1.2.840.113549.1.9.16.1.47", "id-ct-geofeedCSVwithCRLF"),
+    ("1.2.840.113549.1.9.16.1.48", "id-ct-signedChecklist"),
+    ("1.2.840.113549.1.9.16.1.49", "id-ct-ASPA"),
+    ("1.2.840.113549.1.9.16.1.50", "id-ct-signedTAL"),
+    ("1.2.840.113549.1.9.16.1.51", "id-ct-rpkiSignedPrefixList"),
+    ("1.2.840.113549.1.9.16.2.1", "id-smime-aa-receiptRequest"),
+    ("1.2.840.113549.1.9.16.2.2", "id-smime-aa-securityLabel"),
+    ("1.2.840.113549.1.9.16.2.3", "id-smime-aa-mlExpandHistory"),
+    ("1.2.840.113549.1.9.16.2.4", "id-smime-aa-contentHint"),
+    ("1.2.840.113549.1.9.16.2.5", "id-smime-aa-msgSigDigest"),
+    ("1.2.840.113549.1.9.16.2.6", "id-smime-aa-encapContentType"),
+    ("1.2.840.113549.1.9.16.2.7", "id-smime-aa-contentIdentifier"),
+    ("1.2.840.113549.1.9.16.2.8", "id-smime-aa-macValue"),
+    ("1.2.840.113549.1.9.16.2.9", "id-smime-aa-equivalentLabels"),
+    ("1.2.840.113549.1.9.16.2.10", "id-smime-aa-contentReference"),
+    ("1.2.840.113549.1.9.16.2.11", "id-smime-aa-encrypKeyPref"),
+    (
+        "1.2.840.113549.1.9.16.2.12",
+        "id-smime-aa-signingCertificate",
+    ),
+    (
+        "1.2.840.113549.1.9.16.2.13",
+        "id-smime-aa-smimeEncryptCerts",
+    ),
+    ("1.2.840.113549.1.9.16.2.14", "id-smime-aa-timeStampToken"),
+    ("1.2.840.113549.1.9.16.2.15", "id-smime-aa-ets-sigPolicyId"),
+    (
+        "1.2.840.113549.1.9.16.2.16",
+        "id-smime-aa-ets-commitmentType",
+    ),
+    (
+        "1.2.840.113549.1.9.16.2.17",
+        "id-smime-aa-ets-signerLocation",
+    ),
+    ("1.2.840.113549.1.9.16.2.18", "id-smime-aa-ets-signerAttr"),
+    ("1.2.840.113549.1.9.16.2.19", "id-smime-aa-ets-otherSigCert"),
+    (
+        "1.2.840.113549.1.9.16.2.20",
+        "id-smime-aa-ets-contentTimestamp",
+    ),
+    (
+        "1.2.840.113549.1.9.16.2.21",
+        "id-smime-aa-ets-CertificateRefs",
+    ),
+    (
+        "1.2.840.113549.1.9.16.2.22",
+        "id-smime-aa-ets-RevocationRefs",
+    ),
+    ("1.2.840.113549.1.9.16.2.23", "id-smime-aa-ets-certValues"),
+    (
+        "1.2.840.113549.1.9.16.2.24",
+        "id-smime-aa-ets-revocationValues",
+    ),
+    ("1.2.840.113549.1.9.16.2.25", "id-smime-aa-ets-escTimeStamp"),
+    (
+        "1.2.840.113549.1.9.16.2.26",
+        "id-smime-aa-ets-certCRLTimestamp",
+    ),
+    (
+        "1.2.840.113549.1.9.16.2.27",
+        "id-smime-aa-ets-archiveTimeStamp",
+    ),
+    ("1.2.840.113549.1.9.16.2.28", "id-smime-aa-signatureType"),
+    ("1.2.840.113549.1.9.16.2.29", "id-smime-aa-dvcs-dvc"),
+    (
+        "1.2.840.113549.1.9.16.2.44",
+        "id-aa-ets-attrCertificateRefs",
+    ),
+    ("1.2.840.113549.1.9.16.2.45", "id-aa-ets-attrRevocationRefs"),
+    (
+        "1.2.840.113549.1.9.16.2.47",
+        "id-smime-aa-signingCertificateV2",
+    ),
+    ("1.2.840.113549.1.9.16.2.48", "id-aa-ets-archiveTimestampV2"),
+    ("1.2.840.113549.1.9.16.3.1", "id-smime-alg-ESDHwith3DES"),
+    ("1.2.840.113549.1.9.16.3.2", "id-smime-alg-ESDHwithRC2"),
+    ("1.2.840.113549.1.9.16.3.3", "id-smime-alg-3DESwrap"),
+    ("1.2.840.113549.1.9.16.3.4", "id-smime-alg-RC2wrap"),
+    ("1.2.840.113549.1.9.16.3.5", "id-smime-alg-ESDH"),
+    ("1.2.840.113549.1.9.16.3.6", "id-smime-alg-CMS3DESwrap"),
+    ("1.2.840.113549.1.9.16.3.7", "id-smime-alg-CMSRC2wrap"),
+    ("1.2.840.113549.1.9.16.3.9", "id-alg-PWRI-KEK"),
+    ("1.2.840.113549.1.9.16.4.1", "id-smime-cd-ldap"),
+    ("1.2.840.113549.1.9.16.5.1", "id-smime-spq-ets-sqt-uri"),
+    ("1.2.840.113549.1.9.16.5.2", "id-smime-spq-ets-sqt-unotice"),
+    (
+        "1.2.840.113549.1.9.16.6.1",
+        "id-smime-cti-ets-proofOfOrigin",
+    ),
+    (
+        "1.2.840.113549.1.9.16.6.2",
+        "id-smime-cti-ets-proofOfReceipt",
+    ),
+    (
+        "1.2.840.113549.1.9.16.6.3",
+        "id-smime-cti-ets-proofOfDelivery",
+    ),
+    (
+        "1.2.840.113549.1.9.16.6.4",
+        "id-smime-cti-ets-proofOfSender",
+    ),
+    (
+        "1.2.840.113549.1.9.16.6.5",
+        "id-smime-cti-ets-proofOfApproval",
+    ),
+    (
+        "1.2.840.113549.1.9.16.6.6",
+        "id-smime-cti-ets-proofOfCreation",
+    ),
+    ("1.2.840.113549.1.9.20", "friendlyName"),
+    ("1.2.840.113549.1.9.21", "localKeyID"),
+    ("1.3.6.1.4.1.311.17.1", "Microsoft CSP Name"),
+    ("1.3.6.1.4.1.311.17.2", "Microsoft Local Key set"),
+    ("1.2.840.113549.1.9.22.1", "x509Certificate"),
+    ("1.2.840.113549.1.9.22.2", "sdsiCertificate"),
+    ("1.2.840.113549.1.9.23.1", "x509Crl"),
+    ("1.2.840.113549.1.9.52", "id-aa-CMSAlgorithmProtection"),
+    ("1.2.840.113549.1.12.1.1", "pbeWithSHA1And128BitRC4"),
+    ("1.2.840.113549.1.12.1.2", "pbeWithSHA1And40BitRC4"),
+    (
+        "1.2.840.113549.1.12.1.3",
+        "pbeWithSHA1And3-KeyTripleDES-CBC",
+    ),
+    (
+        "1.2.840.113549.1.12.1.4",
+        "pbeWithSHA1And2-KeyTripleDES-CBC",
+    ),
+    ("1.2.840.113549.1.12.1.5", "pbeWithSHA1And128BitRC2-CBC"),
+    ("1.2.840.113549.1.12.1.6", "pbeWithSHA1And40BitRC2-CBC"),
+    ("1.2.840.113549.1.12.10.1.1", "keyBag"),
+    ("1.2.840.113549.1.12.10.1.2", "pkcs8ShroudedKeyBag"),
+    ("1.2.840.113549.1.12.10.1.3", "certBag"),
+    ("1.2.840.113549.1.12.10.1.4", "crlBag"),
+    ("1.2.840.113549.1.12.10.1.5", "secretBag"),
+    ("1.2.840.113549.1.12.10.1.6", "safeContentsBag"),
+    ("1.2.840.113549.2.2", "md2"),
+    ("1.2.840.113549.2.4", "md4"),
+    ("1.2.840.113549.2.5", "md5"),
+    ("1.2.840.113549.2.6", "hmacWithMD5"),
+    ("1.2.840.113549.2.7", "hmacWithSHA1"),
+    ("1.2.156.10197.1.301", "sm2"),
+    ("1.2.156.10197.1.401", "sm3"),
+    ("1.2.156.10197.1.504", "sm3WithRSAEncryption"),
+    ("1.2.156.10197.1.501", "SM2-with-SM3"),
+    ("1.2.156.10197.1.401.3.1", "hmacWithSM3"),
+    ("1.2.840.113549.2.8", "hmacWithSHA224"),
+    ("1.2.840.113549.2.9", "hmacWithSHA256"),
+    ("1.2.840.113549.2.10", "hmacWithSHA384"),
+    ("1.2.840.113549.2.11", "hmacWithSHA512"),
+    ("1.2.840.113549.2.12", "hmacWithSHA512-224"),
+    ("1.2.840.113549.2.13", "hmacWithSHA512-256"),
+    ("1.2.840.113549.3.2", "rc2-cbc"),
+    ("1.2.840.113549.3.4", "rc4"),
+    ("1.2.840.113549.3.7", "des-ede3-cbc"),
+    ("1.2.840.113549.3.8", "rc5-cbc"),
+    ("1.3.6.1.4.1.311.2.1.14", "Microsoft Extension Request"),
+    (
+        "1.3.6.1.4.1.311.2.1.21",
+        "Microsoft Individual Code Signing",
+    ),
+    (
+        "1.3.6.1.4.1.311.2.1.22",
+        "Microsoft Commercial Code Signing",
+    ),
+    ("1.3.6.1.4.1.311.10.3.1", "Microsoft Trust List Signing"),
+    ("1.3.6.1.4.1.311.10.3.3", "Microsoft Server Gated Crypto"),
+    ("1.3.6.1.4.1.311.10.3.4", "Microsoft Encrypted File System"),
+    ("1.3.6.1.4.1.311.20.2.2", "Microsoft Smartcard Login"),
+    ("1.3.6.1.4.1.311.20.2.3", "Microsoft User Principal Name"),
+    ("1.3.6.1.4.1.311.25.2", "Microsoft NTDS CA Extension"),
+    ("1.3.6.1.4.1.311.25.2.1", "Microsoft NTDS AD objectSid"),
+    ("1.3.6.1.4.1.311.21.7", "Microsoft certificate template"),
+    (
+        "1.3.6.1.4.1.311.21.10",
+        "Microsoft Application Policies Extension",
+    ),
+    ("1.3.6.1.4.1.188.7.1.1.2", "idea-cbc"),
+    ("1.3.6.1.4.1.3029.1.2", "bf-cbc"),
+    ("1.3.6.1.5.5.7", "PKIX"),
+    ("1.3.6.1.5.5.7.0", "id-pkix-mod"),
+    ("1.3.6.1.5.5.7.1", "id-pe"),
+    ("1.3.6.1.5.5.7.2", "id-qt"),
+    ("1.3.6.1.5.5.7.3", "id-kp"),
+    ("1.3.6.1.5.5.7.4", "id-it"),
+    ("1.3.6.1.5.5.7.5", "id-pkip"),
+    ("1.3.6.1.5.5.7.6", "id-alg"),
+    ("1.3.6.1.5.5.7.7", "id-cmc"),
+    ("1.3.6.1.5.5.7.8", "id-on"),
+    ("1.3.6.1.5.5.7.9", "id-pda"),
+    ("1.3.6.1.5.5.7.10", "id-aca"),
+    ("1.3.6.1.5.5.7.11", "id-qcs"),
+    ("1.3.6.1.5.5.7.14", "id-cp"),
+    ("1.3.6.1.5.5.7.12", "id-cct"),
+    ("1.3.6.1.5.5.7.21", "id-ppl"),
+    ("1.3.6.1.5.5.7.48", "id-ad"),
+    ("1.3.6.1.5.5.7.0.1", "id-pkix1-explicit-88"),
+    ("1.3.6.1.5.5.7.0.2", "id-pkix1-implicit-88"),
+    ("1.3.6.1.5.5.7.0.3", "id-pkix1-explicit-93"),
+    ("1.3.6.1.5.5.7.0.4", "id-pkix1-implicit-93"),
+    ("1.3.6.1.5.5.7.0.5", "id-mod-crmf"),
+    ("1.3.6.1.5.5.7.0.6", "id-mod-cmc"),
+    ("1.3.6.1.5.5.7.0.7", "id-mod-kea-profile-88"),
+    ("1.3.6.1.5.5.7.0.8", "id-mod-kea-profile-93"),
+    ("1.3.6.1.5.5.7.0.9", "id-mod-cmp"),
+    ("1.3.6.1.5.5.7.0.10", "id-mod-qualified-cert-88"),
+    ("1.3.6.1.5.5.7.0.11", "id-mod-qualified-cert-93"),
+    ("1.3.6.1.5.5.7.0.12", "id-mod-attribute-cert"),
+    ("1.3.6.1.5.5.7.0.13", "id-mod-timestamp-protocol"),
+    ("1.3.6.1.5.5.7.0.14", "id-mod-ocsp"),
+    ("1.3.6.1.5.5.7.0.15", "id-mod-dvcs"),
+    ("1.3.6.1.5.5.7.0.16", "id-mod-cmp2000"),
+    ("1.3.6.1.5.5.7.0.50", "id-mod-cmp2000-02"),
+    ("1.3.6.1.5.5.7.0.99", "id-mod-cmp2021-88"),
+    ("1.3.6.1.5.5.7.0.100", "id-mod-cmp2021-02"),
+    ("1.3.6.1.5.5.7.1.1", "Authority Information Access"),
+    ("1.3.6.1.5.5.7.1.2", "Biometric Info"),
+    ("1.3.6.1.5.5.7.1.3", "qcStatements"),
+    ("1.3.6.1.5.5.7.1.4", "X509v3 Audit Identity"),
+    ("1.3.6.1.5.5.7.1.5", "ac-targeting"),
+    ("1.3.6.1.5.5.7.1.6", "aaControls"),
+    ("1.3.6.1.5.5.7.1.7", "sbgp-ipAddrBlock"),
+    ("1.3.6.1.5.5.7.1.8", "sbgp-autonomousSysNum"),
+    ("1.3.6.1.5.5.7.1.9", "sbgp-routerIdentifier"),
+    ("1.3.6.1.5.5.7.1.10", "ac-proxying"),
+    ("1.3.6.1.5.5.7.1.11", "Subject Information Access"),
+    ("1.3.6.1.5.5.7.1.14", "Proxy Certificate Information"),
+    ("1.3.6.1.5.5.7.1.24", "TLS Feature"),
+    ("1.3.6.1.5.5.7.1.28", "sbgp-ipAddrBlockv2"),
+    ("1.3.6.1.5.5.7.1.29", "sbgp-autonomousSysNumv2"),
+    ("1.3.6.1.5.5.7.2.1", "Policy Qualifier CPS"),
+    ("1.3.6.1.5.5.7.2.2", "Policy Qualifier User Notice"),
+    ("1.3.6.1.5.5.7.2.3", "textNotice"),
+    ("1.3.6.1.5.5.7.3.1", "TLS Web Server Authentication"),
+    ("1.3.6.1.5.5.7.3.2", "TLS Web Client Authentication"),
+    ("1.3.6.1.5.5.7.3.3", "Code Signing"),
+    ("1.3.6.1.5.5.7.3.4", "E-mail Protection"),
+    ("1.3.6.1.5.5.7.3.5", "IPSec End System"),
+    ("1.3.6.1.5.5.7.3.6", "IPSec Tunnel"),
+    ("1.3.6.1.5.5.7.3.7", "IPSec User"),
+    ("1.3.6.1.5.5.7.3.8", "Time Stamping"),
+    ("1.3.6.1.5.5.7.3.9", "OCSP Signing"),
+    ("1.3.6.1.5.5.7.3.10", "dvcs"),
+    ("1.3.6.1.5.5.7.3.17", "ipsec Internet Key Exchange"),
+    ("1.3.6.1.5.5.7.3.18", "Ctrl/provision WAP Access"),
+    ("1.3.6.1.5.5.7.3.19", "Ctrl/Provision WAP Termination"),
+    ("1.3.6.1.5.5.7.3.21", "SSH Client"),
+    ("1.3.6.1.5.5.7.3.22", "SSH Server"),
+    ("1.3.6.1.5.5.7.3.23", "Send Router"),
+    ("1.3.6.1.5.5.7.3.24", "Send Proxied Router"),
+    ("1.3.6.1.5.5.7.3.25", "Send Owner"),
+    ("1.3.6.1.5.5.7.3.26", "Send Proxied Owner"),
+    ("1.3.6.1.5.5.7.3.27", "CMC Certificate Authority"),
+    ("1.3.6.1.5.5.7.3.28", "CMC Registration Authority"),
+    ("1.3.6.1.5.5.7.3.29", "CMC Archive Server"),
+    ("1.3.6.1.5.5.7.3.30", "BGPsec Router"),
+    (
+        "1.3.6.1.5.5.7.3.31",
+        "Brand Indicator for Message Identification",
+    ),
+    (
+        "1.3.6.1.5.5.7.3.32",
+        "Certificate Management Key Generation Authority",
+    ),
+    ("1.3.6.1.5.5.7.4.1", "id-it-caProtEncCert"),
+    ("1.3.6.1.5.5.7.4.2", "id-it-signKeyPairTypes"),
+    ("1.3.6.1.5.5.7.4.3", "id-it-encKeyPairTypes"),
+    ("1.3.6.1.5.5.7.4.4", "id-it-preferredSymmAlg"),
+    ("1.3.6.1.5.5.7.4.5", "id-it-caKeyUpdateInfo"),
+    ("1.3.6.1.5.5.7.4.6", "id-it-currentCRL"),
+    ("1.3.6.1.5.5.7.4.7", "id-it-unsupportedOIDs"),
+    ("1.3.6.1.5.5.7.4.8", "id-it-subscriptionRequest"),
+    ("1.3.6.1.5.5.7.4.9", "id-it-subscriptionResponse"),
+    ("1.3.6.1.5.5.7.4.10", "id-it-keyPairParamReq"),
+    ("1.3.6.1.5.5.7.4.11", "id-it-keyPairParamRep"),
+    ("1.3.6.1.5.5.7.4.12", "id-it-revPassphrase"),
+    ("1.3.6.1.5.5.7.4.13", "id-it-implicitConfirm"),
+    ("1.3.6.1.5.5.7.4.14", "id-it-confirmWaitTime"),
+    ("1.3.6.1.5.5.7.4.15", "id-it-origPKIMessage"),
+    ("1.3.6.1.5.5.7.4.16", "id-it-suppLangTags"),
+    ("1.3.6.1.5.5.7.4.17", "id-it-caCerts"),
+    ("1.3.6.1.5.5.7.4.18", "id-it-rootCaKeyUpdate"),
+    ("1.3.6.1.5.5.7.4.19", "id-it-certReqTemplate"),
+    ("1.3.6.1.5.5.7.4.20", "id-it-rootCaCert"),
+    ("1.3.6.1.5.5.7.4.21", "id-it-certProfile"),
+    ("1.3.6.1.5.5.7.4.22", "id-it-crlStatusList"),
+    ("1.3.6.1.5.5.7.4.23", "id-it-crls"),
+    ("1.3.6.1.5.5.7.5.1", "id-regCtrl"),
+    ("1.3.6.1.5.5.7.5.2", "id-regInfo"),
+    ("1.3.6.1.5.5.7.5.1.1", "id-regCtrl-regToken"),
+    ("1.3.6.1.5.5.7.5.1.2", "id-regCtrl-authenticator"),
+    ("1.3.6.1.5.5.7.5.1.3", "id-regCtrl-pkiPublicationInfo"),
+    ("1.3.6.1.5.5.7.5.1.4", "id-regCtrl-pkiArchiveOptions"),
+    ("1.3.6.1.5.5.7.5.1.5", "id-regCtrl-oldCertID"),
+    ("1.3.6.1.5.5.7.5.1.6", "id-regCtrl-protocolEncrKey"),
+    ("1.3.6.1.5.5.7.5.1.7", "id-regCtrl-altCertTemplate"),
+    ("1.3.6.1.5.5.7.5.1.11", "id-regCtrl-algId"),
+    ("1.3.6.1.5.5.7.5.1.12", "id-regCtrl-rsaKeyLen"),
+    ("1.3.6.1.5.5.7.5.2.1", "id-regInfo-utf8Pairs"),
+    ("1.3.6.1.5.5.7.5.2.2", "id-regInfo-certReq"),
+    ("1.3.6.1.5.5.7.6.1", "id-alg-des40"),
+    ("1.3.6.1.5.5.7.6.2", "id-alg-noSignature"),
+    ("1.3.6.1.5.5.7.6.3", "id-alg-dh-sig-hmac-sha1"),
+    ("1.3.6.1.5.5.7.6.4", "id-alg-dh-pop"),
+    ("1.3.6.1.5.5.7.7.1", "id-cmc-statusInfo"),
+    ("1.3.6.1.5.5.7.7.2", "id-cmc-identification"),
+    ("1.3.6.1.5.5.7.7.3", "id-cmc-identityProof"),
+    ("1.3.6.1.5.5.7.7.4", "id-cmc-dataReturn"),
+    ("1.3.6.1.5.5.7.7.5", "id-cmc-transactionId"),
+    ("1.3.6.1.5.5.7.7.6", "id-cmc-senderNonce"),
+    ("1.3.6.1.5.5.7.7.7", "id-cmc-recipientNonce"),
+    ("1.3.6.1.5.5.7.7.8", "id-cmc-addExtensions"),
+    ("1.3.6.1.5.5.7.7.9", "id-cmc-encryptedPOP"),
+    ("1.3.6.1.5.5.7.7.10", "id-cmc-decryptedPOP"),
+    ("1.3.6.1.5.5.7.7.11", "id-cmc-lraPOPWitness"),
+    ("1.3.6.1.5.5.7.7.15", "id-cmc-getCert"),
+    ("1.3.6.1.5.5.7.7.16", "id-cmc-getCRL"),
+    ("1.3.6.1.5.5.7.7.17", "id-cmc-revokeRequest"),
+    ("1.3.6.1.5.5.7.7.18", "id-cmc-regInfo"),
+    ("1.3.6.1.5.5.7.7.19", "id-cmc-responseInfo"),
+    ("1.3.6.1.5.5.7.7.21", "id-cmc-queryPending"),
+    ("1.3.6.1.5.5.7.7.22", "id-cmc-popLinkRandom"),
+    ("1.3.6.1.5.5.7.7.23", "id-cmc-popLinkWitness"),
+    ("1.3.6.1.5.5.7.7.24", "id-cmc-confirmCertAcceptance"),
+    ("1.3.6.1.5.5.7.8.1", "id-on-personalData"),
+    ("1.3.6.1.5.5.7.8.3", "Permanent Identifier"),
+    ("1.3.6.1.5.5.7.8.4", "Hardware Module Name"),
+    ("1.3.6.1.5.5.7.8.5", "XmppAddr"),
+    ("1.3.6.1.5.5.7.8.7", "SRVName"),
+    ("1.3.6.1.5.5.7.8.8", "NAIRealm"),
+    ("1.3.6.1.5.5.7.8.9", "Smtp UTF8 Mailbox"),
+    ("1.3.6.1.5.5.7.9.1", "id-pda-dateOfBirth"),
+    ("1.3.6.1.5.5.7.9.2", "id-pda-placeOfBirth"),
+    ("1.3.6.1.5.5.7.9.3", "id-pda-gender"),
+    ("1.3.6.1.5.5.7.9.4", "id-pda-countryOfCitizenship"),
+    ("1.3.6.1.5.5.7.9.5", "id-pda-countryOfResidence"),
+    ("1.3.6.1.5.5.7.10.1", "id-aca-authenticationInfo"),
+    ("1.3.6.1.5.5.7.10.2", "id-aca-accessIdentity"),
+    ("1.3.6.1.5.5.7.10.3", "id-aca-chargingIdentity"),
+    ("1.3.6.1.5.5.7.10.4", "id-aca-group"),
+    ("1.3.6.1.5.5.7.10.5", "id-aca-role"),
+    ("1.3.6.1.5.5.7.10.6", "id-aca-encAttrs"),
+    ("1.3.6.1.5.5.7.11.1", "id-qcs-pkixQCSyntax-v1"),
+    ("1.3.6.1.5.5.7.14.2", "ipAddr-asNumber"),
+    ("1.3.6.1.5.5.7.14.3", "ipAddr-asNumberv2"),
+    ("1.3.6.1.5.5.7.12.1", "id-cct-crs"),
+    ("1.3.6.1.5.5.7.12.2", "id-cct-PKIData"),
+    ("1.3.6.1.5.5.7.12.3", "id-cct-PKIResponse"),
+    ("1.3.6.1.5.5.7.21.0", "Any language"),
+    ("1.3.6.1.5.5.7.21.1", "Inherit all"),
+    ("1.3.6.1.5.5.7.21.2", "Independent"),
+    ("1.3.6.1.5.5.7.48.1", "OCSP"),
+    ("1.3.6.1.5.5.7.48.2", "CA Issuers"),
+    ("1.3.6.1.5.5.7.48.3", "AD Time Stamping"),
+    ("1.3.6.1.5.5.7.48.4", "ad dvcs"),
+    ("1.3.6.1.5.5.7.48.5", "CA Repository"),
+    ("1.3.6.1.5.5.7.48.10", "RPKI Manifest"),
+    ("1.3.6.1.5.5.7.48.11", "Signed Object"),
+    ("1.3.6.1.5.5.7.48.13", "RPKI Notify"),
+    ("1.3.6.1.5.5.7.48.1.1", "Basic OCSP Response"),
+    ("1.3.6.1.5.5.7.48.1.2", "OCSP Nonce"),
+    ("1.3.6.1.5.5.7.48.1.3", "OCSP CRL ID"),
+    ("1.3.6.1.5.5.7.48.1.4", "Acceptable OCSP Responses"),
+    ("1.3.6.1.5.5.7.48.1.5", "OCSP No Check"),
+    ("1.3.6.1.5.5.7.48.1.6", "OCSP Archive Cutoff"),
+    ("1.3.6.1.5.5.7.48.1.7", "OCSP Service Locator"),
+    ("1.3.6.1.5.5.7.48.1.8", "Extended OCSP Status"),
+    ("1.3.6.1.5.5.7.48.1.9", "valid"),
+    ("1.3.6.1.5.5.7.48.1.10", "path"),
+    ("1.3.6.1.5.5.7.48.1.11", "Trust Root"),
+    ("1.3.14.3.2", "algorithm"),
+    ("1.3.14.3.2.3", "md5WithRSA"),
+    ("1.3.14.3.2.6", "des-ecb"),
+    ("1.3.14.3.2.7", "des-cbc"),
+    ("1.3.14.3.2.8", "des-ofb"),
+    ("1.3.14.3.2.9", "des-cfb"),
+    ("1.3.14.3.2.11", "rsaSignature"),
+    ("1.3.14.3.2.12", "dsaEncryption-old"),
+    ("1.3.14.3.2.13", "dsaWithSHA"),
+    ("1.3.14.3.2.15", "shaWithRSAEncryption"),
+    ("1.3.14.3.2.17", "des-ede"),
+    ("1.3.14.3.2.18", "sha"),
+    ("1.3.14.3.2.26", "sha1"),
+    ("1.3.14.3.2.27", "dsaWithSHA1-old"),
+    ("1.3.14.3.2.29", "sha1WithRSA"),
+    ("1.3.36.3.2.1", "ripemd160"),
+    ("1.3.36.3.3.1.2", "ripemd160WithRSA"),
+    ("1.3.6.1.4.1.1722.12.2.1", "blake2bmac"),
+    ("1.3.6.1.4.1.1722.12.2.2", "blake2smac"),
+    ("1.3.6.1.4.1.1722.12.2.1.16", "blake2b512"),
+    ("1.3.6.1.4.1.1722.12.2.2.8", "blake2s256"),
+    ("1.3.101.1.4.1", "Strong Extranet ID"),
+    ("2.5", "directory services (X.500)"),
+    ("2.5.4", "X509"),
+    ("2.5.4.3", "commonName"),
+    ("2.5.4.4", "surname"),
+    ("2.5.4.5", "serialNumber"),
+    ("2.5.4.6", "countryName"),
+    ("2.5.4.7", "localityName"),
+    ("2.5.4.8", "stateOrProvinceName"),
+    ("2.5.4.9", "streetAddress"),
+    ("2.5.4.10", "organizationName"),
+    ("2.5.4.11", "organizationalUnitName"),
+    ("2.5.4.12", "title"),
+    ("2.5.4.13", "description"),
+    ("2.5.4.14", "searchGuide"),
+    ("2.5.4.15", "businessCategory"),
+    ("2.5.4.16", "postalAddress"),
+    ("2.5.4.17", "postalCode"),
+    ("2.5.4.18", "postOfficeBox"),
+    ("2.5.4.19", "physicalDeliveryOfficeName"),
+    ("2.5.4.20", "telephoneNumber"),
+    ("2.5.4.21", "telexNumber"),
+    ("2.5.4.22", "teletexTerminalIdentifier"),
+    ("2.5.4.23", "facsimileTelephoneNumber"),
+    ("2.5.4.24", "x121Address"),
+    ("2.5.4.25", "internationaliSDNNumber"),
+    ("2.5.4.26", "registeredAddress"),
+    ("2.5.4.27", "destinationIndicator"),
+    ("2.5.4.28", "preferredDeliveryMethod"),
+    ("2.5.4.29", "presentationAddress"),
+    ("2.5.4.30", "supportedApplicationContext"),
+    ("2.5.4.31", "member"),
+    ("2.5.4.32", "owner"),
+    ("2.5.4.33", "roleOccupant"),
+    ("2.5.4.34", "seeAlso"),
+    ("2.5.4.35", "userPassword"),
+    ("2.5.4.36", "userCertificate"),
+    ("2.5.4.37", "cACertificate"),
+    ("2.5.4.38", "authorityRevocationList"),
+    ("2.5.4.39", "certificateRevocationList"),
+    ("2.5.4.40", "crossCertificatePair"),
+    ("2.5.4.41", "name"),
+    ("2.5.4.42", "givenName"),
+    ("2.5.4.43", "initials"),
+    ("2.5.4.44", "generationQualifier"),
+    ("2.5.4.45", "x500UniqueIdentifier"),
+    ("2.5.4.46", "dnQualifier"),
+    ("2.5.4.47", "enhancedSearchGuide"),
+    ("2.5.4.48", "protocolInformation"),
+    ("2.5.4.49", "distinguishedName"),
+    ("2.5.4.50", "uniqueMember"),
+    ("2.5.4.51", "houseIdentifier"),
+    ("2.5.4.52", "supportedAlgorithms"),
+    ("2.5.4.53", "deltaRevocationList"),
+    ("2.5.4.54", "dmdName"),
+    ("2.5.4.65", "pseudonym"),
+    ("2.5.4.72", "role"),
+    ("2.5.4.97", "organizationIdentifier"),
+    ("2.5.4.98", "countryCode3c"),
+    ("2.5.4.99", "countryCode3n"),
+    ("2.5.4.100", "dnsName"),
+    ("2.5.8", "directory services - algorithms"),
+    ("2.5.8.1.1", "rsa"),
+    ("2.5.8.3.100", "mdc2WithRSA"),
+    ("2.5.8.3.101", "mdc2"),
+    ("2.5.29", "id-ce"),
+    ("2.5.29.9", "X509v3 Subject Directory Attributes"),
+    ("2.5.29.14", "X509v3 Subject Key Identifier"),
+    ("2.5.29.15", "X509v3 Key Usage"),
+    ("2.5.29.16", "X509v3 Private Key Usage Period"),
+    ("2.5.29.17", "X509v3 Subject Alternative Name"),
+    ("2.5.29.18", "X509v3 Issuer Alternative Name"),
+    ("2.5.29.19", "X509v3 Basic Constraints"),
+    ("2.5.29.20", "X509v3 CRL Number"),
+    ("2.5.29.21", "X509v3 CRL Reason Code"),
+    ("2.5.29.24", "Invalidity Date"),
+    ("2.5.29.27", "X509v3 Delta CRL Indicator"),
+    ("2.5.29.28", "X509v3 Issuing Distribution Point"),
+    ("2.5.29.29", "X509v3 Certificate Issuer"),
+    ("2.5.29.30", "X509v3 Name Constraints"),
+    ("2.5.29.31", "X509v3 CRL Distribution Points"),
+    ("2.5.29.32", "X509v3 Certificate Policies"),
+    ("2.5.29.32.0", "X509v3 Any Policy"),
+    ("2.5.29.33", "X509v3 Policy Mappings"),
+    ("2.5.29.35", "X509v3 Authority Key Identifier"),
+    ("2.5.29.36", "X509v3 Policy Constraints"),
+    ("2.5.29.37", "X509v3 Extended Key Usage"),
+    ("2.5.29.38", "X509v3 Authority Attribute Identifier"),
+    (
+        "2.5.29.39",
+        "X509v3 Role Specification Certificate Identifier",
+    ),
+    (
+        "2.5.29.41",
+        "X509v3 Basic Attribute Certificate Constraints",
+    ),
+    ("2.5.29.42", "X509v3 Delegated Name Constraints"),
+    ("2.5.29.43", "X509v3 Time Specification"),
+    ("2.5.29.46", "X509v3 Freshest CRL"),
+    ("2.5.29.48", "X509v3 Attribute Descriptor"),
+    ("2.5.29.49", "X509v3 User Notice"),
+    ("2.5.29.50", "X509v3 Source of Authority Identifier"),
+    ("2.5.29.52", "X509v3 Acceptable Certification Policies"),
+    ("2.5.29.54", "X509v3 Inhibit Any Policy"),
+    ("2.5.29.55", "X509v3 AC Targeting"),
+    ("2.5.29.56", "X509v3 No Revocation Available"),
+    ("2.5.29.57", "X509v3 Acceptable Privilege Policies"),
+    ("2.5.29.61", "X509v3 Indirect Issuer"),
+    ("2.5.29.62", "X509v3 No Assertion"),
+    (
+        "2.5.29.63",
+        "X509v3 Attribute Authority Issuing Distribution Point",
+    ),
+    ("2.5.29.64", "X509v3 Issued On Behalf Of"),
+    ("2.5.29.65", "X509v3 Single Use"),
+    ("2.5.29.66", "X509v3 Group Attribute Certificate"),
+    ("2.5.29.67", "X509v3 Allowed Attribute Assignments"),
+    ("2.5.29.68", "X509v3 Attribute Mappings"),
+    ("2.5.29.69", "X509v3 Holder Name Constraints"),
+    ("2.5.29.70", "X509v3 Authorization Validation"),
+    ("2.5.29.71", "X509v3 Protocol Restriction"),
+    ("2.5.29.72", "X509v3 Subject Alternative Public Key Info"),
+    ("2.5.29.73", "X509v3 Alternative Signature Algorithm"),
+    ("2.5.29.74", "X509v3 Alternative Signature Value"),
+    ("2.5.29.75", "X509v3 Associated Information"),
+    ("2.5.29.37.0", "Any Extended Key Usage"),
+    ("2.16.840.1.113730", "Netscape Communications Corp."),
+    ("2.16.840.1.113730.1", "Netscape Certificate Extension"),
+    ("2.16.840.1.113730.2", "Netscape Data Type"),
+    ("2.16.840.1.113730.1.1", "Netscape Cert Type"),
+    ("2.16.840.1.113730.1.2", "Netscape Base Url"),
+    ("2.16.840.1.113730.1.3", "Netscape Revocation Url"),
+    ("2.16.840.1.113730.1.4", "Netscape CA Revocation Url"),
+    ("2.16.840.1.113730.1.7", "Netscape Renewal Url"),
+    ("2.16.840.1.113730.1.8", "Netscape CA Policy Url"),
+    ("2.16.840.1.113730.1.12", "Netscape SSL Server Name"),
+    ("2.16.840.1.113730.1.13", "Netscape Comment"),
+    ("2.16.840.1.113730.2.5", "Netscape Certificate Sequence"),
+    ("2.16.840.1.113730.4.1", "Netscape Server Gated Crypto"),
+    ("1.3.6", "dod"),
+    ("1.3.6.1", "iana"),
+    ("1.3.6.1.1", "Directory"),
+    ("1.3.6.1.2", "Management"),
+    ("1.3.6.1.3", "Experimental"),
+    ("1.3.6.1.4", "Private"),
+    ("1.3.6.1.5", "Security"),
+    ("1.3.6.1.6", "SNMPv2"),
+    ("1.3.6.1.7", "Mail"),
+    ("1.3.6.1.4.1", "Enterprises"),
+    ("1.3.6.1.4.1.1466.344", "dcObject"),
+    (
+        "1.3.6.1.4.1.45605.1",
+        "Wi-SUN Alliance Field Area Network (FAN)",
+    ),
+    ("1.3.6.1.7.1", "MIME MHS"),
+    ("1.3.6.1.7.1.1", "mime-mhs-headings"),
+    ("1.3.6.1.7.1.2", "mime-mhs-bodies"),
+    ("1.3.6.1.7.1.1.1", "id-hex-partial-message"),
+    ("1.3.6.1.7.1.1.2", "id-hex-multipart-message"),
+    ("1.2.840.113549.1.9.16.3.8", "zlib compression"),
+    ("2.16.840.1.101.3.4.1.1", "aes-128-ecb"),
+    ("2.16.840.1.101.3.4.1.2", "aes-128-cbc"),
+    ("2.16.840.1.101.3.4.1.3", "aes-128-ofb"),
+    ("2.16.840.1.101.3.4.1.4", "aes-128-cfb"),
+    ("2.16.840.1.101.3.4.1.5", "id-aes128-wrap"),
+    ("2.16.840.1.101.3.4.1.6", "aes-128-gcm"),
+    ("2.16.840.1.101.3.4.1.7", "aes-128-ccm"),
+    ("2.16.840.1.101.3.4.1.8", "id-aes128-wrap-pad"),
+    ("2.16.840.1.101.3.4.1.21", "aes-192-ecb"),
+    ("2.16.840.1.101.3.4.1.22", "aes-192-cbc"),
+    ("2.16.840.1.101.3.4.1.23", "aes-192-ofb"),
+    ("2.16.840.1.101.3.4.1.24", "aes-192-cfb"),
+    ("2.16.840.1.101.3.4.1.25", "id-aes192-wrap"),
+    ("2.16.840.1.101.3.4.1.26", "aes-192-gcm"),
+    ("2.16.840.1.101.3.4.1.27", "aes-192-ccm"),
+    ("2.16.840.1.101.3.4.1.28", "id-aes192-wrap-pad"),
+    ("2.16.840.1.101.3.4.1.41", "aes-256-ecb"),
+    ("2.16.840.1.101.3.4.1.42", "aes-256-cbc"),
+    ("2.16.840.1.101.3.4.1.43", "aes-256-ofb"),
+    ("2.16.840.1.101.3.4.1.44", "aes-256-cfb"),
+    ("2.16.840.1.101.3.4.1.45", "id-aes256-wrap"),
+    ("2.16.840.1.101.3.4.1.46", "aes-256-gcm"),
+    ("2.16.840.1.101.3.4.1.47", "aes-256-ccm"),
+    ("2.16.840.1.101.3.4.1.48", "id-aes256-wrap-pad"),
+    ("1.3.111.2.1619.0.1.1", "aes-128-xts"),
+    ("1.3.111.2.1619.0.1.2", "aes-256-xts"),
+    ("2.16.840.1.101.3.4.2.1", "sha256"),
+    ("2.16.840.1.101.3.4.2.2", "sha384"),
+    ("2.16.840.1.101.3.4.2.3", "sha512"),
+    ("2.16.840.1.101.3.4.2.4", "sha224"),
+    ("2.16.840.1.101.3.4.2.5", "sha512-224"),
+    ("2.16.840.1.101.3.4.2.6", "sha512-256"),
+    ("2.16.840.1.101.3.4.2.7", "sha3-224"),
+    ("2.16.840.1.101.3.4.2.8", "sha3-256"),
+    ("2.16.840.1.101.3.4.2.9", "sha3-384"),
+    ("2.16.840.1.101.3.4.2.10", "sha3-512"),
+    ("2.16.840.1.101.3.4.2.11", "shake128"),
+    ("2.16.840.1.101.3.4.2.12", "shake256"),
+    ("2.16.840.1.101.3.4.2.13", "hmac-sha3-224"),
+    ("2.16.840.1.101.3.4.2.14", "hmac-sha3-256"),
+    ("2.16.840.1.101.3.4.2.15", "hmac-sha3-384"),
+    ("2.16.840.1.101.3.4.2.16", "hmac-sha3-512"),
+    ("2.16.840.1.101.3.4.2.19", "kmac128"),
+    ("2.16.840.1.101.3.4.2.20", "kmac256"),
+    ("2.16.840.1.101.3.4.3.1", "dsa_with_SHA224"),
+    ("2.16.840.1.101.3.4.3.2", "dsa_with_SHA256"),
+    ("2.16.840.1.101.3.4.3.3", "dsa_with_SHA384"),
+    ("2.16.840.1.101.3.4.3.4", "dsa_with_SHA512"),
+    ("2.16.840.1.101.3.4.3.5", "dsa_with_SHA3-224"),
+    ("2.16.840.1.101.3.4.3.6", "dsa_with_SHA3-256"),
+    ("2.16.840.1.101.3.4.3.7", "dsa_with_SHA3-384"),
+    ("2.16.840.1.101.3.4.3.8", "dsa_with_SHA3-512"),
+    ("2.16.840.1.101.3.4.3.9", "ecdsa_with_SHA3-224"),
+    ("2.16.840.1.101.3.4.3.10", "ecdsa_with_SHA3-256"),
+    ("2.16.840.1.101.3.4.3.11", "ecdsa_with_SHA3-384"),
+    ("2.16.840.1.101.3.4.3.12", "ecdsa_with_SHA3-512"),
+    ("2.16.840.1.101.3.4.3.13", "RSA-SHA3-224"),
+    ("2.16.840.1.101.3.4.3.14", "RSA-SHA3-256"),
+    ("2.16.840.1.101.3.4.3.15", "RSA-SHA3-384"),
+    ("2.16.840.1.101.3.4.3.16", "RSA-SHA3-512"),
+    ("2.16.840.1.101.3.4.3.17", "ML-DSA-44"),
+    ("2.16.840.1.101.3.4.3.18", "ML-DSA-65"),
+    ("2.16.840.1.101.3.4.3.19", "ML-DSA-87"),
+    ("2.16.840.1.101.3.4.3.20", "SLH-DSA-SHA2-128s"),
+    ("2.16.840.1.101.3.4.3.21", "SLH-DSA-SHA2-128f"),
+    ("2.16.840.1.101.3.4.3.22", "SLH-DSA-SHA2-192s"),
+    ("2.16.840.1.101.3.4.3.23", "SLH-DSA-SHA2-192f"),
+    ("2.16.840.1.101.3.4.3.24", "SLH-DSA-SHA2-256s"),
+    ("2.16.840.1.101.3.4.3.25", "SLH-DSA-SHA2-256f"),
+    ("2.16.840.1.101.3.4.3.26", "SLH-DSA-SHAKE-128s"),
+    ("2.16.840.1.101.3.4.3.27", "SLH-DSA-SHAKE-128f"),
+    ("2.16.840.1.101.3.4.3.28", "SLH-DSA-SHAKE-192s"),
+    ("2.16.840.1.101.3.4.3.29", "SLH-DSA-SHAKE-192f"),
+    ("2.16.840.1.101.3.4.3.30", "SLH-DSA-SHAKE-256s"),
+    ("2.16.840.1.101.3.4.3.31", "SLH-DSA-SHAKE-256f"),
+    ("2.16.840.1.101.3.4.3.32", "HASH-ML-DSA-44-WITH-SHA512"),
+    ("2.16.840.1.101.3.4.3.33", "HASH-ML-DSA-65-WITH-SHA512"),
+    ("2.16.840.1.101.3.4.3.34", "HASH-ML-DSA-87-WITH-SHA512"),
+    ("2.16.840.1.101.3.4.3.35", "SLH-DSA-SHA2-128s-WITH-SHA256"),
+    ("2.16.840.1.101.3.4.3.36", "SLH-DSA-SHA2-128f-WITH-SHA256"),
+    ("2.16.840.1.101.3.4.3.37", "SLH-DSA-SHA2-192s-WITH-SHA512"),
+    ("2.16.840.1.101.3.4.3.38", "SLH-DSA-SHA2-192f-WITH-SHA512"),
+    ("2.16.840.1.101.3.4.3.39", "SLH-DSA-SHA2-256s-WITH-SHA512"),
+    ("2.16.840.1.101.3.4.3.40", "SLH-DSA-SHA2-256f-WITH-SHA512"),
+    (
+        "2.16.840.1.101.3.4.3.41",
+        "SLH-DSA-SHAKE-128s-WITH-SHAKE128",
+    ),
+    (
+        "2.16.840.1.101.3.4.3.42",
+        "SLH-DSA-SHAKE-128f-WITH-SHAKE128",
+    ),
+    (
+        "2.16.840.1.101.3.4.3.43",
+        "SLH-DSA-SHAKE-192s-WITH-SHAKE256",
+    ),
+    (
+        "2.16.840.1.101.3.4.3.44",
+        "SLH-DSA-SHAKE-192f-WITH-SHAKE256",
+    ),
+    (
+        "2.16.840.1.101.3.4.3.45",
+        "SLH-DSA-SHAKE-256s-WITH-SHAKE256",
+    ),
+    (
+        "2.16.840.1.101.3.4.3.46",
+        "SLH-DSA-SHAKE-256f-WITH-SHAKE256",
+    ),
+    ("2.5.29.23", "Hold Instruction Code"),
+    ("1.2.840.10040.2.1", "Hold Instruction None"),
+    ("1.2.840.10040.2.2", "Hold Instruction Call Issuer"),
+    ("1.2.840.10040.2.3", "Hold Instruction Reject"),
+    ("0.4", "itu-t-identified-organization"),
+    ("0.4.0", "etsi"),
+    ("0.4.0.1733", "electronic-signature-standard"),
+    ("0.4.0.1733.2", "ess-attributes"),
+    ("0.4.0.1733.2.1", "id-aa-ets-mimeType"),
+    ("0.4.0.1733.2.2", "id-aa-ets-longTermValidation"),
+    ("0.4.0.1733.2.3", "id-aa-ets-SignaturePolicyDocument"),
+    ("0.4.0.1733.2.4", "id-aa-ets-archiveTimestampV3"),
+    ("0.4.0.1733.2.5", "id-aa-ATSHashIndex"),
+    ("0.4.0.19122", "cades"),
+    ("0.4.0.19122.1", "cades-attributes"),
+    ("0.4.0.19122.1.1", "id-aa-ets-signerAttrV2"),
+    ("0.4.0.19122.1.3", "id-aa-ets-sigPolicyStore"),
+    ("0.4.0.19122.1.4", "id-aa-ATSHashIndex-v2"),
+    ("0.4.0.19122.1.5", "id-aa-ATSHashIndex-v3"),
+    ("0.4.0.19122.1.6", "signedAssertion"),
+    ("0.9", "data"),
+    ("0.9.2342", "pss"),
+    ("0.9.2342.19200300", "ucl"),
+    ("0.9.2342.19200300.100", "pilot"),
+    ("0.9.2342.19200300.100.1", "pilotAttributeType"),
+    ("0.9.2342.19200300.100.3", "pilotAttributeSyntax"),
+    ("0.9.2342.19200300.100.4", "pilotObjectClass"),
+    ("0.9.2342.19200300.100.10", "pilotGroups"),
+    ("0.9.2342.19200300.100.3.4", "iA5StringSyntax"),
+    ("0.9.2342.19200300.100.3.5", "caseIgnoreIA5StringSyntax"),
+    ("0.9.2342.19200300.100.4.3", "pilotObject"),
+    ("0.9.2342.19200300.100.4.4", "pilotPerson"),
+    ("0.9.2342.19200300.100.4.5", "account"),
+    ("0.9.2342.19200300.100.4.6", "document"),
+    ("0.9.2342.19200300.100.4.7", "room"),
+    ("0.9.2342.19200300.100.4.9", "documentSeries"),
+    ("0.9.2342.19200300.100.4.13", "Domain"),
+    ("0.9.2342.19200300.100.4.14", "rFC822localPart"),
+    ("0.9.2342.19200300.100.4.15", "dNSDomain"),
+    ("0.9.2342.19200300.100.4.17", "domainRelatedObject"),
+    ("0.9.2342.19200300.100.4.18", "friendlyCountry"),
+    ("0.9.2342.19200300.100.4.19", "simpleSecurityObject"),
+    ("0.9.2342.19200300.100.4.20", "pilotOrganization"),
+    ("0.9.2342.19200300.100.4.21", "pilotDSA"),
+    ("0.9.2342.19200300.100.4.22", "qualityLabelledData"),
+    ("0.9.2342.19200300.100.1.1", "userId"),
+    ("0.9.2342.19200300.100.1.2", "textEncodedORAddress"),
+    ("0.9.2342.19200300.100.1.3", "rfc822Mailbox"),
+    ("0.9.2342.19200300.100.1.4", "info"),
+    ("0.9.2342.19200300.100.1.5", "favouriteDrink"),
+    ("0.9.2342.19200300.100.1.6", "roomNumber"),
+    ("0.9.2342.19200300.100.1.7", "photo"),
+    ("0.9.2342.19200300.100.1.8", "userClass"),
+    ("0.9.2342.19200300.100.1.9", "host"),
+    ("0.9.2342.19200300.100.1.10", "manager"),
+    ("0.9.2342.19200300.100.1.11", "documentIdentifier"),
+    ("0.9.2342.19200300.100.1.12", "documentTitle"),
+    ("0.9.2342.19200300.100.1.13", "documentVersion"),
+    ("0.9.2342.19200300.100.1.14", "documentAuthor"),
+    ("0.9.2342.19200300.100.1.15", "documentLocation"),
+    ("0.9.2342.19200300.100.1.20", "homeTelephoneNumber"),
+    ("0.9.2342.19200300.100.1.21", "secretary"),
+    ("0.9.2342.19200300.100.1.22", "otherMailbox"),
+    ("0.9.2342.19200300.100.1.23", "lastModifiedTime"),
+    ("0.9.2342.19200300.100.1.24", "lastModifiedBy"),
+    ("0.9.2342.19200300.100.1.25", "domainComponent"),
+    ("0.9.2342.19200300.100.1.26", "aRecord"),
+    ("0.9.2342.19200300.100.1.27", "pilotAttributeType27"),
+    ("0.9.2342.19200300.100.1.28", "mXRecord"),
+    ("0.9.2342.19200300.100.1.29", "nSRecord"),
+    ("0.9.2342.19200300.100.1.30", "sOARecord"),
+    ("0.9.2342.19200300.100.1.31", "cNAMERecord"),
+    ("0.9.2342.19200300.100.1.37", "associatedDomain"),
+    ("0.9.2342.19200300.100.1.38", "associatedName"),
+    ("0.9.2342.19200300.100.1.39", "homePostalAddress"),
+    ("0.9.2342.19200300.100.1.40", "personalTitle"),
+    ("0.9.2342.19200300.100.1.41", "mobileTelephoneNumber"),
+    ("0.9.2342.19200300.100.1.42", "pagerTelephoneNumber"),
+    ("0.9.2342.19200300.100.1.43", "friendlyCountryName"),
+    ("0.9.2342.19200300.100.1.44", "uniqueIdentifier"),
+    ("0.9.2342.19200300.100.1.45", "organizationalStatus"),
+    ("0.9.2342.19200300.100.1.46", "janetMailbox"),
+    ("0.9.2342.19200300.100.1.47", "mailPreferenceOption"),
+    ("0.9.2342.19200300.100.1.48", "buildingName"),
+    ("0.9.2342.19200300.100.1.49", "dSAQuality"),
+    ("0.9.2342.19200300.100.1.50", "singleLevelQuality"),
+    ("0.9.2342.19200300.100.1.51", "subtreeMinimumQuality"),
+    ("0.9.2342.19200300.100.1.52", "subtreeMaximumQuality"),
+    ("0.9.2342.19200300.100.1.53", "personalSignature"),
+    ("0.9.2342.19200300.100.1.54", "dITRedirect"),
+    ("0.9.2342.19200300.100.1.55", "audio"),
+    ("0.9.2342.19200300.100.1.56", "documentPublisher"),
+    ("2.23.42", "Secure Electronic Transactions"),
+    ("2.23.42.0", "content types"),
+    ("2.23.42.1", "message extensions"),
+    ("2.23.42.3", "set-attr"),
+    ("2.23.42.5", "set-policy"),
+    ("2.23.42.7", "certificate extensions"),
+    ("2.23.42.8", "set-brand"),
+    ("2.23.42.0.0", "setct-PANData"),
+    ("2.23.42.0.1", "setct-PANToken"),
+    ("2.23.42.0.2", "setct-PANOnly"),
+    ("2.23.42.0.3", "setct-OIData"),
+    ("2.23.42.0.4", "setct-PI"),
+    ("2.23.42.0.5", "setct-PIData"),
+    ("2.23.42.0.6", "setct-PIDataUnsigned"),
+    ("2.23.42.0.7", "setct-HODInput"),
+    ("2.23.42.0.8", "setct-AuthResBaggage"),
+    ("2.23.42.0.9", "setct-AuthRevReqBaggage"),
+    ("2.23.42.0.10", "setct-AuthRevResBaggage"),
+    ("2.23.42.0.11", "setct-CapTokenSeq"),
+    ("2.23.42.0.12", "setct-PInitResData"),
+    ("2.23.42.0.13", "setct-PI-TBS"),
+    ("2.23.42.0.14", "setct-PResData"),
+    ("2.23.42.0.16", "setct-AuthReqTBS"),
+    ("2.23.42.0.17", "setct-AuthResTBS"),
+    ("2.23.42.0.18", "setct-AuthResTBSX"),
+    ("2.23.42.0.19", "setct-AuthTokenTBS"),
+    ("2.23.42.0.20", "setct-CapTokenData"),
+    ("2.23.42.0.21", "setct-CapTokenTBS"),
+    ("2.23.42.0.22", "setct-AcqCardCodeMsg"),
+    ("2.23.42.0.23", "setct-AuthRevReqTBS"),
+    ("2.23.42.0.24", "setct-AuthRevResData"),
+    ("2.23.42.0.25", "setct-AuthRevResTBS"),
+    ("2.23.42.0.26", "setct-CapReqTBS"),
+    ("2.23.42.0.27", "setct-CapReqTBSX"),
+    ("2.23.42.0.28", "setct-CapResData"),
+    ("2.23.42.0.29", "setct-CapRevReqTBS"),
+    ("2.23.42.0.30", "setct-CapRevReqTBSX"),
+    ("2.23.42.0.31", "setct-CapRevResData"),
+    ("2.23.42.0.32", "setct-CredReqTBS"),
+    ("2.23.42.0.33", "setct-CredReqTBSX"),
+    ("2.23.42.0.34", "setct-CredResData"),
+    ("2.23.42.0.35", "setct-CredRevReqTBS"),
+    ("2.23.42.0.36", "setct-CredRevReqTBSX"),
+    ("2.23.42.0.37", "setct-CredRevResData"),
+    ("2.23.42.0.38", "setct-PCertReqData"),
+    ("2.23.42.0.39", "setct-PCertResTBS"),
+    ("2.23.42.0.40", "setct-BatchAdminReqData"),
+    ("2.23.42.0.41", "setct-BatchAdminResData"),
+    ("2.23.42.0.42", "setct-CardCInitResTBS"),
+    ("2.23.42.0.43", "setct-MeAqCInitResTBS"),
+    ("2.23.42.0.44", "setct-RegFormResTBS"),
+    ("2.23.42.0.45", "setct-CertReqData"),
+    ("2.23.42.0.46", "setct-CertReqTBS"),
+    ("2.23.42.0.47", "setct-CertResData"),
+    ("2.23.42.0.48", "setct-CertInqReqTBS"),
+    ("2.23.42.0.49", "setct-ErrorTBS"),
+    ("2.23.42.0.50", "setct-PIDualSignedTBE"),
+    ("2.23.42.0.51", "setct-PIUnsignedTBE"),
+    ("2.23.42.0.52", "setct-AuthReqTBE"),
+    ("2.23.42.0.53", "setct-AuthResTBE"),
+    ("2.23.42.0.54", "setct-AuthResTBEX"),
+    ("2.23.42.0.55", "setct-AuthTokenTBE"),
+    ("2.23.42.0.56", "setct-CapTokenTBE"),
+    ("2.23.42.0.57", "setct-CapTokenTBEX"),
+    ("2.23.42.0.58", "setct-AcqCardCodeMsgTBE"),
+    ("2.23.42.0.59", "setct-AuthRevReqTBE"),
+    ("2.23.42.0.60", "setct-AuthRevResTBE"),
+    ("2.23.42.0.61", "setct-AuthRevResTBEB"),
+    ("2.23.42.0.62", "setct-CapReqTBE"),
+    ("2.23.42.0.63", "setct-CapReqTBEX"),
+    ("2.23.42.0.64", "setct-CapResTBE"),
+    ("2.23.42.0.65", "setct-CapRevReqTBE"),
+    ("2.23.42.0.66", "setct-CapRevReqTBEX"),
+    ("2.23.42.0.67", "setct-CapRevResTBE"),
+    ("2.23.42.0.68", "setct-CredReqTBE"),
+    ("2.23.42.0.69", "setct-CredReqTBEX"),
+    ("2.23.42.0.70", "setct-CredResTBE"),
+    ("2.23.42.0.71", "setct-CredRevReqTBE"),
+    ("2.23.42.0.72", "setct-CredRevReqTBEX"),
+    ("2.23.42.0.73", "setct-CredRevResTBE"),
+    ("2.23.42.0.74", "setct-BatchAdminReqTBE"),
+    ("2.23.42.0.75", "setct-BatchAdminResTBE"),
+    ("2.23.42.0.76", "setct-RegFormReqTBE"),
+    ("2.23.42.0.77", "setct-CertReqTBE"),
+    ("2.23.42.0.78", "setct-CertReqTBEX"),
+    ("2.23.42.0.79", "setct-CertResTBE"),
+    ("2.23.42.0.80", "setct-CRLNotificationTBS"),
+    ("2.23.42.0.81", "setct-CRLNotificationResTBS"),
+    ("2.23.42.0.82", "setct-BCIDistributionTBS"),
+    ("2.23.42.1.1", "generic cryptogram"),
+    ("2.23.42.1.3", "merchant initiated auth"),
+    ("2.23.42.1.4", "setext-pinSecure"),
+    ("2.23.42.1.5", "setext-pinAny"),
+    ("2.23.42.1.7", "setext-track2"),
+    ("2.23.42.1.8", "additional verification"),
+    ("2.23.42.5.0", "set-policy-root"),
+    ("2.23.42.7.0", "setCext-hashedRoot"),
+    ("2.23.42.7.1", "setCext-certType"),
+    ("2.23.42.7.2", "setCext-merchData"),
+    ("2.23.42.7.3", "setCext-cCertRequired"),
+    ("2.23.42.7.4", "setCext-tunneling"),
+    ("2.23.42.7.5", "setCext-setExt"),
+    ("2.23.42.7.6", "setCext-setQualf"),
+    ("2.23.42.7.7", "setCext-PGWYcapabilities"),
+    ("2.23.42.7.8", "setCext-TokenIdentifier"),
+    ("2.23.42.7.9", "setCext-Track2Data"),
+    ("2.23.42.7.10", "setCext-TokenType"),
+    ("2.23.42.7.11", "setCext-IssuerCapabilities"),
+    ("2.23.42.3.0", "setAttr-Cert"),
+    ("2.23.42.3.1", "payment gateway capabilities"),
+    ("2.23.42.3.2", "setAttr-TokenType"),
+    ("2.23.42.3.3", "issuer capabilities"),
+    ("2.23.42.3.0.0", "set-rootKeyThumb"),
+    ("2.23.42.3.0.1", "set-addPolicy"),
+    ("2.23.42.3.2.1", "setAttr-Token-EMV"),
+    ("2.23.42.3.2.2", "setAttr-Token-B0Prime"),
+    ("2.23.42.3.3.3", "setAttr-IssCap-CVM"),
+    ("2.23.42.3.3.4", "setAttr-IssCap-T2"),
+    ("2.23.42.3.3.5", "setAttr-IssCap-Sig"),
+    ("2.23.42.3.3.3.1", "generate cryptogram"),
+    ("2.23.42.3.3.4.1", "encrypted track 2"),
+    ("2.23.42.3.3.4.2", "cleartext track 2"),
+    ("2.23.42.3.3.5.1", "ICC or token signature"),
+    ("2.23.42.3.3.5.2", "secure device signature"),
+    ("2.23.42.8.1", "set-brand-IATA-ATA"),
+    ("2.23.42.8.30", "set-brand-Diners"),
+    ("2.23.42.8.34", "set-brand-AmericanExpress"),
+    ("2.23.42.8.35", "set-brand-JCB"),
+    ("2.23.42.8.4", "set-brand-Visa"),
+    ("2.23.42.8.5", "set-brand-MasterCard"),
+    ("2.23.42.8.6011", "set-brand-Novus"),
+    ("1.2.840.113549.3.10", "des-cdmf"),
+    ("1.2.840.113549.1.1.6", "rsaOAEPEncryptionSET"),
+    ("1.0.10118.3.0.55", "whirlpool"),
+    ("1.2.643.2.2", "cryptopro"),
+    ("1.2.643.2.9", "cryptocom"),
+    ("1.2.643.7.1", "id-tc26"),
+    ("1.2.643.2.2.3", "GOST R 34.11-94 with GOST R 34.10-2001"),
+    ("1.2.643.2.2.4", "GOST R 34.11-94 with GOST R 34.10-94"),
+    ("1.2.643.2.2.9", "GOST R 34.11-94"),
+    ("1.2.643.2.2.10", "HMAC GOST 34.11-94"),
+    ("1.2.643.2.2.19", "GOST R 34.10-2001"),
+    ("1.2.643.2.2.20", "GOST R 34.10-94"),
+    ("1.2.643.2.2.21", "GOST 28147-89"),
+    ("1.2.643.2.2.22", "GOST 28147-89 MAC"),
+    ("1.2.643.2.2.23", "GOST R 34.11-94 PRF"),
+    ("1.2.643.2.2.98", "GOST R 34.10-2001 DH"),
+    ("1.2.643.2.2.99", "GOST R 34.10-94 DH"),
+    ("1.2.643.2.2.14.1", "id-Gost28147-89-CryptoPro-KeyMeshing"),
+    ("1.2.643.2.2.14.0", "id-Gost28147-89-None-KeyMeshing"),
+    ("1.2.643.2.2.30.0", "id-GostR3411-94-TestParamSet"),
+    ("1.2.643.2.2.30.1", "id-GostR3411-94-CryptoProParamSet"),
+    ("1.2.643.2.2.31.0", "id-Gost28147-89-TestParamSet"),
+    ("1.2.643.2.2.31.1", "id-Gost28147-89-CryptoPro-A-ParamSet"),
+    ("1.2.643.2.2.31.2", "id-Gost28147-89-CryptoPro-B-ParamSet"),
+    ("1.2.643.2.2.31.3", "id-Gost28147-89-CryptoPro-C-ParamSet"),
+    ("1.2.643.2.2.31.4", "id-Gost28147-89-CryptoPro-D-ParamSet"),
+    (
+        "1.2.643.2.2.31.5",
+        "id-Gost28147-89-CryptoPro-Oscar-1-1-ParamSet",
+    ),
+    (
+        "1.2.643.2.2.31.6",
+        "id-Gost28147-89-CryptoPro-Oscar-1-0-ParamSet",
+    ),
+    (
+        "1.2.643.2.2.31.7",
+        "id-Gost28147-89-CryptoPro-RIC-1-ParamSet",
+    ),
+    ("1.2.643.2.2.32.0", "id-GostR3410-94-TestParamSet"),
+    ("1.2.643.2.2.32.2", "id-GostR3410-94-CryptoPro-A-ParamSet"),
+    ("1.2.643.2.2.32.3", "id-GostR3410-94-CryptoPro-B-ParamSet"),
+    ("1.2.643.2.2.32.4", "id-GostR3410-94-CryptoPro-C-ParamSet"),
+    ("1.2.643.2.2.32.5", "id-GostR3410-94-CryptoPro-D-ParamSet"),
+    (
+        "1.2.643.2.2.33.1",
+        "id-GostR3410-94-CryptoPro-XchA-ParamSet",
+    ),
+    (
+        "1.2.643.2.2.33.2",
+        "id-GostR3410-94-CryptoPro-XchB-ParamSet",
+    ),
+    (
+        "1.2.643.2.2.33.3",
+        "id-GostR3410-94-CryptoPro-XchC-ParamSet",
+    ),
+    ("1.2.643.2.2.35.0", "id-GostR3410-2001-TestParamSet"),
+    ("1.2.643.2.2.35.1", "id-GostR3410-2001-CryptoPro-A-ParamSet"),
+    ("1.2.643.2.2.35.2", "id-GostR3410-2001-CryptoPro-B-ParamSet"),
+    ("1.2.643.2.2.35.3", "id-GostR3410-2001-CryptoPro-C-ParamSet"),
+    (
+        "1.2.643.2.2.36.0",
+        "id-GostR3410-2001-CryptoPro-XchA-ParamSet",
+    ),
+    (
+        "1.2.643.2.2.36.1",
+        "id-GostR3410-2001-CryptoPro-XchB-ParamSet",
+    ),
+    ("1.2.643.2.2.20.1", "id-GostR3410-94-a"),
+    ("1.2.643.2.2.20.2", "id-GostR3410-94-aBis"),
+    ("1.2.643.2.2.20.3", "id-GostR3410-94-b"),
+    ("1.2.643.2.2.20.4", "id-GostR3410-94-bBis"),
+    ("1.2.643.2.9.1.6.1", "GOST 28147-89 Cryptocom ParamSet"),
+    ("1.2.643.2.9.1.5.3", "GOST 34.10-94 Cryptocom"),
+    ("1.2.643.2.9.1.5.4", "GOST 34.10-2001 Cryptocom"),
+    (
+        "1.2.643.2.9.1.3.3",
+        "GOST R 34.11-94 with GOST R 34.10-94 Cryptocom",
+    ),
+    (
+        "1.2.643.2.9.1.3.4",
+        "GOST R 34.11-94 with GOST R 34.10-2001 Cryptocom",
+    ),
+    (
+        "1.2.643.2.9.1.8.1",
+        "GOST R 3410-2001 Parameter Set Cryptocom",
+    ),
+    ("1.2.643.7.1.1", "id-tc26-algorithms"),
+    ("1.2.643.7.1.1.1", "id-tc26-sign"),
+    (
+        "1.2.643.7.1.1.1.1",
+        "GOST R 34.10-2012 with 256 bit modulus",
+    ),
+    (
+        "1.2.643.7.1.1.1.2",
+        "GOST R 34.10-2012 with 512 bit modulus",
+    ),
+    ("1.2.643.7.1.1.2", "id-tc26-digest"),
+    ("1.2.643.7.1.1.2.2", "GOST R 34.11-2012 with 256 bit hash"),
+    ("1.2.643.7.1.1.2.3", "GOST R 34.11-2012 with 512 bit hash"),
+    ("1.2.643.7.1.1.3", "id-tc26-signwithdigest"),
+    (
+        "1.2.643.7.1.1.3.2",
+        "GOST R 34.10-2012 with GOST R 34.11-2012 (256 bit)",
+    ),
+    (
+        "1.2.643.7.1.1.3.3",
+        "GOST R 34.10-2012 with GOST R 34.11-2012 (512 bit)",
+    ),
+    ("1.2.643.7.1.1.4", "id-tc26-mac"),
+    ("1.2.643.7.1.1.4.1", "HMAC GOST 34.11-2012 256 bit"),
+    ("1.2.643.7.1.1.4.2", "HMAC GOST 34.11-2012 512 bit"),
+    ("1.2.643.7.1.1.5", "id-tc26-cipher"),
+    ("1.2.643.7.1.1.5.1", "id-tc26-cipher-gostr3412-2015-magma"),
+    ("1.2.643.7.1.1.5.1.1", "magma-ctr-acpkm"),
+    ("1.2.643.7.1.1.5.1.2", "magma-ctr-acpkm-omac"),
+    (
+        "1.2.643.7.1.1.5.2",
+        "id-tc26-cipher-gostr3412-2015-kuznyechik",
+    ),
+    ("1.2.643.7.1.1.5.2.1", "kuznyechik-ctr-acpkm"),
+    ("1.2.643.7.1.1.5.2.2", "kuznyechik-ctr-acpkm-omac"),
+    ("1.2.643.7.1.1.6", "id-tc26-agreement"),
+    ("1.2.643.7.1.1.6.1", "id-tc26-agreement-gost-3410-2012-256"),
+    ("1.2.643.7.1.1.6.2", "id-tc26-agreement-gost-3410-2012-512"),
+    ("1.2.643.7.1.1.7", "id-tc26-wrap"),
+    ("1.2.643.7.1.1.7.1", "id-tc26-wrap-gostr3412-2015-magma"),
+    ("1.2.643.7.1.1.7.1.1", "magma-kexp15"),
+    (
+        "1.2.643.7.1.1.7.2",
+        "id-tc26-wrap-gostr3412-2015-kuznyechik",
+    ),
+    ("1.2.643.7.1.1.7.2.1", "kuznyechik-kexp15"),
+    ("1.2.643.7.1.2", "id-tc26-constants"),
+    ("1.2.643.7.1.2.1", "id-tc26-sign-constants"),
+    ("1.2.643.7.1.2.1.1", "id-tc26-gost-3410-2012-256-constants"),
+    (
+        "1.2.643.7.1.2.1.1.1",
+        "GOST R 34.10-2012 (256 bit) ParamSet A",
+    ),
+    (
+        "1.2.643.7.1.2.1.1.2",
+        "GOST R 34.10-2012 (256 bit) ParamSet B",
+    ),
+    (
+        "1.2.643.7.1.2.1.1.3",
+        "GOST R 34.10-2012 (256 bit) ParamSet C",
+    ),
+    (
+        "1.2.643.7.1.2.1.1.4",
+        "GOST R 34.10-2012 (256 bit) ParamSet D",
+    ),
+    ("1.2.643.7.1.2.1.2", "id-tc26-gost-3410-2012-512-constants"),
+    (
+        "1.2.643.7.1.2.1.2.0",
+        "GOST R 34.10-2012 (512 bit) testing parameter set",
+    ),
+    (
+        "1.2.643.7.1.2.1.2.1",
+        "GOST R 34.10-2012 (512 bit) ParamSet A",
+    ),
+    (
+        "1.2.643.7.1.2.1.2.2",
+        "GOST R 34.10-2012 (512 bit) ParamSet B",
+    ),
+    (
+        "1.2.643.7.1.2.1.2.3",
+        "GOST R 34.10-2012 (512 bit) ParamSet C",
+    ),
+    ("1.2.643.7.1.2.2", "id-tc26-digest-constants"),
+    ("1.2.643.7.1.2.5", "id-tc26-cipher-constants"),
+    ("1.2.643.7.1.2.5.1", "id-tc26-gost-28147-constants"),
+    ("1.2.643.7.1.2.5.1.1", "GOST 28147-89 TC26 parameter set"),
+    ("1.2.643.3.131.1.1", "INN"),
+    ("1.2.643.100.1", "OGRN"),
+    ("1.2.643.100.3", "SNILS"),
+    ("1.2.643.100.5", "OGRNIP"),
+    ("1.2.643.100.111", "Signing Tool of Subject"),
+    ("1.2.643.100.112", "Signing Tool of Issuer"),
+    ("1.2.643.100.113", "Class of Signing Tool"),
+    ("1.2.643.100.113.1", "Class of Signing Tool KC1"),
+    ("1.2.643.100.113.2", "Class of Signing Tool KC2"),
+    ("1.2.643.100.113.3", "Class of Signing Tool KC3"),
+    ("1.2.643.100.113.4", "Class of Signing Tool KB1"),
+    ("1.2.643.100.113.5", "Class of Signing Tool KB2"),
+    ("1.2.643.100.113.6", "Class of Signing Tool KA1"),
+    ("1.2.392.200011.61.1.1.1.2", "camellia-128-cbc"),
+    ("1.2.392.200011.61.1.1.1.3", "camellia-192-cbc"),
+    ("1.2.392.200011.61.1.1.1.4", "camellia-256-cbc"),
+    ("1.2.392.200011.61.1.1.3.2", "id-camellia128-wrap"),
+    ("1.2.392.200011.61.1.1.3.3", "id-camellia192-wrap"),
+    ("1.2.392.200011.61.1.1.3.4", "id-camellia256-wrap"),
+    ("0.3.4401.5.3.1.9.1", "camellia-128-ecb"),
+    ("0.3.4401.5.3.1.9.3", "camellia-128-ofb"),
+    ("0.3.4401.5.3.1.9.4", "camellia-128-cfb"),
+    ("0.3.4401.5.3.1.9.6", "camellia-128-gcm"),
+    ("0.3.4401.5.3.1.9.7", "camellia-128-ccm"),
+    ("0.3.4401.5.3.1.9.9", "camellia-128-ctr"),
+    ("0.3.4401.5.3.1.9.10", "camellia-128-cmac"),
+    ("0.3.4401.5.3.1.9.21", "camellia-192-ecb"),
+    ("0.3.4401.5.3.1.9.23", "camellia-192-ofb"),
+    ("0.3.4401.5.3.1.9.24", "camellia-192-cfb"),
+    ("0.3.4401.5.3.1.9.26", "camellia-192-gcm"),
+    ("0.3.4401.5.3.1.9.27", "camellia-192-ccm"),
+    ("0.3.4401.5.3.1.9.29", "camellia-192-ctr"),
+    ("0.3.4401.5.3.1.9.30", "camellia-192-cmac"),
+    ("0.3.4401.5.3.1.9.41", "camellia-256-ecb"),
+    ("0.3.4401.5.3.1.9.43", "camellia-256-ofb"),
+    ("0.3.4401.5.3.1.9.44", "camellia-256-cfb"),
+    ("0.3.4401.5.3.1.9.46", "camellia-256-gcm"),
+    ("0.3.4401.5.3.1.9.47", "camellia-256-ccm"),
+    ("0.3.4401.5.3.1.9.49", "camellia-256-ctr"),
+    ("0.3.4401.5.3.1.9.50", "camellia-256-cmac"),
+    ("1.2.410.200046.1.1.1", "aria-128-ecb"),
+    ("1.2.410.200046.1.1.2", "aria-128-cbc"),
+    ("1.2.410.200046.1.1.3", "aria-128-cfb"),
+    ("1.2.410.200046.1.1.4", "aria-128-ofb"),
+    ("1.2.410.200046.1.1.5", "aria-128-ctr"),
+    ("1.2.410.200046.1.1.6", "aria-192-ecb"),
+    ("1.2.410.200046.1.1.7", "aria-192-cbc"),
+    ("1.2.410.200046.1.1.8", "aria-192-cfb"),
+    ("1.2.410.200046.1.1.9", "aria-192-ofb"),
+    ("1.2.410.200046.1.1.10", "aria-192-ctr"),
+    ("1.2.410.200046.1.1.11", "aria-256-ecb"),
+    ("1.2.410.200046.1.1.12", "aria-256-cbc"),
+    ("1.2.410.200046.1.1.13", "aria-256-cfb"),
+    ("1.2.410.200046.1.1.14", "aria-256-ofb"),
+    ("1.2.410.200046.1.1.15", "aria-256-ctr"),
+    ("1.2.410.200046.1.1.37", "aria-128-ccm"),
+    ("1.2.410.200046.1.1.38", "aria-192-ccm"),
+    ("1.2.410.200046.1.1.39", "aria-256-ccm"),
+    ("1.2.410.200046.1.1.34", "aria-128-gcm"),
+    ("1.2.410.200046.1.1.35", "aria-192-gcm"),
+    ("1.2.410.200046.1.1.36", "aria-256-gcm"),
+    ("1.2.410.200004", "kisa"),
+    ("1.2.410.200004.1.3", "seed-ecb"),
+    ("1.2.410.200004.1.4", "seed-cbc"),
+    ("1.2.410.200004.1.5", "seed-cfb"),
+    ("1.2.410.200004.1.6", "seed-ofb"),
+    ("1.2.156.10197.1.104.1", "sm4-ecb"),
+    ("1.2.156.10197.1.104.2", "sm4-cbc"),
+    ("1.2.156.10197.1.104.3", "sm4-ofb"),
+    ("1.2.156.10197.1.104.4", "sm4-cfb"),
+    ("1.2.156.10197.1.104.5", "sm4-cfb1"),
+    ("1.2.156.10197.1.104.6", "sm4-cfb8"),
+    ("1.2.156.10197.1.104.7", "sm4-ctr"),
+    ("1.2.156.10197.1.104.8", "sm4-gcm"),
+    ("1.2.156.10197.1.104.9", "sm4-ccm"),
+    ("1.2.156.10197.1.104.10", "sm4-xts"),
+    ("1.2.840.10046.2.1", "X9.42 DH"),
+    ("1.3.36.3.3.2.8.1.1.1", "brainpoolP160r1"),
+    ("1.3.36.3.3.2.8.1.1.2", "brainpoolP160t1"),
+    ("1.3.36.3.3.2.8.1.1.3", "brainpoolP192r1"),
+    ("1.3.36.3.3.2.8.1.1.4", "brainpoolP192t1"),
+    ("1.3.36.3.3.2.8.1.1.5", "brainpoolP224r1"),
+    ("1.3.36.3.3.2.8.1.1.6", "brainpoolP224t1"),
+    ("1.3.36.3.3.2.8.1.1.7", "brainpoolP256r1"),
+    ("1.3.36.3.3.2.8.1.1.8", "brainpoolP256t1"),
+    ("1.3.36.3.3.2.8.1.1.9", "brainpoolP320r1"),
+    ("1.3.36.3.3.2.8.1.1.10", "brainpoolP320t1"),
+    ("1.3.36.3.3.2.8.1.1.11", "brainpoolP384r1"),
+    ("1.3.36.3.3.2.8.1.1.12", "brainpoolP384t1"),
+    ("1.3.36.3.3.2.8.1.1.13", "brainpoolP512r1"),
+    ("1.3.36.3.3.2.8.1.1.14", "brainpoolP512t1"),
+    ("1.3.133.16.840.63.0.2", "dhSinglePass-stdDH-sha1kdf-scheme"),
+    ("1.3.132.1.11.0", "dhSinglePass-stdDH-sha224kdf-scheme"),
+    ("1.3.132.1.11.1", "dhSinglePass-stdDH-sha256kdf-scheme"),
+    ("1.3.132.1.11.2", "dhSinglePass-stdDH-sha384kdf-scheme"),
+    ("1.3.132.1.11.3", "dhSinglePass-stdDH-sha512kdf-scheme"),
+    (
+        "1.3.133.16.840.63.0.3",
+        "dhSinglePass-cofactorDH-sha1kdf-scheme",
+    ),
+    ("1.3.132.1.14.0", "dhSinglePass-cofactorDH-sha224kdf-scheme"),
+    ("1.3.132.1.14.1", "dhSinglePass-cofactorDH-sha256kdf-scheme"),
+    ("1.3.132.1.14.2", "dhSinglePass-cofactorDH-sha384kdf-scheme"),
+    ("1.3.132.1.14.3", "dhSinglePass-cofactorDH-sha512kdf-scheme"),
+    ("1.3.6.1.4.1.11129.2.4.2", "CT Precertificate SCTs"),
+    ("1.3.6.1.4.1.11129.2.4.3", "CT Precertificate Poison"),
+    ("1.3.6.1.4.1.11129.2.4.4", "CT Precertificate Signer"),
+    ("1.3.6.1.4.1.11129.2.4.5", "CT Certificate SCTs"),
+    ("1.3.6.1.4.1.311.60.2.1.1", "jurisdictionLocalityName"),
+    (
+        "1.3.6.1.4.1.311.60.2.1.2",
+        "jurisdictionStateOrProvinceName",
+    ),
+    ("1.3.6.1.4.1.311.60.2.1.3", "jurisdictionCountryName"),
+    ("1.3.6.1.4.1.11591.4.11", "scrypt"),
+    ("1.3.6.1.5.2.3", "id-pkinit"),
+    ("1.3.6.1.5.2.3.4", "PKINIT Client Auth"),
+    ("1.3.6.1.5.2.3.5", "Signing KDC Response"),
+    ("1.3.101.110", "X25519"),
+    ("1.3.101.111", "X448"),
+    ("1.3.101.112", "ED25519"),
+    ("1.3.101.113", "ED448"),
+    ("1.2.804", "ISO-UA"),
+    ("1.2.804.2.1.1.1", "ua-pki"),
+    ("1.2.804.2.1.1.1.1.1.1", "DSTU Gost 28147-2009"),
+    ("1.2.804.2.1.1.1.1.1.1.2", "DSTU Gost 28147-2009 OFB mode"),
+    ("1.2.804.2.1.1.1.1.1.1.3", "DSTU Gost 28147-2009 CFB mode"),
+    ("1.2.804.2.1.1.1.1.1.1.5", "DSTU Gost 28147-2009 key wrap"),
+    ("1.2.804.2.1.1.1.1.1.2", "HMAC DSTU Gost 34311-95"),
+    ("1.2.804.2.1.1.1.1.2.1", "DSTU Gost 34311-95"),
+    ("1.2.804.2.1.1.1.1.3.1.1", "DSTU 4145-2002 little endian"),
+    ("1.2.804.2.1.1.1.1.3.1.1.1.1", "DSTU 4145-2002 big endian"),
+    ("1.2.804.2.1.1.1.1.3.1.1.2.0", "DSTU curve 0"),
+    ("1.2.804.2.1.1.1.1.3.1.1.2.1", "DSTU curve 1"),
+    ("1.2.804.2.1.1.1.1.3.1.1.2.2", "DSTU curve 2"),
+    ("1.2.804.2.1.1.1.1.3.1.1.2.3", "DSTU curve 3"),
+    ("1.2.804.2.1.1.1.1.3.1.1.2.4", "DSTU curve 4"),
+    ("1.2.804.2.1.1.1.1.3.1.1.2.5", "DSTU curve 5"),
+    ("1.2.804.2.1.1.1.1.3.1.1.2.6", "DSTU curve 6"),
+    ("1.2.804.2.1.1.1.1.3.1.1.2.7", "DSTU curve 7"),
+    ("1.2.804.2.1.1.1.1.3.1.1.2.8", "DSTU curve 8"),
+    ("1.2.804.2.1.1.1.1.3.1.1.2.9", "DSTU curve 9"),
+    ("2.16.840.1.113894", "Oracle organization"),
+    ("2.16.840.1.113894.746875.1.1", "Trusted key usage (Oracle)"),
+    ("2.23.133", "Trusted Computing Group"),
+    ("2.23.133.1", "tcg-tcpaSpecVersion"),
+    ("2.23.133.2", "Trusted Computing Group Attributes"),
+    ("2.23.133.3", "Trusted Computing Group Protocols"),
+    ("2.23.133.4", "Trusted Computing Group Algorithms"),
+    ("2.23.133.5", "Trusted Computing Group Platform Classes"),
+    (
+        "2.23.133.6",
+        "Trusted Computing Group Certificate Extensions",
+    ),
+    ("2.23.133.8", "Trusted Computing Group Key Purposes"),
+    (
+        "2.23.133.11",
+        "Trusted Computing Group Certificate Policies",
+    ),
+    ("2.23.133.17", "Trusted Computing Group Address Formats"),
+    ("2.23.133.18", "Trusted Computing Group Registry"),
+    ("2.23.133.19", "Trusted Computing Group Traits"),
+    ("2.23.133.5.1", "Trusted Computing Group Common"),
+    ("2.23.133.5.1.1", "TCG Platform Manufacturer String"),
+    ("2.23.133.5.1.2", "TCG Platform Manufacturer ID"),
+    ("2.23.133.5.1.3", "TCG Platform Configuration URI"),
+    ("2.23.133.5.1.4", "TCG Platform Model"),
+    ("2.23.133.5.1.5", "TCG Platform Version"),
+    ("2.23.133.5.1.6", "TCG Platform Serial Number"),
+    ("2.23.133.5.1.7", "TCG Platform Configuration"),
+    ("2.23.133.5.1.8", "TCG Platform Identifier"),
+    ("2.23.133.2.1", "TPM Manufacturer"),
+    ("2.23.133.2.2", "TPM Model"),
+    ("2.23.133.2.3", "TPM Version"),
+    ("2.23.133.2.10", "Security Qualities"),
+    ("2.23.133.2.11", "TPM Protection Profile"),
+    ("2.23.133.2.12", "TPM Security Target"),
+    ("2.23.133.2.13", "TBB Protection Profile"),
+    ("2.23.133.2.14", "TBB Security Target"),
+    ("2.23.133.2.15", "TPM ID Label"),
+    ("2.23.133.2.16", "TPM Specification"),
+    ("2.23.133.2.17", "TPM Platform Specification"),
+    ("2.23.133.2.18", "TPM Security Assertions"),
+    ("2.23.133.2.19", "TBB Security Assertions"),
+    ("2.23.133.2.23", "TCG Credential Specification"),
+    ("2.23.133.2.25", "TCG Credential Type"),
+    ("2.23.133.2.26", "TCG Previous Platform Certificates"),
+    ("2.23.133.2.27", "TCG TBB Security Assertions V3"),
+    ("2.23.133.2.28", "TCG Cryptographic Anchors"),
+    ("2.23.133.5.1.7.1", "Platform Configuration Version 1"),
+    ("2.23.133.5.1.7.2", "Platform Configuration Version 2"),
+    ("2.23.133.5.1.7.3", "Platform Configuration Version 3"),
+    ("2.23.133.5.1.7.4", "Platform Configuration URI Version 3"),
+    ("2.23.133.4.1", "TCG NULL Algorithm"),
+    ("2.23.133.8.1", "Endorsement Key Certificate"),
+    ("2.23.133.8.2", "Platform Attribute Certificate"),
+    ("2.23.133.8.3", "Attestation Identity Key Certificate"),
+    ("2.23.133.8.4", "Platform Key Certificate"),
+    ("2.23.133.8.5", "Delta Platform Attribute Certificate"),
+    ("2.23.133.8.6", "Delta Platform Key Certificate"),
+    ("2.23.133.8.7", "Additional Platform Attribute Certificate"),
+    ("2.23.133.8.8", "Additional Platform Key Certificate"),
+    ("2.23.133.6.2", "Relevant Credentials"),
+    ("2.23.133.6.3", "Relevant Manifests"),
+    ("2.23.133.6.4", "Virtual Platform Attestation Service"),
+    ("2.23.133.6.5", "Migration Controller Attestation Service"),
+    ("2.23.133.6.6", "Migration Controller Registration Service"),
+    ("2.23.133.6.7", "Virtual Platform Backup Service"),
+    ("2.23.133.3.1", "TCG TPM Protocol"),
+    ("2.23.133.17.1", "Ethernet MAC Address"),
+    ("2.23.133.17.2", "WLAN MAC Address"),
+    ("2.23.133.17.3", "Bluetooth MAC Address"),
+    ("2.23.133.18.3", "TCG Component Class"),
+    ("2.23.133.18.3.1", "Trusted Computed Group Registry"),
+    (
+        "2.23.133.18.3.2",
+        "Internet Engineering Task Force Registry",
+    ),
+    (
+        "2.23.133.18.3.3",
+        "Distributed Management Task Force Registry",
+    ),
+    ("2.23.133.18.3.4", "PCIE Component Class"),
+    ("2.23.133.18.3.5", "Disk Component Class"),
+    (
+        "2.23.133.11.4",
+        "TCG Verified Platform Certificate CA Policy",
+    ),
+    ("2.23.133.19.1", "TCG Trait Identifiers"),
+    ("2.23.133.19.2", "TCG Trait Categories"),
+    ("2.23.133.19.3", "TCG Trait Registries"),
+    ("2.23.133.19.1.1", "Boolean Trait"),
+    ("2.23.133.19.1.2", "Certificate Identifier Trait"),
+    ("2.23.133.19.1.3", "Common Criteria Trait"),
+    ("2.23.133.19.1.4", "Component Class Trait"),
+    ("2.23.133.19.1.5", "Component Identifier V1.1 Trait"),
+    ("2.23.133.19.1.6", "FIPS Level Trait"),
+    ("2.23.133.19.1.7", "ISO 9000 Level Trait"),
+    ("2.23.133.19.1.8", "Network MAC Trait"),
+    ("2.23.133.19.1.9", "Object Identifier Trait"),
+    ("2.23.133.19.1.10", "Private Enterprise Number Trait"),
+    ("2.23.133.19.1.11", "Platform Firmware Capabilities Trait"),
+    (
+        "2.23.133.19.1.12",
+        "Platform Firmware Signature Verification Trait",
+    ),
+    (
+        "2.23.133.19.1.13",
+        "Platform Firmware Update Compliance Trait",
+    ),
+    ("2.23.133.19.1.14", "Platform Hardware Capabilities Trait"),
+    ("2.23.133.19.1.15", "Root of Trust for Measurement Trait"),
+    ("2.23.133.19.1.16", "Attribute Status Trait"),
+    ("2.23.133.19.1.17", "Uniform Resource Identifier Trait"),
+    ("2.23.133.19.1.18", "UTF8String Trait"),
+    ("2.23.133.19.1.19", "IA5String Trait"),
+    ("2.23.133.19.1.20", "PEM-Encoded Certificate String Trait"),
+    ("2.23.133.19.1.21", "Public Key Trait"),
+    ("2.23.133.19.2.1", "Platform Manufacturer Trait Category"),
+    ("2.23.133.19.2.2", "Platform Model Trait Category"),
+    ("2.23.133.19.2.3", "Platform Version Trait Category"),
+    ("2.23.133.19.2.4", "Platform Serial Trait Category"),
+    (
+        "2.23.133.19.2.5",
+        "Platform Manufacturer Identifier Trait Category",
+    ),
+    ("2.23.133.19.2.6", "Platform Ownership Trait Category"),
+    ("2.23.133.19.2.7", "Component Class Trait Category"),
+    ("2.23.133.19.2.8", "Component Manufacturer Trait Category"),
+    ("2.23.133.19.2.9", "Component Model Trait Category"),
+    ("2.23.133.19.2.10", "Component Serial Trait Category"),
+    ("2.23.133.19.2.11", "Component Status Trait Category"),
+    ("2.23.133.19.2.12", "Component Location Trait Category"),
+    ("2.23.133.19.2.13", "Component Revision Trait Category"),
+    (
+        "2.23.133.19.2.14",
+        "Component Field Replaceable Trait Category",
+    ),
+    ("2.23.133.19.2.15", "EK Certificate Trait Category"),
+    ("2.23.133.19.2.16", "IAK Certificate Trait Category"),
+    ("2.23.133.19.2.17", "IDevID Certificate Trait Category"),
+    ("2.23.133.19.2.18", "DICE Certificate Trait Category"),
+    ("2.23.133.19.2.19", "SPDM Certificate Trait Category"),
+    ("2.23.133.19.2.20", "PEM Certificate Trait Category"),
+    ("2.23.133.19.2.21", "Platform Certificate Trait Category"),
+    (
+        "2.23.133.19.2.22",
+        "Delta Platform Certificate Trait Category",
+    ),
+    (
+        "2.23.133.19.2.23",
+        "Rebase Platform Certificate Trait Category",
+    ),
+    ("2.23.133.19.2.24", "Generic Certificate Trait Category"),
+    ("2.23.133.19.2.25", "Common Criteria Trait Category"),
+    (
+        "2.23.133.19.2.26",
+        "Component Identifier V1.1 Trait Category",
+    ),
+    ("2.23.133.19.2.27", "FIPS Level Trait Category"),
+    ("2.23.133.19.2.28", "ISO 9000 Trait Category"),
+    ("2.23.133.19.2.29", "Network MAC Trait Category"),
+    ("2.23.133.19.2.30", "Attestation Protocol Trait Category"),
+    (
+        "2.23.133.19.2.31",
+        "Private Enterprise Number Trait Category",
+    ),
+    (
+        "2.23.133.19.2.32",
+        "Platform Firmware Capabilities Trait Category",
+    ),
+    (
+        "2.23.133.19.2.33",
+        "Platform Hardware Capabilities Trait Category",
+    ),
+    (
+        "2.23.133.19.2.34",
+        "Platform Firmware Signature Verification Trait Category",
+    ),
+    (
+        "2.23.133.19.2.35",
+        "Platform Firmware Update Compliance Trait Category",
+    ),
+    (
+        "2.23.133.19.2.36",
+        "Root of Trust of Measurement Trait Category",
+    ),
+    ("2.23.133.19.2.37", "Public Key Trait Category"),
+    ("2.16.840.1.101.3.4.4.1", "ML-KEM-512"),
+    ("2.16.840.1.101.3.4.4.2", "ML-KEM-768"),
+    ("2.16.840.1.101.3.4.4.3", "ML-KEM-1024"),
+];
@@ -0,0 +1,254 @@
+use crate::Asn1Node;
+use serde::Serialize;
+
+/// Which part of an element's encoding a byte belongs to
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BytePart {
+    Identifier,
+    Length,
+    Content,
+    /// The 0x00 0x00 terminating an indefinite-length element
+    EndOfContents,
+}
+
+/// The innermost node that owns a byte
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OffsetLocation {
+    pub path: String,
+    pub part: BytePart,
+    pub byte_offset: usize,
+    pub byte_length: usize,
+}
+
+/// Parse a node path such as "0.0.3" into child indices.
+///
+/// Index `i` selects the i-th child at each level, starting from the PEM root, so
+/// "0" is the first top-level element. The empty path addresses the root itself.
+pub fn parse_path(path: &str) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+    let path = path.trim();
+    if path.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    path.split('.')
+        .map(|part| {
+            part.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid path component '{}' in '{}'", part, path).into())
+        })
+        .collect()
+}
+
+/// Path of the `index`-th child of the node at `parent`
+pub fn child_path(parent: &str, index: usize) -> String {
+    if parent.is_empty() {
+        index.to_string()
+    } else {
+        format!("{}.{}", parent, index)
+    }
+}
+
+/// Path of the parent node, or None for the root
+pub fn parent_path(path: &str) -> Option<String> {
+    let path = path.trim();
+    if path.is_empty() {
+        return None;
+    }
+    Some(
+        path.rsplit_once('.')
+            .map_or(String::new(), |(parent, _)| parent.to_string()),
+    )
+}
+
+/// Set `path` on a node and all of its descendants
+pub fn assign_paths(node: &mut Asn1Node, path: &str) {
+    node.path = path.to_string();
+    for (index, child) in node.children.iter_mut().enumerate() {
+        assign_paths(child, &child_path(path, index));
+    }
+}
+
+/// Find the node at `path` below `root`
+pub fn find_node<'a>(
+    root: &'a Asn1Node,
+    path: &str,
+) -> Result<&'a Asn1Node, Box<dyn std::error::Error>> {
+    node_at_path(root, &parse_path(path)?)
+        .ok_or_else(|| format!("No node at path '{}'", path).into())
+}
+
+/// Mutable version of [`find_node`]
+pub fn find_node_mut<'a>(
+    root: &'a mut Asn1Node,
+    path: &str,
+) -> Result<&'a mut Asn1Node, Box<dyn std::error::Error>> {
+    parse_path(path)?
+        .into_iter()
+        .try_fold(root, |node, index| node.children.get_mut(index))
+        .ok_or_else(|| format!("No node at path '{}'", path).into())
+}
+
+/// Find the parent of the node at `path`
+pub fn find_parent<'a>(
+    root: &'a Asn1Node,
+    path: &str,
+) -> Result<&'a Asn1Node, Box<dyn std::error::Error>> {
+    find_node(root, path)?;
+    let parent = parent_path(path).ok_or("The root node has no parent")?;
+    find_node(root, &parent)
+}
+
+/// Find the innermost node containing `offset` and the part of its encoding the byte is in.
+///
+/// Returns None for bytes outside every element (e.g. trailing data after the last one).
+pub fn resolve_offset(root: &Asn1Node, offset: usize) -> Option<OffsetLocation> {
+    let mut node = root.children.iter().find(|child| contains(child, offset))?;
+
+    loop {
+        let part = byte_part(
+            offset - node.byte_offset,
+            node.identifier_octets.len() / 2,
+            node.header_length,
+            node.length,
+        );
+        if part == BytePart::Content {
+            if let Some(child) = node.children.iter().find(|child| contains(child, offset)) {
+                node = child;
+                continue;
+            }
+        }
+
+        return Some(OffsetLocation {
+            path: node.path.clone(),
+            part,
+            byte_offset: node.byte_offset,
+            byte_length: node.byte_length,
+        });
+    }
+}
+
+fn contains(node: &Asn1Node, offset: usize) -> bool {
+    offset >= node.byte_offset && offset - node.byte_offset < node.byte_length
+}
+
+/// Classify a position relative to the start of an element
+pub fn byte_part(
+    relative: usize,
+    identifier_length: usize,
+    header_length: usize,
+    content_length: usize,
+) -> BytePart {
+    if relative < identifier_length {
+        BytePart::Identifier
+    } else if relative < header_length {
+        BytePart::Length
+    } else if relative < header_length + content_length {
+        BytePart::Content
+    } else {
+        BytePart::EndOfContents
+    }
+}
+
+/// Find the node at the given child indices below `root`
+pub fn node_at_path<'a>(root: &'a Asn1Node, indices: &[usize]) -> Option<&'a Asn1Node> {
+    indices
+        .iter()
+        .try_fold(root, |node, &index| node.children.get(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(parse_path("").unwrap(), Vec::<usize>::new());
+        assert_eq!(parse_path("0").unwrap(), vec![0]);
+        assert_eq!(parse_path("0.0.3.1").unwrap(), vec![0, 0, 3, 1]);
+        assert!(parse_path("0.a").is_err());
+        assert!(parse_path("0..1").is_err());
+    }
+
+    #[test]
+    fn test_node_at_path() {
+        let root = Asn1Node {
+            children: vec![Asn1Node {
+                children: vec![Asn1Node {
+                    tag_number: 2,
+                    ..Default::default()
+                }],
+                tag_number: 16,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(node_at_path(&root, &[0]).unwrap().tag_number, 16);
+        assert_eq!(node_at_path(&root, &[0, 0]).unwrap().tag_number, 2);
+        assert!(node_at_path(&root, &[0, 1]).is_none());
+        assert!(node_at_path(&root, &[]).is_some());
+    }
+
+    #[test]
+    fn test_parent_and_child_paths() {
+        assert_eq!(child_path("", 0), "0");
+        assert_eq!(child_path("0.1", 2), "0.1.2");
+        assert_eq!(parent_path("0.1.2").as_deref(), Some("0.1"));
+        assert_eq!(parent_path("3").as_deref(), Some(""));
+        assert_eq!(parent_path(""), None);
+    }
+
+    #[test]
+    fn test_decoded_paths_and_lookup() {
+        let root = crate::decoder::decode_pem_tree(crate::test_data::CERT_PEM).unwrap();
+        assert_eq!(root.path, "");
+
+        let serial = find_node(&root, "0.0.1").unwrap();
+        assert_eq!(serial.path, "0.0.1");
+        assert_eq!(serial.tag_number, 2);
+
+        let tbs = find_parent(&root, "0.0.1").unwrap();
+        assert_eq!(tbs.path, "0.0");
+        assert_eq!(find_parent(&root, "0").unwrap().path, "");
+
+        assert!(find_parent(&root, "").is_err());
+        assert!(find_node(&root, "0.7").is_err());
+        assert!(find_parent(&root, "0.7").is_err());
+    }
+
+    #[test]
+    fn test_resolve_offset() {
+        // SEQUENCE (long form) { INTEGER 1, [0] (indefinite) { NULL } EOC }
+        let der = [
+            0x30, 0x81, 0x09, 0x02, 0x01, 0x01, 0xA0, 0x80, 0x05, 0x00, 0x00, 0x00,
+        ];
+        let root = crate::decoder::decode_der_tree("TEST", &der, None).unwrap();
+
+        let at = |offset| {
+            let location = resolve_offset(&root, offset).unwrap();
+            (location.path, location.part)
+        };
+        assert_eq!(at(0), ("0".to_string(), BytePart::Identifier));
+        assert_eq!(at(2), ("0".to_string(), BytePart::Length));
+        assert_eq!(at(3), ("0.0".to_string(), BytePart::Identifier));
+        assert_eq!(at(5), ("0.0".to_string(), BytePart::Content));
+        assert_eq!(at(7), ("0.1".to_string(), BytePart::Length));
+        assert_eq!(at(9), ("0.1.0".to_string(), BytePart::Length));
+        assert_eq!(at(11), ("0.1".to_string(), BytePart::EndOfContents));
+        assert!(resolve_offset(&root, 12).is_none());
+
+        let location = resolve_offset(&root, 4).unwrap();
+        assert_eq!((location.byte_offset, location.byte_length), (3, 3));
+    }
+
+    #[test]
+    fn test_resolve_offset_in_collapsed_node() {
+        let der = [0x30, 0x03, 0x02, 0x01, 0x01];
+        let root = crate::decoder::decode_der_tree("TEST", &der, Some(0)).unwrap();
+
+        let location = resolve_offset(&root, 4).unwrap();
+        assert_eq!(location.path, "0");
+        assert_eq!(location.part, BytePart::Content);
+    }
+}
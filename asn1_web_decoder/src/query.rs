@@ -0,0 +1,791 @@
+//! A small selector language for finding nodes in a decoded tree.
+//!
+//! A selector is a chain of steps joined by whitespace (descendant) or `>` (child);
+//! several selectors can be separated by commas. A leading `>` anchors the first
+//! step at the top level. Each step is a node test followed by any number of
+//! predicates in brackets:
+//!
+//! - `*` any node, `INTEGER`, `UTF8String`, `OID`, ... a universal type
+//! - `[CONTEXT 0]`, `[APPLICATION 1]`, `[PRIVATE 2]`, `[UNIVERSAL 2]` or `[0]` a class and tag
+//! - `$serialNumber` an X.509 field name
+//! - `[value*=example]`, `[oid=2.5.29.17]`, `[depth<3]`, ... predicates; `oid=` also
+//!   accepts the OID's display name, e.g. `[oid="Subject Alternative Name"]`
+//!
+//! Predicate attributes are `value`, `oid`, `label`, `class`, `field`, `path`,
+//! `tag`, `depth` and `length`; operators are `=`, `!=`, `*=` (contains), `^=`
+//! (starts with), `$=` (ends with) and, for the numeric attributes, `<`, `<=`, `>`,
+//! `>=`. The numeric attributes take `=`, `!=` and the orderings only. Values may
+//! be quoted with `"` or `'`.
+//!
+//! For example `$extensions OID`, `$tbsCertificate > $serialNumber` or
+//! `UTF8String[value*=example]`.
+
+use crate::decoder::{decode_pem_tree, get_universal_tag_name};
+use crate::node::is_universal;
+use crate::Asn1Node;
+use std::error::Error;
+
+/// A parsed selector
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    alternatives: Vec<Vec<(Combinator, Step)>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    test: NodeTest,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum NodeTest {
+    Any,
+    Universal(u32),
+    Tag(&'static str, u32),
+    Field(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Predicate {
+    attribute: Attribute,
+    operator: Operator,
+    value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Attribute {
+    Value,
+    Oid,
+    Label,
+    Class,
+    Field,
+    Path,
+    Tag,
+    Depth,
+    Length,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Attribute {
+    fn from_name(name: &str) -> Option<Attribute> {
+        match name.to_ascii_lowercase().as_str() {
+            "value" => Some(Attribute::Value),
+            "oid" => Some(Attribute::Oid),
+            "label" => Some(Attribute::Label),
+            "class" => Some(Attribute::Class),
+            "field" => Some(Attribute::Field),
+            "path" => Some(Attribute::Path),
+            "tag" => Some(Attribute::Tag),
+            "depth" => Some(Attribute::Depth),
+            "length" => Some(Attribute::Length),
+            _ => None,
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Attribute::Tag | Attribute::Depth | Attribute::Length)
+    }
+}
+
+impl Operator {
+    fn is_ordering(self) -> bool {
+        matches!(
+            self,
+            Operator::Less | Operator::LessOrEqual | Operator::Greater | Operator::GreaterOrEqual
+        )
+    }
+}
+
+/// The universal tag number for a type name, ignoring case, spaces, `-` and `_`
+pub(crate) fn universal_tag_for_name(name: &str) -> Option<u32> {
+    let normalize = |s: &str| {
+        s.chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '_'))
+            .collect::<String>()
+            .to_ascii_uppercase()
+    };
+    let name = normalize(name);
+    if name == "OID" {
+        return Some(6);
+    }
+    (1..=30).find(|&tag| {
+        let tag_name = get_universal_tag_name(tag);
+        tag_name != "Unknown" && normalize(tag_name) == name
+    })
+}
+
+pub(crate) fn class_for_name(name: &str) -> Option<&'static str> {
+    match name.to_ascii_uppercase().as_str() {
+        "UNIVERSAL" | "UNIV" => Some("UNIVERSAL"),
+        "APPLICATION" | "APPL" => Some("APPLICATION"),
+        "CONTEXT" | "CONT" => Some("CONTEXT"),
+        "PRIVATE" | "PRIV" => Some("PRIVATE"),
+        _ => None,
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skip whitespace, returning whether any was skipped
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.position;
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.advance();
+        }
+        self.position > start
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+        let start = self.position;
+        while self.peek().is_some_and(&predicate) {
+            self.advance();
+        }
+        &self.input[start..self.position]
+    }
+
+    fn error(&self, message: &str) -> Box<dyn Error> {
+        match self.peek() {
+            Some(c) => format!(
+                "{} at position {} ('{}') in selector",
+                message, self.position, c
+            )
+            .into(),
+            None => format!("{} at end of selector", message).into(),
+        }
+    }
+
+    fn identifier(&mut self) -> &'a str {
+        self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+
+    fn selector(&mut self) -> Result<Selector, Box<dyn Error>> {
+        let mut alternatives = Vec::new();
+        loop {
+            self.skip_whitespace();
+            alternatives.push(self.complex()?);
+            if self.peek().is_none() {
+                return Ok(Selector { alternatives });
+            }
+            if !self.eat(',') {
+                return Err(self.error("Expected ','"));
+            }
+        }
+    }
+
+    fn complex(&mut self) -> Result<Vec<(Combinator, Step)>, Box<dyn Error>> {
+        let mut steps = Vec::new();
+        let mut combinator = Combinator::Descendant;
+        if self.eat('>') {
+            combinator = Combinator::Child;
+            self.skip_whitespace();
+        }
+
+        loop {
+            steps.push((combinator, self.step()?));
+            let had_whitespace = self.skip_whitespace();
+            match self.peek() {
+                None | Some(',') => return Ok(steps),
+                Some('>') => {
+                    self.advance();
+                    self.skip_whitespace();
+                    combinator = Combinator::Child;
+                }
+                Some(_) if had_whitespace => combinator = Combinator::Descendant,
+                Some(_) => return Err(self.error("Unexpected character")),
+            }
+        }
+    }
+
+    fn step(&mut self) -> Result<Step, Box<dyn Error>> {
+        let mut test = match self.peek() {
+            Some('*') => {
+                self.advance();
+                Some(NodeTest::Any)
+            }
+            Some('$') => {
+                self.advance();
+                let name = self.identifier();
+                if name.is_empty() {
+                    return Err(self.error("Expected a field name after '$'"));
+                }
+                Some(NodeTest::Field(name.to_string()))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let name = self.identifier();
+                let tag = universal_tag_for_name(name)
+                    .ok_or_else(|| format!("Unknown type '{}' in selector", name))?;
+                Some(NodeTest::Universal(tag))
+            }
+            Some('[') => None,
+            _ => return Err(self.error("Expected a type, '*', '$field' or '['")),
+        };
+
+        let mut predicates = Vec::new();
+        while self.eat('[') {
+            self.skip_whitespace();
+            if let Some(tag) = self.tag_test()? {
+                if test.is_some() || !predicates.is_empty() {
+                    return Err(self.error("A class and tag test must come first in a step"));
+                }
+                test = Some(tag);
+            } else {
+                predicates.push(self.predicate()?);
+            }
+            self.skip_whitespace();
+            if !self.eat(']') {
+                return Err(self.error("Expected ']'"));
+            }
+        }
+
+        Ok(Step {
+            test: test.unwrap_or(NodeTest::Any),
+            predicates,
+        })
+    }
+
+    /// Parse `CLASS n` or `n` inside brackets, leaving the position unchanged otherwise
+    fn tag_test(&mut self) -> Result<Option<NodeTest>, Box<dyn Error>> {
+        let start = self.position;
+        let word = self.identifier();
+        let class = if word.chars().all(|c| c.is_ascii_digit()) && !word.is_empty() {
+            self.position = start;
+            "CONTEXT"
+        } else if let Some(class) = class_for_name(word) {
+            self.skip_whitespace();
+            class
+        } else {
+            self.position = start;
+            return Ok(None);
+        };
+
+        let digits = self.take_while(|c| c.is_ascii_digit());
+        let number = digits
+            .parse::<u32>()
+            .map_err(|_| self.error("Expected a tag number"))?;
+        Ok(Some(NodeTest::Tag(class, number)))
+    }
+
+    fn predicate(&mut self) -> Result<Predicate, Box<dyn Error>> {
+        let name = self.identifier();
+        let attribute = Attribute::from_name(name)
+            .ok_or_else(|| format!("Unknown attribute '{}' in selector", name))?;
+        self.skip_whitespace();
+
+        let operator = match (self.advance(), self.peek()) {
+            (Some('='), _) => Operator::Equal,
+            (Some('!'), Some('=')) => Operator::NotEqual,
+            (Some('*'), Some('=')) => Operator::Contains,
+            (Some('^'), Some('=')) => Operator::StartsWith,
+            (Some('$'), Some('=')) => Operator::EndsWith,
+            (Some('<'), Some('=')) => Operator::LessOrEqual,
+            (Some('>'), Some('=')) => Operator::GreaterOrEqual,
+            (Some('<'), _) => Operator::Less,
+            (Some('>'), _) => Operator::Greater,
+            _ => return Err(self.error("Expected an operator")),
+        };
+        if !matches!(
+            operator,
+            Operator::Equal | Operator::Less | Operator::Greater
+        ) {
+            self.advance();
+        }
+        self.skip_whitespace();
+
+        let value = match self.peek() {
+            Some(quote @ ('"' | '\'')) => {
+                self.advance();
+                let value = self.take_while(|c| c != quote);
+                if !self.eat(quote) {
+                    return Err(self.error("Unterminated string"));
+                }
+                value
+            }
+            _ => self.take_while(|c| c != ']').trim_end(),
+        };
+
+        if attribute.is_numeric() {
+            if matches!(
+                operator,
+                Operator::Contains | Operator::StartsWith | Operator::EndsWith
+            ) {
+                return Err(format!(
+                    "Attribute '{}' is a number and cannot be matched with '*=', '^=' or '$='",
+                    name
+                )
+                .into());
+            }
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("Attribute '{}' needs a number, got '{}'", name, value))?;
+        } else if operator.is_ordering() {
+            return Err(format!("Attribute '{}' cannot be compared with '<' or '>'", name).into());
+        }
+
+        Ok(Predicate {
+            attribute,
+            operator,
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Where a node sits in a known structure, used to name its children
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Schema {
+    Unknown,
+    Certificate,
+    TbsCertificate,
+    AlgorithmIdentifier,
+    Validity,
+    SubjectPublicKeyInfo,
+    ExtensionsWrapper,
+    Extensions,
+    Extension,
+}
+
+fn is_context(node: &Asn1Node, tag_number: u32) -> bool {
+    node.tag_class == "CONTEXT" && node.tag_number == tag_number
+}
+
+/// Guess the schema of a top-level element from its shape
+fn top_level_schema(node: &Asn1Node) -> Schema {
+    match node.children.as_slice() {
+        [tbs, algorithm, signature]
+            if is_universal(node, 16)
+                && is_universal(tbs, 16)
+                && is_universal(algorithm, 16)
+                && is_universal(signature, 3) =>
+        {
+            Schema::Certificate
+        }
+        _ => Schema::Unknown,
+    }
+}
+
+/// X.509 field names and schemas for the children of a node
+fn child_fields(schema: Schema, node: &Asn1Node) -> Vec<(Option<&'static str>, Schema)> {
+    let mut fields = vec![(None, Schema::Unknown); node.children.len()];
+    let mut name = |index: usize, field: &'static str, schema: Schema| {
+        if let Some(slot) = fields.get_mut(index) {
+            *slot = (Some(field), schema);
+        }
+    };
+
+    match schema {
+        Schema::Certificate => {
+            name(0, "tbsCertificate", Schema::TbsCertificate);
+            name(1, "signatureAlgorithm", Schema::AlgorithmIdentifier);
+            name(2, "signatureValue", Schema::Unknown);
+        }
+        Schema::TbsCertificate => {
+            let mut index = 0;
+            if node
+                .children
+                .first()
+                .is_some_and(|child| is_context(child, 0))
+            {
+                name(0, "version", Schema::Unknown);
+                index = 1;
+            }
+            let positional = [
+                ("serialNumber", Schema::Unknown),
+                ("signature", Schema::AlgorithmIdentifier),
+                ("issuer", Schema::Unknown),
+                ("validity", Schema::Validity),
+                ("subject", Schema::Unknown),
+                ("subjectPublicKeyInfo", Schema::SubjectPublicKeyInfo),
+            ];
+            for (offset, (field, schema)) in positional.into_iter().enumerate() {
+                name(index + offset, field, schema);
+            }
+            for (position, child) in node
+                .children
+                .iter()
+                .enumerate()
+                .skip(index + positional.len())
+            {
+                match child.tag_number {
+                    1 if child.tag_class == "CONTEXT" => {
+                        name(position, "issuerUniqueID", Schema::Unknown)
+                    }
+                    2 if child.tag_class == "CONTEXT" => {
+                        name(position, "subjectUniqueID", Schema::Unknown)
+                    }
+                    3 if child.tag_class == "CONTEXT" => {
+                        name(position, "extensions", Schema::ExtensionsWrapper)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Schema::AlgorithmIdentifier => {
+            name(0, "algorithm", Schema::Unknown);
+            name(1, "parameters", Schema::Unknown);
+        }
+        Schema::Validity => {
+            name(0, "notBefore", Schema::Unknown);
+            name(1, "notAfter", Schema::Unknown);
+        }
+        Schema::SubjectPublicKeyInfo => {
+            name(0, "algorithm", Schema::AlgorithmIdentifier);
+            name(1, "subjectPublicKey", Schema::Unknown);
+        }
+        Schema::ExtensionsWrapper => {
+            fields
+                .iter_mut()
+                .for_each(|slot| slot.1 = Schema::Extensions);
+        }
+        Schema::Extensions => {
+            fields
+                .iter_mut()
+                .for_each(|slot| *slot = (Some("extension"), Schema::Extension));
+        }
+        Schema::Extension => {
+            name(0, "extnID", Schema::Unknown);
+            let has_critical = node
+                .children
+                .get(1)
+                .is_some_and(|child| is_universal(child, 1));
+            if has_critical {
+                name(1, "critical", Schema::Unknown);
+            }
+            name(
+                if has_critical { 2 } else { 1 },
+                "extnValue",
+                Schema::Unknown,
+            );
+        }
+        Schema::Unknown => {}
+    }
+
+    fields
+}
+
+/// A node together with what the selector needs to know about its position
+struct Visit<'a> {
+    node: &'a Asn1Node,
+    field: Option<&'static str>,
+    depth: usize,
+}
+
+impl Step {
+    fn matches(&self, visit: &Visit) -> bool {
+        let node = visit.node;
+        let test = match &self.test {
+            NodeTest::Any => true,
+            NodeTest::Universal(tag) => is_universal(node, *tag),
+            NodeTest::Tag(class, tag) => node.tag_class == *class && node.tag_number == *tag,
+            NodeTest::Field(name) => visit
+                .field
+                .is_some_and(|field| field.eq_ignore_ascii_case(name)),
+        };
+        test && self
+            .predicates
+            .iter()
+            .all(|predicate| predicate.matches(visit))
+    }
+}
+
+impl Predicate {
+    fn matches(&self, visit: &Visit) -> bool {
+        let node = visit.node;
+        let number = match self.attribute {
+            Attribute::Tag => Some(node.tag_number as usize),
+            Attribute::Depth => Some(visit.depth),
+            Attribute::Length => Some(node.length),
+            _ => None,
+        };
+        if let Some(actual) = number {
+            // Checked when parsing
+            let expected = self.value.parse::<usize>().unwrap_or_default();
+            return match self.operator {
+                Operator::Equal => actual == expected,
+                Operator::NotEqual => actual != expected,
+                Operator::Less => actual < expected,
+                Operator::LessOrEqual => actual <= expected,
+                Operator::Greater => actual > expected,
+                Operator::GreaterOrEqual => actual >= expected,
+                // Rejected when parsing
+                Operator::Contains | Operator::StartsWith | Operator::EndsWith => false,
+            };
+        }
+
+        let actual = match self.attribute {
+            Attribute::Value => node.value.clone(),
+            Attribute::Oid if is_universal(node, 6) => node.value.clone(),
+            Attribute::Label => Some(node.label.clone()),
+            Attribute::Class => Some(node.tag_class.clone()),
+            Attribute::Field => visit.field.map(str::to_string),
+            Attribute::Path => Some(node.path.clone()),
+            _ => None,
+        };
+        let Some(actual) = actual else {
+            return self.operator == Operator::NotEqual;
+        };
+
+        let case_insensitive = matches!(self.attribute, Attribute::Class | Attribute::Field);
+        let (actual, expected) = if case_insensitive {
+            (actual.to_ascii_lowercase(), self.value.to_ascii_lowercase())
+        } else {
+            (actual, self.value.clone())
+        };
+
+        // OID values are displayed as "2.5.29.17 (subjectAltName)"; match either part
+        if self.attribute == Attribute::Oid
+            && matches!(self.operator, Operator::Equal | Operator::NotEqual)
+        {
+            let (dotted, name) = match actual.split_once(" (") {
+                Some((dotted, name)) => (dotted, name.trim_end_matches(')')),
+                None => (actual.as_str(), ""),
+            };
+            let equal = dotted == expected || name == expected;
+            return equal == (self.operator == Operator::Equal);
+        }
+
+        match self.operator {
+            Operator::Equal => actual == expected,
+            Operator::NotEqual => actual != expected,
+            Operator::Contains => actual.contains(&expected),
+            Operator::StartsWith => actual.starts_with(&expected),
+            Operator::EndsWith => actual.ends_with(&expected),
+            _ => false,
+        }
+    }
+}
+
+/// Whether the last visit in `chain` matches `steps`, with earlier steps matched
+/// against its ancestors. Steps are matched left to right, keeping for each visit
+/// whether the steps so far can end on it, so descendant combinators never
+/// backtrack.
+fn matches_chain(steps: &[(Combinator, Step)], chain: &[Visit]) -> bool {
+    let (Some((_, last)), Some(current)) = (steps.last(), chain.last()) else {
+        return steps.is_empty();
+    };
+    if !last.matches(current) {
+        return false;
+    }
+
+    let mut matched: Vec<bool> = Vec::new();
+    for (position, (combinator, step)) in steps.iter().enumerate() {
+        let mut matched_before = false;
+        let next = chain
+            .iter()
+            .enumerate()
+            .map(|(index, visit)| {
+                let reachable = match combinator {
+                    _ if position == 0 => *combinator == Combinator::Descendant || index == 0,
+                    Combinator::Child => index > 0 && matched[index - 1],
+                    Combinator::Descendant => matched_before,
+                };
+                if position > 0 {
+                    matched_before |= matched[index];
+                }
+                reachable && step.matches(visit)
+            })
+            .collect();
+        matched = next;
+    }
+    matched.last() == Some(&true)
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Selector, Box<dyn Error>> {
+        if selector.trim().is_empty() {
+            return Err("Empty selector".into());
+        }
+        Parser {
+            input: selector,
+            position: 0,
+        }
+        .selector()
+    }
+
+    /// Paths of all nodes below `root` matching the selector, in document order
+    pub fn select(&self, root: &Asn1Node) -> Vec<String> {
+        let mut matches = Vec::new();
+        let mut chain = Vec::new();
+        for child in &root.children {
+            self.visit(
+                child,
+                None,
+                top_level_schema(child),
+                &mut chain,
+                &mut matches,
+            );
+        }
+        matches
+    }
+
+    fn visit<'a>(
+        &self,
+        node: &'a Asn1Node,
+        field: Option<&'static str>,
+        schema: Schema,
+        chain: &mut Vec<Visit<'a>>,
+        matches: &mut Vec<String>,
+    ) {
+        chain.push(Visit {
+            node,
+            field,
+            depth: chain.len(),
+        });
+        if self
+            .alternatives
+            .iter()
+            .any(|steps| matches_chain(steps, chain))
+        {
+            matches.push(node.path.clone());
+        }
+        for (child, (field, schema)) in node.children.iter().zip(child_fields(schema, node)) {
+            self.visit(child, field, schema, chain, matches);
+        }
+        chain.pop();
+    }
+}
+
+/// Select nodes of a PEM document, returning the matching paths as a JSON array
+pub fn query_internal(pem_input: &str, selector: &str) -> Result<String, Box<dyn Error>> {
+    let selector = Selector::parse(selector)?;
+    let root = decode_pem_tree(pem_input)?;
+    Ok(serde_json::to_string(&selector.select(&root))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::find_node;
+    use crate::test_data::CERT_PEM;
+
+    fn select(selector: &str) -> Vec<String> {
+        let root = decode_pem_tree(CERT_PEM).unwrap();
+        Selector::parse(selector).unwrap().select(&root)
+    }
+
+    #[test]
+    fn test_select_by_field() {
+        assert_eq!(select("$tbsCertificate > $serialNumber"), vec!["0.0.1"]);
+        assert_eq!(select("$serialNumber"), vec!["0.0.1"]);
+        assert_eq!(select("$validity > *"), vec!["0.0.4.0", "0.0.4.1"]);
+        assert_eq!(select("$signatureValue"), vec!["0.2"]);
+        assert_eq!(select("INTEGER[field=serialNumber]"), vec!["0.0.1"]);
+    }
+
+    #[test]
+    fn test_select_oids_under_extensions() {
+        let root = decode_pem_tree(CERT_PEM).unwrap();
+        let paths = Selector::parse("$extensions OID").unwrap().select(&root);
+        let oids: Vec<_> = paths
+            .iter()
+            .map(|path| find_node(&root, path).unwrap().value.clone().unwrap())
+            .collect();
+        assert_eq!(oids.len(), 4);
+        assert!(oids[0].starts_with("2.5.29.14"));
+        assert!(oids[3].starts_with("2.5.29.17"));
+
+        assert_eq!(select("$extnID[oid=2.5.29.17]"), vec!["0.0.7.0.3.0"]);
+        assert_eq!(
+            select("$extnID[oid='Subject Alternative Name']"),
+            vec!["0.0.7.0.3.0"]
+        );
+    }
+
+    #[test]
+    fn test_select_types_and_predicates() {
+        assert_eq!(select("UTF8String[value*=example]").len(), 2);
+        assert_eq!(select("UTF8String[value=example.com]").len(), 2);
+        assert_eq!(select("[CONTEXT 0]"), select("[0]"));
+        assert_eq!(select("> SEQUENCE"), vec!["0"]);
+        assert_eq!(select("SEQUENCE[depth<1]"), vec!["0"]);
+        assert_eq!(
+            select("$critical, $serialNumber"),
+            vec!["0.0.1", "0.0.7.0.2.1"]
+        );
+        assert_eq!(select("* > BOOLEAN[value='TRUE']").len(), 1);
+        assert!(select("NULL").is_empty());
+    }
+
+    #[test]
+    fn test_descendant_steps_do_not_backtrack() {
+        // 200 nested SEQUENCEs around a NULL; every middle step matches at every level
+        let mut der = Vec::new();
+        for level in (0..200usize).rev() {
+            der.extend_from_slice(&[0x30, 0x82]);
+            der.extend_from_slice(&(2 + 4 * level as u16).to_be_bytes());
+        }
+        der.extend_from_slice(&[0x05, 0x00]);
+        let root = crate::decoder::decode_der_tree("TEST", &der, None).unwrap();
+
+        let selector = format!("INTEGER{}", " SEQUENCE".repeat(20));
+        assert!(Selector::parse(&selector).unwrap().select(&root).is_empty());
+        let selector = format!("SEQUENCE{} > NULL", " SEQUENCE".repeat(20));
+        assert_eq!(Selector::parse(&selector).unwrap().select(&root).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Selector::parse("").is_err());
+        assert!(Selector::parse("FOO").is_err());
+        assert!(Selector::parse("INTEGER[").is_err());
+        assert!(Selector::parse("INTEGER[colour=red]").is_err());
+        assert!(Selector::parse("INTEGER[depth<x]").is_err());
+        assert!(Selector::parse("INTEGER[value<3]").is_err());
+        assert!(Selector::parse("INTEGER[0]").is_err());
+        assert!(Selector::parse("INTEGER,").is_err());
+    }
+
+    #[test]
+    fn test_numeric_attribute_operators() {
+        for selector in ["INTEGER[tag*=2]", "INTEGER[depth^=1]", "INTEGER[length$=1]"] {
+            let error = Selector::parse(selector).unwrap_err().to_string();
+            assert!(
+                error.contains("cannot be matched with '*=', '^=' or '$='"),
+                "{}",
+                error
+            );
+        }
+        assert_eq!(select("SEQUENCE[depth=0]"), vec!["0"]);
+        assert_eq!(select("[depth!=0][depth<=1]"), vec!["0.0", "0.1", "0.2"]);
+        assert_eq!(select("[tag>=2][tag<=2][depth>0]"), select("INTEGER"));
+    }
+}
@@ -0,0 +1,330 @@
+//! Replayable edit scripts.
+//!
+//! A script is a JSON array of operations, each addressing its target either by
+//! `path` or by a `select`or (see [`crate::query`]), e.g.
+//!
+//! ```json
+//! [
+//!   { "op": "increment", "select": "$serialNumber", "by": 1 },
+//!   { "op": "set_value", "select": "$validity > $notAfter", "value": "361231235959Z" }
+//! ]
+//! ```
+//!
+//! Operations: `set_value` (`value`), `increment` (`by`, default 1), `set_tag` and
+//! `wrap` (`tag_class`, `tag_number`), `delete`, `insert` (the target is the parent;
+//! `node` in the JSON node model and an optional `index`, default append) and
+//! `move` (`to` parent path and optional `index`). A selector may match several
+//! nodes; the operation is applied to each, last match first so earlier paths
+//! stay valid. Every operation either applies completely or not at all, and a
+//! failed operation does not stop the rest of the script.
+
+use crate::document::Document;
+use crate::edit::Edit;
+use crate::query::Selector;
+use crate::Asn1Node;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// How an operation finds its nodes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Target {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub select: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    SetValue {
+        #[serde(flatten)]
+        target: Target,
+        value: String,
+    },
+    Increment {
+        #[serde(flatten)]
+        target: Target,
+        #[serde(default = "default_increment")]
+        by: i64,
+    },
+    SetTag {
+        #[serde(flatten)]
+        target: Target,
+        tag_class: String,
+        tag_number: u32,
+    },
+    Wrap {
+        #[serde(flatten)]
+        target: Target,
+        tag_class: String,
+        tag_number: u32,
+    },
+    Delete {
+        #[serde(flatten)]
+        target: Target,
+    },
+    Insert {
+        #[serde(flatten)]
+        target: Target,
+        node: Box<Asn1Node>,
+        #[serde(default)]
+        index: Option<usize>,
+    },
+    Move {
+        #[serde(flatten)]
+        target: Target,
+        to: String,
+        #[serde(default)]
+        index: Option<usize>,
+    },
+}
+
+fn default_increment() -> i64 {
+    1
+}
+
+/// Outcome of one operation
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OperationResult {
+    /// Position of the operation in the script
+    pub index: usize,
+    pub op: &'static str,
+    pub success: bool,
+    /// Paths of the nodes the operation changed or created, in document order
+    pub paths: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::SetValue { .. } => "set_value",
+            Operation::Increment { .. } => "increment",
+            Operation::SetTag { .. } => "set_tag",
+            Operation::Wrap { .. } => "wrap",
+            Operation::Delete { .. } => "delete",
+            Operation::Insert { .. } => "insert",
+            Operation::Move { .. } => "move",
+        }
+    }
+
+    fn target(&self) -> &Target {
+        match self {
+            Operation::SetValue { target, .. }
+            | Operation::Increment { target, .. }
+            | Operation::SetTag { target, .. }
+            | Operation::Wrap { target, .. }
+            | Operation::Delete { target }
+            | Operation::Insert { target, .. }
+            | Operation::Move { target, .. } => target,
+        }
+    }
+
+    /// Apply to the node at one resolved path
+    fn apply_at(&self, document: &mut Document, path: &str) -> Result<Edit, Box<dyn Error>> {
+        match self {
+            Operation::SetValue { value, .. } => document.set_value(path, value),
+            Operation::Increment { by, .. } => document.increment(path, *by),
+            Operation::SetTag {
+                tag_class,
+                tag_number,
+                ..
+            } => document.set_tag(path, tag_class, *tag_number),
+            Operation::Wrap {
+                tag_class,
+                tag_number,
+                ..
+            } => document.wrap(path, tag_class, *tag_number),
+            Operation::Delete { .. } => document.delete(path),
+            Operation::Insert { node, index, .. } => {
+                let index = match index {
+                    Some(index) => *index,
+                    None => document.child_count(path)?,
+                };
+                document.insert_child(path, index, node)
+            }
+            Operation::Move { to, index, .. } => {
+                let index = match index {
+                    Some(index) => *index,
+                    None => document.child_count(to)?,
+                };
+                document.move_node(path, to, index)
+            }
+        }
+    }
+}
+
+/// Resolve an operation's target to paths, in document order
+fn resolve(document: &Document, target: &Target) -> Result<Vec<String>, Box<dyn Error>> {
+    match (&target.path, &target.select) {
+        (Some(path), None) => Ok(vec![path.trim().to_string()]),
+        (None, Some(selector)) => {
+            let paths = Selector::parse(selector)?.select(&document.decode(None)?);
+            if paths.is_empty() {
+                return Err(format!("Selector '{}' matched no nodes", selector).into());
+            }
+            Ok(paths)
+        }
+        _ => Err("Each operation needs exactly one of 'path' or 'select'".into()),
+    }
+}
+
+fn apply_operation(
+    document: &mut Document,
+    operation: &Operation,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let original = document.der().to_vec();
+    let result = resolve(document, operation.target()).and_then(|paths| {
+        let mut changed = Vec::new();
+        for path in paths.iter().rev() {
+            if let Some(path) = operation.apply_at(document, path)?.path {
+                changed.push(path);
+            }
+        }
+        changed.reverse();
+        Ok(changed)
+    });
+
+    if result.is_err() {
+        document.set_der(original);
+    }
+    result
+}
+
+pub fn parse_script(script_json: &str) -> Result<Vec<Operation>, Box<dyn Error>> {
+    Ok(serde_json::from_str(script_json)?)
+}
+
+/// Run every operation in order, reporting the outcome of each
+pub fn apply_script(document: &mut Document, script: &[Operation]) -> Vec<OperationResult> {
+    script
+        .iter()
+        .enumerate()
+        .map(
+            |(index, operation)| match apply_operation(document, operation) {
+                Ok(paths) => OperationResult {
+                    index,
+                    op: operation.name(),
+                    success: true,
+                    paths,
+                    error: None,
+                },
+                Err(e) => OperationResult {
+                    index,
+                    op: operation.name(),
+                    success: false,
+                    paths: Vec::new(),
+                    error: Some(e.to_string()),
+                },
+            },
+        )
+        .collect()
+}
+
+/// Apply a script to a PEM document, returning JSON `{pem, results}`
+pub fn apply_script_internal(pem_input: &str, script_json: &str) -> Result<String, Box<dyn Error>> {
+    let script = parse_script(script_json)?;
+    let mut document = Document::from_pem(pem_input)?;
+    let results = apply_script(&mut document, &script);
+    Ok(serde_json::to_string_pretty(&serde_json::json!({
+        "pem": document.to_pem(),
+        "results": results,
+    }))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::find_node;
+    use crate::test_data::CERT_PEM;
+
+    fn value_at(document: &Document, selector: &str) -> String {
+        let root = document.decode(None).unwrap();
+        let path = &Selector::parse(selector).unwrap().select(&root)[0];
+        find_node(&root, path).unwrap().value.clone().unwrap()
+    }
+
+    #[test]
+    fn test_bump_serial_and_change_not_after() {
+        let script = parse_script(
+            r#"[
+                { "op": "increment", "select": "$serialNumber" },
+                { "op": "set_value", "select": "$validity > $notAfter", "value": "361231235959Z" }
+            ]"#,
+        )
+        .unwrap();
+
+        let mut document = Document::from_pem(CERT_PEM).unwrap();
+        let serial = document.raw_bytes("0.0.1").unwrap().to_vec();
+        let results = apply_script(&mut document, &script);
+
+        assert!(results.iter().all(|result| result.success), "{:?}", results);
+        assert_eq!(results[0].paths, vec!["0.0.1"]);
+        assert_eq!(results[1].paths, vec!["0.0.4.1"]);
+
+        let bumped = document.raw_bytes("0.0.1").unwrap();
+        assert_eq!(bumped[..bumped.len() - 1], serial[..serial.len() - 1]);
+        assert_eq!(bumped[bumped.len() - 1], serial[serial.len() - 1] + 1);
+        assert_eq!(value_at(&document, "$notAfter"), "361231235959Z");
+    }
+
+    #[test]
+    fn test_failures_are_reported_and_do_not_stop_the_script() {
+        let script = parse_script(
+            r#"[
+                { "op": "set_value", "path": "0.7.7", "value": "1" },
+                { "op": "delete", "select": "NULL" },
+                { "op": "delete" },
+                { "op": "wrap", "path": "0.0.1", "tag_class": "CONTEXT", "tag_number": 9 }
+            ]"#,
+        )
+        .unwrap();
+
+        let mut document = Document::from_pem(CERT_PEM).unwrap();
+        let results = apply_script(&mut document, &script);
+        let outcomes: Vec<_> = results.iter().map(|result| result.success).collect();
+        assert_eq!(outcomes, vec![false, false, false, true]);
+        assert!(results[1]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("matched no nodes"));
+        assert_eq!(document.child_count("0.0.1").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_selector_with_several_matches() {
+        // SEQUENCE { INTEGER 1, NULL, INTEGER 2 }
+        let der = vec![0x30, 0x08, 0x02, 0x01, 0x01, 0x05, 0x00, 0x02, 0x01, 0x02];
+        let mut document = Document::from_der("TEST", der);
+        let script = parse_script(
+            r#"[
+                { "op": "wrap", "select": "INTEGER", "tag_class": "CONTEXT", "tag_number": 0 },
+                { "op": "delete", "select": "NULL" },
+                { "op": "insert", "path": "0", "node": { "tag_class": "UNIVERSAL", "tag_number": 1, "is_constructed": false, "value": "true" } }
+            ]"#,
+        )
+        .unwrap();
+
+        let results = apply_script(&mut document, &script);
+        assert_eq!(results[0].paths, vec!["0.0", "0.2"]);
+        assert_eq!(results[1].paths, Vec::<String>::new());
+        assert_eq!(results[2].paths, vec!["0.2"]);
+        assert_eq!(
+            document.der(),
+            [
+                0x30, 0x0D, 0xA0, 0x03, 0x02, 0x01, 0x01, 0xA0, 0x03, 0x02, 0x01, 0x02, 0x01, 0x01,
+                0xFF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_script(r#"[{ "op": "explode", "path": "0" }]"#).is_err());
+        assert!(parse_script(r#"{ "op": "delete", "path": "0" }"#).is_err());
+        assert!(apply_script_internal(CERT_PEM, "not json").is_err());
+    }
+}
@@ -0,0 +1,497 @@
+//! Push-style streaming decoder.
+//!
+//! [`StreamDecoder`] accepts the input in arbitrary chunks and reports elements as
+//! start/end/primitive events. Content octets are not retained (apart from a short
+//! prefix used to decode small primitive values), so memory use is bounded by the
+//! nesting depth rather than the input size.
+
+use crate::decoder::decode_value;
+use crate::tlv::{read_identifier, read_length, DecodeError, Length, Tag, MAX_DEPTH};
+use serde::Serialize;
+
+/// Primitives up to this size get a decoded `value` in their event
+pub const MAX_VALUE_BYTES: usize = 1024;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// A constructed element begins; `length` is None for indefinite length
+    Start {
+        offset: usize,
+        depth: usize,
+        tag_class: &'static str,
+        tag_number: u32,
+        header_length: usize,
+        length: Option<usize>,
+    },
+    /// A constructed element ends; `end_offset` is just past its last octet
+    End {
+        offset: usize,
+        depth: usize,
+        end_offset: usize,
+    },
+    Primitive {
+        offset: usize,
+        depth: usize,
+        tag_class: &'static str,
+        tag_number: u32,
+        header_length: usize,
+        length: usize,
+        /// Omitted when the content is larger than MAX_VALUE_BYTES
+        value: Option<String>,
+    },
+}
+
+/// An open constructed element
+#[derive(Debug)]
+struct Frame {
+    offset: usize,
+    /// None for indefinite length
+    end: Option<usize>,
+}
+
+#[derive(Debug)]
+enum State {
+    Header,
+    Content {
+        tag: Tag,
+        offset: usize,
+        header_length: usize,
+        length: usize,
+        remaining: usize,
+    },
+}
+
+#[derive(Debug)]
+pub struct StreamDecoder {
+    /// Absolute offset of the next input byte
+    offset: usize,
+    state: State,
+    /// Pending identifier/length octets, or the retained prefix of primitive content
+    buffer: Vec<u8>,
+    stack: Vec<Frame>,
+    failed: Option<DecodeError>,
+}
+
+impl Default for StreamDecoder {
+    fn default() -> Self {
+        StreamDecoder::new()
+    }
+}
+
+impl StreamDecoder {
+    pub fn new() -> StreamDecoder {
+        StreamDecoder {
+            offset: 0,
+            state: State::Header,
+            buffer: Vec::new(),
+            stack: Vec::new(),
+            failed: None,
+        }
+    }
+
+    /// Number of input bytes consumed so far
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Current nesting depth
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Feed the next chunk of input, returning the events it completes.
+    ///
+    /// After an error the decoder stays failed and returns the same error.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<StreamEvent>, DecodeError> {
+        if let Some(ref e) = self.failed {
+            return Err(e.clone());
+        }
+
+        let mut events = Vec::new();
+        let result = self.consume(chunk, &mut events);
+        if let Err(ref e) = result {
+            self.failed = Some(e.clone());
+        }
+        result.map(|_| events)
+    }
+
+    /// Signal the end of input; fails if an element is still incomplete
+    pub fn finish(&mut self) -> Result<(), DecodeError> {
+        if let Some(ref e) = self.failed {
+            return Err(e.clone());
+        }
+
+        let incomplete = match self.state {
+            State::Content { offset, .. } => Some(offset),
+            State::Header if !self.buffer.is_empty() => Some(self.offset - self.buffer.len()),
+            State::Header => self.stack.last().map(|frame| frame.offset),
+        };
+        match incomplete {
+            Some(offset) => {
+                let e = match self.stack.last() {
+                    Some(frame) if frame.end.is_none() && matches!(self.state, State::Header) => {
+                        DecodeError::MissingEndOfContents(frame.offset)
+                    }
+                    _ => DecodeError::Truncated(offset),
+                };
+                self.failed = Some(e.clone());
+                Err(e)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn consume(&mut self, chunk: &[u8], events: &mut Vec<StreamEvent>) -> Result<(), DecodeError> {
+        let mut pos = 0;
+
+        while pos < chunk.len() {
+            match self.state {
+                State::Header => {
+                    self.buffer.push(chunk[pos]);
+                    pos += 1;
+                    self.offset += 1;
+                    self.try_header(events)?;
+                }
+                State::Content {
+                    tag,
+                    offset,
+                    header_length,
+                    length,
+                    remaining,
+                } => {
+                    let take = remaining.min(chunk.len() - pos);
+                    let keep = take.min(MAX_VALUE_BYTES.saturating_sub(self.buffer.len()));
+                    self.buffer.extend_from_slice(&chunk[pos..pos + keep]);
+                    pos += take;
+                    self.offset += take;
+
+                    if take == remaining {
+                        self.emit_primitive(tag, offset, header_length, length, events);
+                        self.close_frames(events);
+                    } else {
+                        self.state = State::Content {
+                            tag,
+                            offset,
+                            header_length,
+                            length,
+                            remaining: remaining - take,
+                        };
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Try to complete the header in the buffer
+    fn try_header(&mut self, events: &mut Vec<StreamEvent>) -> Result<(), DecodeError> {
+        let offset = self.offset - self.buffer.len();
+
+        // End-of-contents octets close the innermost indefinite-length element
+        if self.buffer == [0x00, 0x00] {
+            if let Some(frame) = self.stack.last() {
+                if frame.end.is_none() {
+                    self.check_enclosing_end(offset, None)?;
+                    let frame = self.stack.pop().unwrap();
+                    self.buffer.clear();
+                    events.push(StreamEvent::End {
+                        offset: frame.offset,
+                        depth: self.stack.len(),
+                        end_offset: self.offset,
+                    });
+                    self.close_frames(events);
+                    return Ok(());
+                }
+            }
+        }
+
+        let (tag, identifier_length) = match read_identifier(&self.buffer) {
+            Ok(result) => result,
+            Err(DecodeError::Truncated(_)) => return Ok(()),
+            Err(e) => return Err(e.offset_by(offset)),
+        };
+        let (length, length_octets) = match read_length(&self.buffer[identifier_length..]) {
+            Ok(result) => result,
+            Err(DecodeError::Truncated(_)) => return Ok(()),
+            Err(e) => return Err(e.offset_by(offset)),
+        };
+        let header_length = identifier_length + length_octets;
+        self.buffer.clear();
+
+        let end = match length {
+            Length::Definite(length) => Some(self.offset + length),
+            Length::Indefinite if !tag.constructed => {
+                return Err(DecodeError::IndefinitePrimitive(offset))
+            }
+            Length::Indefinite => None,
+        };
+        self.check_enclosing_end(offset, end)?;
+
+        let depth = self.stack.len();
+        if depth > MAX_DEPTH {
+            return Err(DecodeError::TooDeep(offset));
+        }
+        if tag.constructed {
+            events.push(StreamEvent::Start {
+                offset,
+                depth,
+                tag_class: tag.class.name(),
+                tag_number: tag.number,
+                header_length,
+                length: end.map(|end| end - self.offset),
+            });
+            self.stack.push(Frame { offset, end });
+            self.close_frames(events);
+        } else {
+            let length = end.unwrap() - self.offset;
+            self.state = State::Content {
+                tag,
+                offset,
+                header_length,
+                length,
+                remaining: length,
+            };
+            if length == 0 {
+                self.emit_primitive(tag, offset, header_length, 0, events);
+                self.close_frames(events);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The header just read, and the element it starts if its `end` is known, must
+    /// lie within the nearest enclosing definite-length element, even when
+    /// indefinite-length elements are open in between
+    fn check_enclosing_end(&self, offset: usize, end: Option<usize>) -> Result<(), DecodeError> {
+        let Some(limit) = self.stack.iter().rev().find_map(|frame| frame.end) else {
+            return Ok(());
+        };
+        if self.offset > limit || end.is_some_and(|end| end > limit) {
+            return Err(DecodeError::ExceedsParent(offset));
+        }
+        Ok(())
+    }
+
+    fn emit_primitive(
+        &mut self,
+        tag: Tag,
+        offset: usize,
+        header_length: usize,
+        length: usize,
+        events: &mut Vec<StreamEvent>,
+    ) {
+        let value = if length <= MAX_VALUE_BYTES {
+            Some(decode_value(tag.number, &self.buffer))
+        } else {
+            None
+        };
+        self.buffer.clear();
+        self.state = State::Header;
+
+        events.push(StreamEvent::Primitive {
+            offset,
+            depth: self.stack.len(),
+            tag_class: tag.class.name(),
+            tag_number: tag.number,
+            header_length,
+            length,
+            value,
+        });
+    }
+
+    /// Close every definite-length element that ends at the current offset
+    fn close_frames(&mut self, events: &mut Vec<StreamEvent>) {
+        while let Some(frame) = self.stack.last() {
+            if frame.end != Some(self.offset) {
+                break;
+            }
+            let frame = self.stack.pop().unwrap();
+            events.push(StreamEvent::End {
+                offset: frame.offset,
+                depth: self.stack.len(),
+                end_offset: self.offset,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::CERT_PEM;
+
+    fn decode_all(data: &[u8], chunk_size: usize) -> Result<Vec<StreamEvent>, DecodeError> {
+        let mut decoder = StreamDecoder::new();
+        let mut events = Vec::new();
+        for chunk in data.chunks(chunk_size) {
+            events.extend(decoder.push(chunk)?);
+        }
+        decoder.finish()?;
+        Ok(events)
+    }
+
+    #[test]
+    fn test_stream_sequence() {
+        // SEQUENCE { INTEGER 1, NULL }
+        let events = decode_all(&[0x30, 0x05, 0x02, 0x01, 0x01, 0x05, 0x00], 64).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Start {
+                    offset: 0,
+                    depth: 0,
+                    tag_class: "UNIVERSAL",
+                    tag_number: 16,
+                    header_length: 2,
+                    length: Some(5),
+                },
+                StreamEvent::Primitive {
+                    offset: 2,
+                    depth: 1,
+                    tag_class: "UNIVERSAL",
+                    tag_number: 2,
+                    header_length: 2,
+                    length: 1,
+                    value: Some("1".to_string()),
+                },
+                StreamEvent::Primitive {
+                    offset: 5,
+                    depth: 1,
+                    tag_class: "UNIVERSAL",
+                    tag_number: 5,
+                    header_length: 2,
+                    length: 0,
+                    value: Some("NULL".to_string()),
+                },
+                StreamEvent::End {
+                    offset: 0,
+                    depth: 0,
+                    end_offset: 7
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stream_chunking_is_transparent() {
+        let der = pem::parse(CERT_PEM).unwrap().into_contents();
+        let whole = decode_all(&der, der.len()).unwrap();
+
+        assert_eq!(decode_all(&der, 1).unwrap(), whole);
+        assert_eq!(decode_all(&der, 7).unwrap(), whole);
+
+        let starts = whole
+            .iter()
+            .filter(|e| matches!(e, StreamEvent::Start { .. }))
+            .count();
+        let ends = whole
+            .iter()
+            .filter(|e| matches!(e, StreamEvent::End { .. }))
+            .count();
+        assert_eq!(starts, ends);
+        assert!(
+            matches!(whole.last(), Some(StreamEvent::End { depth: 0, end_offset, .. }) if *end_offset == der.len())
+        );
+    }
+
+    #[test]
+    fn test_stream_indefinite_length() {
+        // [0] (indefinite) { SEQUENCE {} } EOC
+        let events = decode_all(&[0xA0, 0x80, 0x30, 0x00, 0x00, 0x00], 1).unwrap();
+        assert!(matches!(events[0], StreamEvent::Start { length: None, .. }));
+        assert_eq!(
+            events[2],
+            StreamEvent::End {
+                offset: 2,
+                depth: 1,
+                end_offset: 4
+            }
+        );
+        assert_eq!(
+            events[3],
+            StreamEvent::End {
+                offset: 0,
+                depth: 0,
+                end_offset: 6
+            }
+        );
+    }
+
+    #[test]
+    fn test_stream_large_primitive_not_retained() {
+        let mut der = vec![0x04, 0x83, 0x01, 0x00, 0x00];
+        der.resize(5 + 65536, 0xAB);
+
+        let mut decoder = StreamDecoder::new();
+        let mut events = Vec::new();
+        for chunk in der.chunks(4096) {
+            events.extend(decoder.push(chunk).unwrap());
+            assert!(decoder.buffer.len() <= MAX_VALUE_BYTES);
+        }
+        decoder.finish().unwrap();
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            StreamEvent::Primitive {
+                length: 65536,
+                value: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_stream_errors() {
+        assert_eq!(
+            decode_all(&[0x30, 0x05, 0x02, 0x01], 2),
+            Err(DecodeError::Truncated(2))
+        );
+        assert_eq!(
+            decode_all(&[0x30, 0x80, 0x05, 0x00], 2),
+            Err(DecodeError::MissingEndOfContents(0))
+        );
+        assert_eq!(
+            decode_all(&[0x30, 0x02, 0x04, 0x05], 2),
+            Err(DecodeError::ExceedsParent(2))
+        );
+        assert_eq!(
+            decode_all(&[0x04, 0x80], 2),
+            Err(DecodeError::IndefinitePrimitive(0))
+        );
+
+        // Definite inside indefinite inside definite: SEQUENCE(4) { [0](indef) { OCTET STRING(5) } }
+        assert_eq!(
+            decode_all(&[0x30, 0x04, 0xA0, 0x80, 0x04, 0x05, 0x01], 1),
+            Err(DecodeError::ExceedsParent(4))
+        );
+        // Indefinite inside definite, running past it: SEQUENCE(4) { [0](indef) { NULL, NULL ...
+        assert_eq!(
+            decode_all(
+                &[0x30, 0x04, 0xA0, 0x80, 0x05, 0x00, 0x05, 0x00, 0x00, 0x00],
+                1
+            ),
+            Err(DecodeError::ExceedsParent(6))
+        );
+        // Its end-of-contents octets past the end of the definite parent
+        assert_eq!(
+            decode_all(&[0x30, 0x05, 0xA0, 0x80, 0x05, 0x00, 0x00, 0x00], 1),
+            Err(DecodeError::ExceedsParent(6))
+        );
+
+        // Nesting is limited as in Tlv::read, however the input is chunked
+        let nested = [0x30, 0x80].repeat(MAX_DEPTH + 2);
+        assert_eq!(
+            decode_all(&nested, 7),
+            Err(DecodeError::TooDeep(2 * (MAX_DEPTH + 1)))
+        );
+
+        let mut decoder = StreamDecoder::new();
+        assert!(decoder.push(&[0x30]).is_ok());
+        assert_eq!(decoder.push(&[0x85]), Err(DecodeError::InvalidLength(0)));
+        assert_eq!(decoder.push(&[0x00]), Err(DecodeError::InvalidLength(0)));
+    }
+}
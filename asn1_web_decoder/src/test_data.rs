@@ -0,0 +1,33 @@
+//! Shared fixtures for unit tests.
+
+/// Self-signed P-256 certificate for example.com with SAN, SKI, AKI and basic constraints
+pub const CERT_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIBxTCCAWqgAwIBAgIUWed2mbjwZD4Oi5j2AhYhFv0brE4wCgYIKoZIzj0EAwIw
+IzELMAkGA1UEBhMCVVMxFDASBgNVBAMMC2V4YW1wbGUuY29tMB4XDTI2MTAxODE2
+NTg0NFoXDTM2MTAxNTE2NTg0NFowIzELMAkGA1UEBhMCVVMxFDASBgNVBAMMC2V4
+YW1wbGUuY29tMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEYxde438rHBaVRxC6
+T8vP9HsXN8yEOrryruNecEX9htsLNhq/IOcHtdT8XxwBiu3mPfX2spm+PYZVDRpf
+3Ys4iaN8MHowHQYDVR0OBBYEFH6QYm+Gu2krmzMv7bKIFl9XMrOFMB8GA1UdIwQY
+MBaAFH6QYm+Gu2krmzMv7bKIFl9XMrOFMA8GA1UdEwEB/wQFMAMBAf8wJwYDVR0R
+BCAwHoILZXhhbXBsZS5jb22CD3d3dy5leGFtcGxlLmNvbTAKBggqhkjOPQQDAgNJ
+ADBGAiEAnkFEtuCf+7qKyD/hxcWaIY/JzrLvNAWQodlL162/400CIQDOtxAhpFnw
+5vJZKYWamsZOomMpPuYevr3ufQZUeCVswA==
+-----END CERTIFICATE-----";
+
+/// Self-signed RSA-PSS (SHA-256, MGF1) certificate for pss.example with a description attribute
+pub const PSS_CERT_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIICljCCAcugAwIBAgIUI2Sz2KlwOu9y4PC+obpYmY6pHokwQQYJKoZIhvcNAQEK
+MDSgDzANBglghkgBZQMEAgEFAKEcMBoGCSqGSIb3DQEBCDANBglghkgBZQMEAgEF
+AKIDAgEgMCkxFDASBgNVBAMMC3Bzcy5leGFtcGxlMREwDwYDVQQNDAhQU1MgdGVz
+dDAeFw0yNjEwMTgxODA3MjJaFw0zNjEwMTUxODA3MjJaMCkxFDASBgNVBAMMC3Bz
+cy5leGFtcGxlMREwDwYDVQQNDAhQU1MgdGVzdDCBnzANBgkqhkiG9w0BAQEFAAOB
+jQAwgYkCgYEA9fPdRGSfjJ0kiqhW0AZlhehdD1Xxhnay6ku6P9DTPDdh1HT+Mmfh
+VKUnLAWnjJMeiquu9Z+t5KEg6p3XAE4GfBBxSQAGjMiXNGaMz76eY4wqMMrVCe5I
+hhHKcOfqd7AIHnHTHNrENIPVS9M4HIFO6DC9mAow1oNSaeH5VzBGX5kCAwEAAaNT
+MFEwHQYDVR0OBBYEFG204A2IcJOImMd9iH8ej01JuWqVMB8GA1UdIwQYMBaAFG20
+4A2IcJOImMd9iH8ej01JuWqVMA8GA1UdEwEB/wQFMAMBAf8wQQYJKoZIhvcNAQEK
+MDSgDzANBglghkgBZQMEAgEFAKEcMBoGCSqGSIb3DQEBCDANBglghkgBZQMEAgEF
+AKIDAgEgA4GBAGwcOnz/OXxllpAkrijjsgrPeVKLcBsIXr3+P4qQyb68Mk18vfal
+dFD6PTf6U5oOntG2WkRd3IZIZ8Rn4syRT9TiOabEjXtx0i7bou0XmwmsOkeOxDm+
+aK35o0asc9XDZ7nAX6ijZwj9S9H5pD7Psh6U8eCzRX4igJ3n5Me/fdAO
+-----END CERTIFICATE-----";
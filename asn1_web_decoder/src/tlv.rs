@@ -0,0 +1,543 @@
+//! Zero-copy TLV reader over BER/DER input.
+//!
+//! [`Reader`] iterates over consecutive elements in a buffer and yields [`Tlv`]s
+//! that borrow their identifier, length and content octets from the input. The
+//! node tree built by the decoder is implemented on top of this reader.
+
+use crate::LengthForm;
+use std::error::Error;
+use std::fmt;
+
+/// Deepest nesting the reader accepts; the top-level elements are at depth 0
+pub const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ends inside the element starting at this offset
+    Truncated(usize),
+    InvalidLength(usize),
+    TagNumberTooLarge(usize),
+    IndefinitePrimitive(usize),
+    MissingEndOfContents(usize),
+    /// The element starting at this offset extends past the end of its parent
+    ExceedsParent(usize),
+    /// The element starting at this offset is nested deeper than `MAX_DEPTH`
+    TooDeep(usize),
+}
+
+impl DecodeError {
+    /// Offset of the element the error refers to
+    pub fn offset(&self) -> usize {
+        match *self {
+            DecodeError::Truncated(offset)
+            | DecodeError::InvalidLength(offset)
+            | DecodeError::TagNumberTooLarge(offset)
+            | DecodeError::IndefinitePrimitive(offset)
+            | DecodeError::MissingEndOfContents(offset)
+            | DecodeError::ExceedsParent(offset)
+            | DecodeError::TooDeep(offset) => offset,
+        }
+    }
+
+    pub(crate) fn offset_by(self, base: usize) -> DecodeError {
+        match self {
+            DecodeError::Truncated(offset) => DecodeError::Truncated(base + offset),
+            DecodeError::InvalidLength(offset) => DecodeError::InvalidLength(base + offset),
+            DecodeError::TagNumberTooLarge(offset) => DecodeError::TagNumberTooLarge(base + offset),
+            DecodeError::IndefinitePrimitive(offset) => {
+                DecodeError::IndefinitePrimitive(base + offset)
+            }
+            DecodeError::MissingEndOfContents(offset) => {
+                DecodeError::MissingEndOfContents(base + offset)
+            }
+            DecodeError::ExceedsParent(offset) => DecodeError::ExceedsParent(base + offset),
+            DecodeError::TooDeep(offset) => DecodeError::TooDeep(base + offset),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Truncated(offset) => {
+                write!(f, "Unexpected end of data at offset {}", offset)
+            }
+            DecodeError::InvalidLength(offset) => {
+                write!(f, "Invalid length encoding at offset {}", offset)
+            }
+            DecodeError::TagNumberTooLarge(offset) => {
+                write!(f, "Tag number too large at offset {}", offset)
+            }
+            DecodeError::IndefinitePrimitive(offset) => {
+                write!(
+                    f,
+                    "Indefinite length on a primitive element at offset {}",
+                    offset
+                )
+            }
+            DecodeError::MissingEndOfContents(offset) => {
+                write!(
+                    f,
+                    "Missing end-of-contents octets for element at offset {}",
+                    offset
+                )
+            }
+            DecodeError::ExceedsParent(offset) => {
+                write!(
+                    f,
+                    "Element at offset {} extends past the end of its parent",
+                    offset
+                )
+            }
+            DecodeError::TooDeep(offset) => {
+                write!(
+                    f,
+                    "Element at offset {} is nested more than {} levels deep",
+                    offset, MAX_DEPTH
+                )
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagClass {
+    Universal,
+    Application,
+    Context,
+    Private,
+}
+
+impl TagClass {
+    /// Class from the top two bits of the first identifier octet
+    pub fn from_bits(bits: u8) -> TagClass {
+        match bits & 0x03 {
+            0 => TagClass::Universal,
+            1 => TagClass::Application,
+            2 => TagClass::Context,
+            _ => TagClass::Private,
+        }
+    }
+
+    pub fn bits(&self) -> u8 {
+        match self {
+            TagClass::Universal => 0,
+            TagClass::Application => 1,
+            TagClass::Context => 2,
+            TagClass::Private => 3,
+        }
+    }
+
+    /// Name used for `tag_class` in the JSON node model
+    pub fn name(&self) -> &'static str {
+        match self {
+            TagClass::Universal => "UNIVERSAL",
+            TagClass::Application => "APPLICATION",
+            TagClass::Context => "CONTEXT",
+            TagClass::Private => "PRIVATE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tag {
+    pub class: TagClass,
+    pub constructed: bool,
+    pub number: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Length {
+    Definite(usize),
+    Indefinite,
+}
+
+/// Read identifier octets, including the high-tag-number form.
+///
+/// Returns the tag and the number of identifier octets.
+pub fn read_identifier(data: &[u8]) -> Result<(Tag, usize), DecodeError> {
+    let Some(&first) = data.first() else {
+        return Err(DecodeError::Truncated(0));
+    };
+
+    let class = TagClass::from_bits(first >> 6);
+    let constructed = (first & 0x20) != 0;
+
+    if first & 0x1F != 0x1F {
+        let number = (first & 0x1F) as u32;
+        return Ok((
+            Tag {
+                class,
+                constructed,
+                number,
+            },
+            1,
+        ));
+    }
+
+    let mut number: u32 = 0;
+    for (i, &byte) in data[1..].iter().enumerate() {
+        if number > (u32::MAX >> 7) {
+            return Err(DecodeError::TagNumberTooLarge(0));
+        }
+        number = (number << 7) | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            return Ok((
+                Tag {
+                    class,
+                    constructed,
+                    number,
+                },
+                i + 2,
+            ));
+        }
+    }
+
+    Err(DecodeError::Truncated(0))
+}
+
+/// Read length octets, returning the length and the number of length octets
+pub fn read_length(data: &[u8]) -> Result<(Length, usize), DecodeError> {
+    let Some(&first_byte) = data.first() else {
+        return Err(DecodeError::Truncated(0));
+    };
+
+    if first_byte & 0x80 == 0 {
+        return Ok((Length::Definite(first_byte as usize), 1));
+    }
+
+    let num_octets = (first_byte & 0x7F) as usize;
+    if num_octets == 0 {
+        return Ok((Length::Indefinite, 1));
+    }
+    if num_octets > 4 {
+        return Err(DecodeError::InvalidLength(0));
+    }
+    if data.len() < 1 + num_octets {
+        return Err(DecodeError::Truncated(0));
+    }
+
+    let length = data[1..=num_octets]
+        .iter()
+        .fold(0usize, |length, &byte| (length << 8) | byte as usize);
+    Ok((Length::Definite(length), 1 + num_octets))
+}
+
+/// One element borrowed from the input buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tlv<'a> {
+    tag: Tag,
+    offset: usize,
+    depth: usize,
+    raw: &'a [u8],
+    identifier_length: usize,
+    header_length: usize,
+    content_length: usize,
+    length_form: LengthForm,
+}
+
+impl<'a> Tlv<'a> {
+    /// Read the element at the start of `data`; `offset` is the absolute position of `data`
+    pub fn read(data: &'a [u8], offset: usize) -> Result<Tlv<'a>, DecodeError> {
+        Tlv::read_nested(data, offset, 0)
+    }
+
+    /// Read an element nested `depth` levels below the element `read` started from
+    pub(crate) fn read_nested(
+        data: &'a [u8],
+        offset: usize,
+        depth: usize,
+    ) -> Result<Tlv<'a>, DecodeError> {
+        if depth > MAX_DEPTH {
+            return Err(DecodeError::TooDeep(offset));
+        }
+        let (tag, identifier_length) = read_identifier(data).map_err(|e| e.offset_by(offset))?;
+        let (length, length_octets) =
+            read_length(&data[identifier_length..]).map_err(|e| e.offset_by(offset))?;
+        let header_length = identifier_length + length_octets;
+
+        let (content_length, total_length, length_form) = match length {
+            Length::Definite(length) => {
+                if data.len() - header_length < length {
+                    return Err(DecodeError::Truncated(offset));
+                }
+                let form = if length_octets == 1 {
+                    LengthForm::Short
+                } else {
+                    LengthForm::Long
+                };
+                (length, header_length + length, form)
+            }
+            Length::Indefinite => {
+                if !tag.constructed {
+                    return Err(DecodeError::IndefinitePrimitive(offset));
+                }
+                let length =
+                    find_end_of_contents(&data[header_length..], offset + header_length, depth + 1)
+                        .map_err(|e| match e {
+                            DecodeError::MissingEndOfContents(_) => {
+                                DecodeError::MissingEndOfContents(offset)
+                            }
+                            e => e,
+                        })?;
+                (length, header_length + length + 2, LengthForm::Indefinite)
+            }
+        };
+
+        Ok(Tlv {
+            tag,
+            offset,
+            depth,
+            raw: &data[..total_length],
+            identifier_length,
+            header_length,
+            content_length,
+            length_form,
+        })
+    }
+
+    pub fn tag(&self) -> Tag {
+        self.tag
+    }
+
+    pub fn is_constructed(&self) -> bool {
+        self.tag.constructed
+    }
+
+    /// Absolute offset of the first identifier octet
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Absolute offset of the first content octet
+    pub fn content_offset(&self) -> usize {
+        self.offset + self.header_length
+    }
+
+    pub fn length_form(&self) -> LengthForm {
+        self.length_form
+    }
+
+    pub fn identifier_octets(&self) -> &'a [u8] {
+        &self.raw[..self.identifier_length]
+    }
+
+    pub fn length_octets(&self) -> &'a [u8] {
+        &self.raw[self.identifier_length..self.header_length]
+    }
+
+    /// Identifier and length octets
+    pub fn header(&self) -> &'a [u8] {
+        &self.raw[..self.header_length]
+    }
+
+    /// Content octets, excluding end-of-contents octets of an indefinite-length element
+    pub fn content(&self) -> &'a [u8] {
+        &self.raw[self.header_length..self.header_length + self.content_length]
+    }
+
+    /// The complete encoding, including end-of-contents octets
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// Iterate over the elements in the content of a constructed element
+    pub fn children(&self) -> Reader<'a> {
+        if self.tag.constructed {
+            Reader::nested(self.content(), self.content_offset(), self.depth + 1)
+        } else {
+            Reader::nested(&[], self.content_offset(), self.depth + 1)
+        }
+    }
+}
+
+/// Length of the content before the end-of-contents octets
+fn find_end_of_contents(data: &[u8], offset: usize, depth: usize) -> Result<usize, DecodeError> {
+    let mut pos = 0;
+    loop {
+        if data.len() - pos < 2 {
+            return Err(DecodeError::MissingEndOfContents(offset));
+        }
+        if data[pos..].starts_with(&[0x00, 0x00]) {
+            return Ok(pos);
+        }
+        pos += Tlv::read_nested(&data[pos..], offset + pos, depth)?
+            .raw()
+            .len();
+    }
+}
+
+/// Iterator over consecutive elements in a buffer.
+///
+/// Iteration stops after the first error, including an element nested deeper
+/// than `MAX_DEPTH`.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    base_offset: usize,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader::with_offset(data, 0)
+    }
+
+    /// Reader whose reported offsets start at `base_offset`
+    pub fn with_offset(data: &'a [u8], base_offset: usize) -> Reader<'a> {
+        Reader::nested(data, base_offset, 0)
+    }
+
+    fn nested(data: &'a [u8], base_offset: usize, depth: usize) -> Reader<'a> {
+        Reader {
+            data,
+            pos: 0,
+            base_offset,
+            depth,
+        }
+    }
+
+    /// Bytes not yet consumed
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+}
+
+impl<'a> Iterator for Reader<'a> {
+    type Item = Result<Tlv<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+
+        match Tlv::read_nested(
+            &self.data[self.pos..],
+            self.base_offset + self.pos,
+            self.depth,
+        ) {
+            Ok(tlv) => {
+                self.pos += tlv.raw().len();
+                Some(Ok(tlv))
+            }
+            Err(e) => {
+                self.pos = self.data.len();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_tlv_slices() {
+        // SEQUENCE (long form) { INTEGER 1 }
+        let data = [0x30, 0x81, 0x03, 0x02, 0x01, 0x01];
+        let tlv = Tlv::read(&data, 0).unwrap();
+
+        assert_eq!(
+            tlv.tag(),
+            Tag {
+                class: TagClass::Universal,
+                constructed: true,
+                number: 16
+            }
+        );
+        assert_eq!(tlv.identifier_octets(), &[0x30]);
+        assert_eq!(tlv.length_octets(), &[0x81, 0x03]);
+        assert_eq!(tlv.header(), &[0x30, 0x81, 0x03]);
+        assert_eq!(tlv.content(), &[0x02, 0x01, 0x01]);
+        assert_eq!(tlv.content_offset(), 3);
+        assert_eq!(tlv.length_form(), LengthForm::Long);
+
+        let children: Vec<_> = tlv.children().collect::<Result<_, _>>().unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].offset(), 3);
+        assert_eq!(children[0].content(), &[0x01]);
+        assert_eq!(children[0].children().count(), 0);
+    }
+
+    #[test]
+    fn test_reader_borrows_input() {
+        let data = vec![0x02, 0x01, 0x05, 0x04, 0x02, 0xAB, 0xCD];
+        let contents: Vec<&[u8]> = Reader::with_offset(&data, 100)
+            .map(|tlv| tlv.unwrap().content())
+            .collect();
+
+        assert_eq!(contents, vec![&data[2..3], &data[5..7]]);
+        assert!(std::ptr::eq(contents[1].as_ptr(), data[5..].as_ptr()));
+    }
+
+    #[test]
+    fn test_read_indefinite() {
+        // [0] (indefinite) { SEQUENCE (indefinite) { NULL } EOC } EOC
+        let data = [0xA0, 0x80, 0x30, 0x80, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let tlv = Tlv::read(&data, 0).unwrap();
+
+        assert_eq!(tlv.length_form(), LengthForm::Indefinite);
+        assert_eq!(tlv.content().len(), 6);
+        assert_eq!(tlv.raw().len(), 10);
+
+        let inner = tlv.children().next().unwrap().unwrap();
+        assert_eq!(inner.content(), &[0x05, 0x00]);
+        assert_eq!(inner.raw().len(), 6);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        assert_eq!(
+            Tlv::read(&[0x30, 0x05, 0x02], 7),
+            Err(DecodeError::Truncated(7))
+        );
+        assert_eq!(
+            Tlv::read(&[0x30, 0x85, 0, 0, 0, 0, 1], 0),
+            Err(DecodeError::InvalidLength(0))
+        );
+        assert_eq!(
+            Tlv::read(&[0x04, 0x80, 0x00, 0x00], 0),
+            Err(DecodeError::IndefinitePrimitive(0))
+        );
+        assert_eq!(
+            Tlv::read(&[0x30, 0x80, 0x05, 0x00], 0),
+            Err(DecodeError::MissingEndOfContents(0))
+        );
+
+        // Indefinite lengths are followed while reading the outer element
+        let mut nested = [0x30, 0x80].repeat(MAX_DEPTH + 2);
+        nested.extend_from_slice(&[0x00, 0x00].repeat(MAX_DEPTH + 2));
+        assert_eq!(
+            Tlv::read(&nested, 0),
+            Err(DecodeError::TooDeep(2 * (MAX_DEPTH + 1)))
+        );
+        assert!(Tlv::read(&nested[2..nested.len() - 2], 0).is_ok());
+
+        let mut reader = Reader::new(&[0x05, 0x00, 0x30]);
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_read_high_tag_number() {
+        let (tag, length) = read_identifier(&[0x9F, 0x81, 0x48]).unwrap();
+        assert_eq!(
+            tag,
+            Tag {
+                class: TagClass::Context,
+                constructed: false,
+                number: 200
+            }
+        );
+        assert_eq!(length, 3);
+        assert_eq!(
+            read_identifier(&[0x9F, 0x81]),
+            Err(DecodeError::Truncated(0))
+        );
+    }
+}
@@ -5,7 +5,7 @@ import { Edit } from '@mui/icons-material'
 import InputDialog from './components/InputDialog'
 import OutputSection from './components/OutputSection'
 import HexViewSection from './components/HexViewSection'
-import init, { decode_pem_to_json, pem_to_hex, encode_asn1_to_pem } from './wasm/asn1_web_decoder.js'
+import init, { decode_pem_to_json, pem_to_hex, encode_changes_to_pem } from './wasm/asn1_web_decoder.js'
 import wasmUrl from './wasm/asn1_web_decoder_bg.wasm?url'
 
 const SAMPLE_CERT = `-----BEGIN CERTIFICATE-----
@@ -43,6 +43,17 @@ const theme = createTheme({
   },
 })
 
+// Resolve a node path such as "0.0.3" (child indices from the root)
+const findNodeByPath = (root, path) => {
+  if (!path) return root
+  let node = root
+  for (const index of path.split('.')) {
+    node = node?.children?.[Number(index)]
+    if (!node) return null
+  }
+  return node
+}
+
 function App() {
   const [input, setInput] = useState('')
   const [originalPemLabel, setOriginalPemLabel] = useState('CERTIFICATE')
@@ -128,37 +139,16 @@ function App() {
   }
 
   const handleValueEdit = async (node, newValue) => {
-    console.log('handleValueEdit called', { node, newValue });
-    
-    // Update the modified data tree by matching byte_offset and label
-    const updateNodeValue = (treeNode) => {
-      if (treeNode.byte_offset === node.byte_offset && 
-          treeNode.label === node.label &&
-          treeNode.value !== undefined) {
-        console.log('Found node to update', treeNode);
-        treeNode.value = newValue
-        return true
-      }
-      if (treeNode.children) {
-        for (const child of treeNode.children) {
-          if (updateNodeValue(child)) {
-            return true
-          }
-        }
-      }
-      return false
-    }
-
     const updatedData = JSON.parse(JSON.stringify(modifiedData))
-    const found = updateNodeValue(updatedData)
-    
-    if (!found) {
+    const target = findNodeByPath(updatedData, node.path)
+
+    if (!target || target.value === undefined) {
       console.error('Could not find node to update')
       setError('Failed to locate node for editing')
       return
     }
-
-    console.log('Updated tree:', updatedData);
+    target.value = newValue
+    target.dirty = true
 
     // Encode the modified tree back to PEM
     await reencodeTree(updatedData)
@@ -171,32 +161,22 @@ function App() {
   }
 
   const handleNodeDelete = async (nodeToDelete) => {
-    const deleteNode = (treeNode, parent = null, index = -1) => {
-      if (treeNode.byte_offset === nodeToDelete.byte_offset && 
-          treeNode.label === nodeToDelete.label) {
-        if (parent && index >= 0) {
-          parent.children.splice(index, 1)
-          return true
-        }
-        return false // Can't delete root
-      }
-      if (treeNode.children) {
-        for (let i = 0; i < treeNode.children.length; i++) {
-          if (deleteNode(treeNode.children[i], treeNode, i)) {
-            return true
-          }
-        }
-      }
-      return false
+    const path = nodeToDelete.path || ''
+    if (!path) {
+      return // Can't delete root
     }
 
     const updatedData = JSON.parse(JSON.stringify(modifiedData))
-    const deleted = deleteNode(updatedData)
-    
-    if (!deleted) {
+    const separator = path.lastIndexOf('.')
+    const parent = findNodeByPath(updatedData, separator < 0 ? '' : path.slice(0, separator))
+    const index = Number(path.slice(separator + 1))
+
+    if (!parent?.children?.[index]) {
       console.error('Could not delete node')
       return
     }
+    parent.children.splice(index, 1)
+    parent.dirty = true
 
     await reencodeTree(updatedData)
   }
@@ -212,49 +192,44 @@ function App() {
       value: nodeType.defaultValue || null,
       children: nodeType.constructed ? [] : undefined,
       byte_offset: 0,
-      byte_length: 0
-    }
-
-    const addNode = (treeNode) => {
-      if (treeNode.byte_offset === parentNode.byte_offset && 
-          treeNode.label === parentNode.label) {
-        if (!treeNode.children) {
-          treeNode.children = []
-        }
-        treeNode.children.push(newNode)
-        return true
-      }
-      if (treeNode.children) {
-        for (const child of treeNode.children) {
-          if (addNode(child)) {
-            return true
-          }
-        }
-      }
-      return false
+      byte_length: 0,
+      dirty: true
     }
 
     const updatedData = JSON.parse(JSON.stringify(modifiedData))
-    const added = addNode(updatedData)
-    
-    if (!added) {
+    const parent = findNodeByPath(updatedData, parentNode.path)
+
+    if (!parent) {
       console.error('Could not add node')
       return
     }
+    if (!parent.children) {
+      parent.children = []
+    }
+    parent.children.push(newNode)
+    parent.dirty = true
 
     await reencodeTree(updatedData)
   }
 
+  // Encoder errors are JSON listing every failing node; fall back to the plain message
+  const describeEncodeError = (e) => {
+    try {
+      const { error, errors } = JSON.parse(e)
+      const details = errors.map(err => `${err.path || 'root'} ${err.label} (${err.field}): ${err.message}`)
+      return [error, ...details].join('\n')
+    } catch {
+      return e.toString()
+    }
+  }
+
   const reencodeTree = async (updatedData) => {
     try {
+      // Only dirty nodes are re-encoded; the returned tree already has the new offsets
       const jsonTree = JSON.stringify(updatedData)
-      const newPem = encode_asn1_to_pem(jsonTree, originalPemLabel)
-      
-      // Re-decode the new PEM to update everything
-      const result = decode_pem_to_json(newPem)
-      const hex = pem_to_hex(newPem)
-      const data = JSON.parse(result)
-      
+      const { pem: newPem, tree: data } = JSON.parse(encode_changes_to_pem(jsonTree, originalPemLabel))
+      const hex = data.source_hex
+
       // Update all states together
       setInput(newPem)
       setDecodedData(data)
@@ -263,7 +238,7 @@ function App() {
       setError('')
     } catch (e) {
       console.error('Failed to encode/decode ASN.1:', e)
-      setError(`Encoding/decoding failed: ${e.toString()}`)
+      setError(`Encoding/decoding failed: ${describeEncodeError(e)}`)
     }
   }
 